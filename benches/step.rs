@@ -0,0 +1,60 @@
+//! How long the CPU takes per instruction, on the MMU and on flat memory.
+//!
+//! There are no benchmarking dependencies, so this is a plain binary:
+//! `cargo bench --bench step`.
+
+use std::{hint::black_box, time::Instant};
+
+use magi::prelude::*;
+
+/// A loop touching registers, memory, the stack, branches and the
+/// CB-prefixed instructions:
+///
+/// ```text
+/// loop: LD A,(HL+); ADD A,B; LD (HL),A; INC E; SRL E; BIT 0,(HL)
+///       PUSH BC; POP BC; CP 0x80; JR NZ,skip; LD HL,0xC100
+/// skip: DEC C; JR loop
+/// ```
+const PROGRAM: [u8; 20] = [
+    0x2A, 0x80, 0x77, 0x1C, 0xCB, 0x3B, 0xCB, 0x46, 0xC5, 0xC1, 0xFE, 0x80, 0x20, 0x03, 0x21, 0x00,
+    0xC1, 0x0D, 0x18, 0xEC,
+];
+const STEPS: u32 = 2_000_000;
+/// The machine is rarely quiet, so the fastest of several rounds is the
+/// best estimate.
+const ROUNDS: u32 = 7;
+
+fn bench(name: &str, bus: &impl Bus) {
+    let mut cpu = SM83::new();
+    cpu.registers.pc = 0xC000;
+    cpu.registers.sp = 0xDFFE;
+    cpu.registers.set_hl(0xC100);
+
+    let best = (0..ROUNDS)
+        .map(|_| {
+            let start = Instant::now();
+            for _ in 0..STEPS {
+                black_box(cpu.step(bus)).unwrap();
+            }
+            start.elapsed()
+        })
+        .min()
+        .unwrap();
+
+    println!(
+        "{name}: {:.1} ns/step",
+        best.as_nanos() as f64 / STEPS as f64
+    );
+}
+
+fn main() {
+    let mmu = MMU::new();
+    for (i, byte) in PROGRAM.iter().enumerate() {
+        mmu.write_byte(0xC000 + i as u16, *byte);
+    }
+    bench("MMU", &mmu);
+
+    let memory = FlatMemory::new();
+    memory.load(0xC000, &PROGRAM);
+    bench("FlatMemory", &memory);
+}
@@ -0,0 +1,95 @@
+//! The interface between the CPU and memory.
+//!
+//! The CPU only needs to read and write bytes, so it's generic over a
+//! [`Bus`]. The real memory map is the [`MMU`](crate::mmu::MMU);
+//! [`FlatMemory`] is a plain 64KB array for testing the CPU on its own.
+//!
+//! ```
+//! use magi::{bus::{Bus, FlatMemory}, cpu::sm83::SM83};
+//!
+//! let memory = FlatMemory::new();
+//! memory.load(0xFE00, &[0x3E, 0x42]); // LD A,42, which the MMU puts in OAM
+//!
+//! let mut cpu = SM83::new();
+//! cpu.registers.pc = 0xFE00;
+//! cpu.step(&memory).unwrap();
+//! assert_eq!(cpu.registers.a, 0x42);
+//! ```
+
+use std::cell::RefCell;
+
+/// Something the CPU can read from and write to.
+///
+/// Writes take `&self` too, so implementors keep their memory behind
+/// interior mutability (see [`FlatMemory`]). Hardware on the bus changes
+/// memory on its own (DMA, timers, the cartridge's clock), so it can't be
+/// owned exclusively by the CPU.
+pub trait Bus {
+    /// Read a byte, or `None` if nothing answers at `addr`.
+    fn read_byte(&self, addr: u16) -> Option<u8>;
+
+    fn write_byte(&self, addr: u16, value: u8);
+
+    /// Read a byte for debugging, without the side effects of a real access.
+    fn peek_byte(&self, addr: u16) -> Option<u8> {
+        self.read_byte(addr)
+    }
+
+    /// Write a byte without it counting as a CPU access. This is how the CPU
+    /// updates IF when it services an interrupt, which isn't a bus access.
+    fn poke_byte(&self, addr: u16, value: u8) {
+        self.write_byte(addr, value)
+    }
+
+    /// Called after the CPU spends `cycles` M-cycles, so hardware outside
+    /// the CPU can catch up.
+    fn tick(&self, _cycles: u8) {}
+
+    /// Called by STOP to do a CGB speed switch, if one has been armed
+    /// through KEY1. Returns whether the CPU is now in double-speed mode, or
+    /// `None` if there's no switch to do and the CPU should stop instead.
+    ///
+    /// Only the CGB has KEY1, so by default there's never a switch.
+    fn switch_speed(&self) -> Option<bool> {
+        None
+    }
+}
+
+/// 64KB of RAM, with nothing mapped anywhere.
+#[derive(Debug, Clone)]
+pub struct FlatMemory {
+    bytes: RefCell<Box<[u8; 0x10000]>>,
+}
+
+impl Default for FlatMemory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FlatMemory {
+    pub fn new() -> Self {
+        FlatMemory {
+            bytes: RefCell::new(Box::new([0; 0x10000])),
+        }
+    }
+
+    /// Copy `bytes` into memory, starting at `addr` and wrapping around at
+    /// the end of the address space.
+    pub fn load(&self, addr: u16, bytes: &[u8]) {
+        let mut memory = self.bytes.borrow_mut();
+        for (i, byte) in bytes.iter().enumerate() {
+            memory[addr.wrapping_add(i as u16) as usize] = *byte;
+        }
+    }
+}
+
+impl Bus for FlatMemory {
+    fn read_byte(&self, addr: u16) -> Option<u8> {
+        Some(self.bytes.borrow()[addr as usize])
+    }
+
+    fn write_byte(&self, addr: u16, value: u8) {
+        self.bytes.borrow_mut()[addr as usize] = value;
+    }
+}
@@ -0,0 +1,255 @@
+//! Checking ROMs against a No-Intro DAT file, to tell good dumps from bad
+//! ones.
+//!
+//! No-Intro publishes its databases in the Logiqx XML format, with one
+//! `<game>` per known release and a `<rom>` inside it giving the dump's
+//! hashes. ROMs are matched by their SHA-1.
+
+use std::{fmt, fs, io, path::Path};
+
+/// What a DAT file says about a ROM.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DumpStatus {
+    /// A verified dump of `name`.
+    Good { name: String },
+    /// A dump of `name` that's known to be broken.
+    Bad { name: String },
+    /// Not in the DAT, so it might be a bad or modified dump, or homebrew.
+    Unknown,
+}
+
+impl fmt::Display for DumpStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Good { name } => write!(f, "good dump of {}", name),
+            Self::Bad { name } => write!(f, "bad dump of {}", name),
+            Self::Unknown => write!(f, "unknown dump"),
+        }
+    }
+}
+
+/// One dump listed in a DAT file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Entry {
+    name: String,
+    sha1: [u8; 20],
+    bad: bool,
+}
+
+/// The dumps listed in a No-Intro DAT file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Dat {
+    entries: Vec<Entry>,
+}
+
+impl Dat {
+    /// Read a DAT file. See [`Dat::parse`].
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::parse(&fs::read_to_string(path)?))
+    }
+
+    /// Parse a DAT file in the Logiqx XML format. Entries without a usable
+    /// SHA-1 are skipped.
+    pub fn parse(text: &str) -> Self {
+        let mut entries = Vec::new();
+
+        for game in text.split("<game").skip(1) {
+            let game = game.split("</game>").next().unwrap_or_default();
+            let name = attribute(game, "name").unwrap_or_default();
+
+            for rom in game.split("<rom").skip(1) {
+                let rom = rom.split('>').next().unwrap_or_default();
+                let Some(sha1) = attribute(rom, "sha1").and_then(|hash| parse_hash(&hash)) else {
+                    continue;
+                };
+                // No-Intro also marks bad dumps with [b] in the name
+                let bad =
+                    attribute(rom, "status").as_deref() == Some("baddump") || name.contains("[b]");
+
+                entries.push(Entry {
+                    name: name.clone(),
+                    sha1,
+                    bad,
+                });
+            }
+        }
+
+        Dat { entries }
+    }
+
+    /// How many dumps are listed.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Look a ROM image up by its SHA-1.
+    pub fn check(&self, rom: &[u8]) -> DumpStatus {
+        let hash = sha1(rom);
+
+        match self.entries.iter().find(|entry| entry.sha1 == hash) {
+            Some(entry) if entry.bad => DumpStatus::Bad {
+                name: entry.name.clone(),
+            },
+            Some(entry) => DumpStatus::Good {
+                name: entry.name.clone(),
+            },
+            None => DumpStatus::Unknown,
+        }
+    }
+}
+
+/// The value of `name="..."` in the first tag of `xml`, unescaped.
+fn attribute(xml: &str, name: &str) -> Option<String> {
+    let tag = xml.split('>').next()?;
+    let pattern = format!(" {}=\"", name);
+    let start = tag.find(&pattern)? + pattern.len();
+    let end = start + tag[start..].find('"')?;
+
+    Some(unescape(&tag[start..end]))
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// A SHA-1 written as 40 hex digits, in either case.
+fn parse_hash(text: &str) -> Option<[u8; 20]> {
+    if text.len() != 40 || !text.is_ascii() {
+        return None;
+    }
+
+    let mut hash = [0; 20];
+    for (i, byte) in hash.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&text[i * 2..i * 2 + 2], 16).ok()?;
+    }
+
+    Some(hash)
+}
+
+/// The SHA-1 hash of `data`.
+pub fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut state: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+
+    // the data is padded with a 1 bit, then 0s, then its length in bits, to
+    // a whole number of 64 byte blocks
+    let mut tail = data[data.len() / 64 * 64..].to_vec();
+    tail.push(0x80);
+    while tail.len() % 64 != 56 {
+        tail.push(0);
+    }
+    tail.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+
+    for block in data.chunks_exact(64).chain(tail.chunks_exact(64)) {
+        let mut words = [0u32; 80];
+        for (i, word) in block.chunks_exact(4).enumerate() {
+            words[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            words[i] = (words[i - 3] ^ words[i - 8] ^ words[i - 14] ^ words[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = state;
+        for (i, word) in words.iter().enumerate() {
+            let (f, k) = match i {
+                0..20 => (b & c | !b & d, 0x5A827999),
+                20..40 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..60 => (b & c | b & d | c & d, 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+
+        for (value, new) in state.iter_mut().zip([a, b, c, d, e]) {
+            *value = value.wrapping_add(new);
+        }
+    }
+
+    let mut hash = [0; 20];
+    for (bytes, value) in hash.chunks_exact_mut(4).zip(state) {
+        bytes.copy_from_slice(&value.to_be_bytes());
+    }
+
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(hash: [u8; 20]) -> String {
+        hash.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    #[test]
+    fn test_sha1() {
+        assert_eq!(hex(sha1(b"")), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(
+            hex(sha1(b"abc")),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        // padding that spills into a second block
+        assert_eq!(
+            hex(sha1(
+                b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+            )),
+            "84983e441c3bd26ebaae4aa1f95129e5e54670f1"
+        );
+        assert_eq!(
+            hex(sha1(&[b'a'; 1_000_000])),
+            "34aa973cd4c4daa4f61eeb2bdbad27316534016f"
+        );
+    }
+
+    #[test]
+    fn test_check() {
+        let dat = Dat::parse(
+            r#"<?xml version="1.0"?>
+            <datafile>
+                <header><name>Nintendo - Game Boy</name></header>
+                <game name="Tom &amp; Jerry (USA)">
+                    <description>Tom &amp; Jerry (USA)</description>
+                    <rom name="Tom &amp; Jerry (USA).gb" size="3" sha1="A9993E364706816ABA3E25717850C26C9CD0D89D"/>
+                </game>
+                <game name="Broken (Europe)">
+                    <rom name="Broken (Europe).gb" size="0" sha1="da39a3ee5e6b4b0d3255bfef95601890afd80709" status="baddump"/>
+                </game>
+                <game name="No Hash">
+                    <rom name="No Hash.gb" size="4" crc="00000000"/>
+                </game>
+            </datafile>"#,
+        );
+
+        assert_eq!(dat.len(), 2);
+        assert_eq!(
+            dat.check(b"abc"),
+            DumpStatus::Good {
+                name: "Tom & Jerry (USA)".to_string()
+            }
+        );
+        assert_eq!(
+            dat.check(b""),
+            DumpStatus::Bad {
+                name: "Broken (Europe)".to_string()
+            }
+        );
+        assert_eq!(dat.check(b"abcd"), DumpStatus::Unknown);
+    }
+}
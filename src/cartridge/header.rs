@@ -0,0 +1,350 @@
+//! The cartridge header, at 0x0100-0x014F of every ROM.
+
+use std::fmt;
+
+use super::RomError;
+
+/// The end of the header, exclusive.
+pub const HEADER_END: usize = 0x0150;
+
+/// The logo bitmap at 0x0104-0x0133. The boot ROM refuses to start a
+/// cartridge without it.
+pub const NINTENDO_LOGO: [u8; 48] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+    0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+
+/// Something wrong with a header, that the boot ROM would reject.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HeaderProblem {
+    BadLogo,
+    BadHeaderChecksum { stored: u8, computed: u8 },
+}
+
+impl fmt::Display for HeaderProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadLogo => write!(f, "the Nintendo logo is missing"),
+            Self::BadHeaderChecksum { stored, computed } => write!(
+                f,
+                "the header checksum is {:02X}, but the header sums to {:02X}",
+                stored, computed
+            ),
+        }
+    }
+}
+
+/// The checksum of 0x0134-0x014C, as the boot ROM computes it.
+pub fn header_checksum(header: &[u8]) -> u8 {
+    header[0x0134..=0x014C]
+        .iter()
+        .fold(0u8, |sum, byte| sum.wrapping_sub(*byte).wrapping_sub(1))
+}
+
+/// The memory bank controller (or other hardware) on the cartridge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapperKind {
+    RomOnly,
+    Mbc1,
+    Mbc2,
+    Mmm01,
+    Mbc3,
+    Mbc5,
+    Mbc6,
+    Mbc7,
+    PocketCamera,
+    Tama5,
+    HuC3,
+    HuC1,
+    Unknown(u8),
+}
+
+/// The cartridge type byte (0x0147), split into the mapper and the extra
+/// hardware it's paired with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CartridgeType {
+    pub code: u8,
+    pub mapper: MapperKind,
+    pub ram: bool,
+    pub battery: bool,
+    pub timer: bool,
+    pub rumble: bool,
+}
+
+impl CartridgeType {
+    pub fn from_code(code: u8) -> Self {
+        use MapperKind::*;
+
+        // (mapper, RAM, battery, timer, rumble)
+        let (mapper, ram, battery, timer, rumble) = match code {
+            0x00 => (RomOnly, false, false, false, false),
+            0x01 => (Mbc1, false, false, false, false),
+            0x02 => (Mbc1, true, false, false, false),
+            0x03 => (Mbc1, true, true, false, false),
+            0x05 => (Mbc2, false, false, false, false),
+            0x06 => (Mbc2, false, true, false, false),
+            0x08 => (RomOnly, true, false, false, false),
+            0x09 => (RomOnly, true, true, false, false),
+            0x0B => (Mmm01, false, false, false, false),
+            0x0C => (Mmm01, true, false, false, false),
+            0x0D => (Mmm01, true, true, false, false),
+            0x0F => (Mbc3, false, true, true, false),
+            0x10 => (Mbc3, true, true, true, false),
+            0x11 => (Mbc3, false, false, false, false),
+            0x12 => (Mbc3, true, false, false, false),
+            0x13 => (Mbc3, true, true, false, false),
+            0x19 => (Mbc5, false, false, false, false),
+            0x1A => (Mbc5, true, false, false, false),
+            0x1B => (Mbc5, true, true, false, false),
+            0x1C => (Mbc5, false, false, false, true),
+            0x1D => (Mbc5, true, false, false, true),
+            0x1E => (Mbc5, true, true, false, true),
+            0x20 => (Mbc6, false, false, false, false),
+            0x22 => (Mbc7, true, true, false, true),
+            0xFC => (PocketCamera, true, true, false, false),
+            0xFD => (Tama5, false, false, false, false),
+            0xFE => (HuC3, true, true, true, false),
+            0xFF => (HuC1, true, true, false, false),
+            _ => (Unknown(code), false, false, false, false),
+        };
+
+        CartridgeType {
+            code,
+            mapper,
+            ram,
+            battery,
+            timer,
+            rumble,
+        }
+    }
+}
+
+/// How a cartridge uses the Game Boy Color's features (0x0143).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CgbSupport {
+    /// A DMG game, which the CGB runs in compatibility mode.
+    None,
+    /// Enhanced for the CGB, but still runs on a DMG.
+    Compatible,
+    Only,
+}
+
+/// Where the cartridge was meant to be sold (0x014A).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Destination {
+    Japan,
+    Overseas,
+}
+
+/// The metadata at 0x0100-0x014F of a ROM.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CartridgeHeader {
+    /// The game's title, in upper case ASCII.
+    pub title: String,
+    /// Only present on later cartridges, which shortened the title to make
+    /// room for it.
+    pub manufacturer: Option<String>,
+    pub cgb: CgbSupport,
+    /// The two ASCII characters of the publisher's code, used when the old
+    /// licensee code is 0x33.
+    pub new_licensee: [u8; 2],
+    pub sgb: bool,
+    pub cartridge_type: CartridgeType,
+    /// In bytes, or `None` for an unknown size code.
+    pub rom_size: Option<usize>,
+    /// In bytes, or `None` for an unknown size code. MBC2's built-in RAM
+    /// isn't counted.
+    pub ram_size: Option<usize>,
+    pub destination: Destination,
+    pub old_licensee: u8,
+    pub version: u8,
+    pub header_checksum: u8,
+    pub global_checksum: u16,
+}
+
+/// Read a NUL-padded ASCII string, replacing anything unprintable.
+fn ascii(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .take_while(|&&byte| byte != 0)
+        .map(|&byte| match byte {
+            0x20..=0x7E => byte as char,
+            _ => '?',
+        })
+        .collect()
+}
+
+impl CartridgeHeader {
+    /// Check the parts of a ROM's header that the boot ROM checks.
+    ///
+    /// `rom` should be at least as long as the header.
+    pub fn verify(rom: &[u8]) -> Vec<HeaderProblem> {
+        let mut problems = Vec::new();
+
+        if rom[0x0104..0x0134] != NINTENDO_LOGO {
+            problems.push(HeaderProblem::BadLogo);
+        }
+        let (stored, computed) = (rom[0x014D], header_checksum(rom));
+        if stored != computed {
+            problems.push(HeaderProblem::BadHeaderChecksum { stored, computed });
+        }
+
+        problems
+    }
+
+    /// Parse the header of a ROM image.
+    pub fn parse(rom: &[u8]) -> Result<Self, RomError> {
+        let Some(header) = rom.get(..HEADER_END) else {
+            return Err(RomError::InvalidSize(rom.len()));
+        };
+
+        let cgb = match header[0x0143] {
+            0xC0 => CgbSupport::Only,
+            flag if flag & 0x80 != 0 => CgbSupport::Compatible,
+            _ => CgbSupport::None,
+        };
+        // CGB-era cartridges end the title early for the CGB flag, and later
+        // ones for a manufacturer code too. Without one, those four bytes are
+        // still part of the title.
+        let (title, manufacturer) = match cgb {
+            CgbSupport::None => (ascii(&header[0x0134..=0x0143]), None),
+            _ => {
+                let code = &header[0x013F..=0x0142];
+                let manufacturer = code
+                    .iter()
+                    .all(|byte| byte.is_ascii_uppercase() || byte.is_ascii_digit())
+                    .then(|| ascii(code));
+                let title = match manufacturer {
+                    Some(_) => ascii(&header[0x0134..0x013F]),
+                    None => ascii(&header[0x0134..=0x0142]),
+                };
+                (title, manufacturer)
+            }
+        };
+
+        let rom_size = match header[0x0148] {
+            code @ 0x00..=0x08 => Some(0x8000 << code),
+            _ => None,
+        };
+        let ram_size = match header[0x0149] {
+            0x00 => Some(0),
+            0x01 => Some(0x0800),
+            0x02 => Some(0x2000),
+            0x03 => Some(0x8000),
+            0x04 => Some(0x20000),
+            0x05 => Some(0x10000),
+            _ => None,
+        };
+
+        Ok(CartridgeHeader {
+            title,
+            manufacturer,
+            cgb,
+            new_licensee: [header[0x0144], header[0x0145]],
+            sgb: header[0x0146] == 0x03,
+            cartridge_type: CartridgeType::from_code(header[0x0147]),
+            rom_size,
+            ram_size,
+            destination: match header[0x014A] {
+                0x00 => Destination::Japan,
+                _ => Destination::Overseas,
+            },
+            old_licensee: header[0x014B],
+            version: header[0x014C],
+            header_checksum: header[0x014D],
+            global_checksum: u16::from_be_bytes([header[0x014E], header[0x014F]]),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rom_with_header(fields: &[(usize, &[u8])]) -> Vec<u8> {
+        let mut rom = vec![0; 0x8000];
+        for (offset, bytes) in fields {
+            rom[*offset..*offset + bytes.len()].copy_from_slice(bytes);
+        }
+        rom
+    }
+
+    #[test]
+    fn test_parse_dmg_header() {
+        let rom = rom_with_header(&[
+            (0x0134, b"POKEMON RED"),
+            (
+                0x0146,
+                &[0x03, 0x13, 0x05, 0x03, 0x01, 0x01, 0x00, 0x20, 0x91, 0xE6],
+            ),
+        ]);
+        let header = CartridgeHeader::parse(&rom).unwrap();
+
+        assert_eq!(header.title, "POKEMON RED");
+        assert_eq!(header.manufacturer, None);
+        assert_eq!(header.cgb, CgbSupport::None);
+        assert!(header.sgb);
+        assert_eq!(header.cartridge_type.mapper, MapperKind::Mbc3);
+        assert!(header.cartridge_type.ram && header.cartridge_type.battery);
+        assert_eq!(header.rom_size, Some(1024 * 1024));
+        assert_eq!(header.ram_size, Some(32 * 1024));
+        assert_eq!(header.destination, Destination::Overseas);
+        assert_eq!(header.header_checksum, 0x20);
+        assert_eq!(header.global_checksum, 0x91E6);
+    }
+
+    #[test]
+    fn test_parse_cgb_header() {
+        let rom = rom_with_header(&[(0x0134, b"ZELDA\0\0\0\0\0\0AZ7E\x80"), (0x0147, &[0x1B])]);
+        let header = CartridgeHeader::parse(&rom).unwrap();
+
+        assert_eq!(header.title, "ZELDA");
+        assert_eq!(header.manufacturer.as_deref(), Some("AZ7E"));
+        assert_eq!(header.cgb, CgbSupport::Compatible);
+        assert_eq!(header.cartridge_type.mapper, MapperKind::Mbc5);
+
+        // early CGB games left the manufacturer code blank
+        let rom = rom_with_header(&[(0x0134, b"TETRIS DX\0\0\0\0\0\0\xC0")]);
+        let header = CartridgeHeader::parse(&rom).unwrap();
+
+        assert_eq!(header.title, "TETRIS DX");
+        assert_eq!(header.manufacturer, None);
+        assert_eq!(header.cgb, CgbSupport::Only);
+
+        // and some used those bytes for a longer title
+        let rom = rom_with_header(&[(0x0134, b"GAME & WATCH GB\x80")]);
+        let header = CartridgeHeader::parse(&rom).unwrap();
+
+        assert_eq!(header.title, "GAME & WATCH GB");
+        assert_eq!(header.manufacturer, None);
+    }
+
+    #[test]
+    fn test_verify() {
+        let mut rom = rom_with_header(&[(0x0104, &NINTENDO_LOGO), (0x0134, b"TETRIS")]);
+        rom[0x014D] = header_checksum(&rom);
+        assert_eq!(CartridgeHeader::verify(&rom), []);
+
+        rom[0x0104] = 0x00;
+        rom[0x0134] = b'B';
+        assert_eq!(
+            CartridgeHeader::verify(&rom),
+            [
+                HeaderProblem::BadLogo,
+                HeaderProblem::BadHeaderChecksum {
+                    stored: rom[0x014D],
+                    computed: rom[0x014D].wrapping_add(b'T' - b'B'),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_short_rom_has_no_header() {
+        assert!(matches!(
+            CartridgeHeader::parse(&[0; 0x014F]),
+            Err(RomError::InvalidSize(0x014F))
+        ));
+    }
+}
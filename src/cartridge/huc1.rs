@@ -0,0 +1,112 @@
+use super::mbc::{load_prefix, ram_bank, ram_offset, rom_bank, rom_byte, Banks, Mbc};
+
+/// Hudson's HuC1: MBC1-like banking, plus an infrared port in place of RAM
+/// enable.
+pub struct HuC1 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    /// Whether 0xA000-0xBFFF is the infrared port instead of RAM.
+    ir_mode: bool,
+    /// Whether the infrared LED is on. There's nothing on the other end, so
+    /// this doesn't go anywhere.
+    ir_led: bool,
+    rom_bank: u8,
+    ram_bank: u8,
+}
+
+impl HuC1 {
+    pub fn new(rom: Vec<u8>, ram_size: usize) -> Self {
+        HuC1 {
+            rom,
+            ram: vec![0; ram_size],
+            ir_mode: false,
+            ir_led: false,
+            rom_bank: 1,
+            ram_bank: 0,
+        }
+    }
+}
+
+impl Mbc for HuC1 {
+    fn read_rom(&self, addr: u16) -> u8 {
+        let bank = match addr {
+            0x0000..=0x3FFF => 0,
+            _ => self.rom_bank as usize,
+        };
+
+        rom_byte(&self.rom, bank, addr)
+    }
+
+    fn write_rom(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x0000..=0x1FFF => self.ir_mode = value & 0x0F == 0x0E,
+            0x2000..=0x3FFF => self.rom_bank = (value & 0x3F).max(1),
+            0x4000..=0x5FFF => self.ram_bank = value & 0x03,
+            _ => {}
+        }
+    }
+
+    fn read_ram(&self, addr: u16) -> u8 {
+        if self.ir_mode {
+            // 0xC1 would mean light was seen
+            return 0xC0;
+        }
+
+        ram_offset(&self.ram, self.ram_bank as usize, addr).map_or(0xFF, |offset| self.ram[offset])
+    }
+
+    fn write_ram(&mut self, addr: u16, value: u8) {
+        if self.ir_mode {
+            self.ir_led = value & 0x01 != 0;
+        } else if let Some(offset) = ram_offset(&self.ram, self.ram_bank as usize, addr) {
+            self.ram[offset] = value;
+        }
+    }
+
+    /// In infrared mode, there's no RAM bank.
+    fn banks(&self) -> Banks {
+        Banks {
+            rom0: 0,
+            romx: rom_bank(&self.rom, self.rom_bank as usize),
+            ram: ram_bank(&self.ram, self.ram_bank as usize).filter(|_| !self.ir_mode),
+            ram_enabled: true,
+        }
+    }
+
+    fn save_data(&self) -> Vec<u8> {
+        self.ram.clone()
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        load_prefix(&mut self.ram, data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::mbc1::tests::banked_rom;
+
+    #[test]
+    fn test_huc1() {
+        let mut mbc = HuC1::new(banked_rom(64), 0x8000);
+
+        mbc.write_rom(0x2000, 0x3F);
+        assert_eq!(mbc.read_rom(0x4000), 0x3F);
+        mbc.write_rom(0x2000, 0x00);
+        assert_eq!(mbc.read_rom(0x4000), 0x01);
+
+        // RAM doesn't need enabling
+        mbc.write_rom(0x4000, 0x02);
+        mbc.write_ram(0xA000, 0x12);
+        assert_eq!(mbc.read_ram(0xA000), 0x12);
+
+        // and is swapped out for the infrared port
+        mbc.write_rom(0x0000, 0x0E);
+        assert_eq!(mbc.read_ram(0xA000), 0xC0);
+        mbc.write_ram(0xA000, 0x01);
+        assert!(mbc.ir_led);
+        mbc.write_rom(0x0000, 0x0A);
+        assert_eq!(mbc.read_ram(0xA000), 0x12);
+    }
+}
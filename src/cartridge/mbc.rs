@@ -0,0 +1,148 @@
+//! Memory bank controllers, the chips on a cartridge that map its ROM and
+//! RAM into the address space.
+
+use super::{
+    header::{CartridgeHeader, MapperKind},
+    huc1::HuC1,
+    mbc1::{self, Mbc1},
+    mbc2::Mbc2,
+    mbc3::Mbc3,
+    mbc5::Mbc5,
+    mbc7::Mbc7,
+    mmm01::Mmm01,
+    rom_only::RomOnly,
+    rtc::{Rtc, SystemClock},
+    RomError, ROM_BANK_SIZE,
+};
+
+/// The size of one bank of cartridge RAM.
+pub const RAM_BANK_SIZE: usize = 0x2000;
+
+/// Called with `true` when a cartridge's rumble motor turns on, and `false`
+/// when it turns off.
+pub type RumbleHandler = Box<dyn FnMut(bool)>;
+
+/// Which banks a mapper has mapped in, counted from the start of ROM or
+/// RAM, after wrapping past the end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Banks {
+    /// The ROM bank at 0x0000-0x3FFF, which is bank 0 on all but a few
+    /// mappers.
+    pub rom0: usize,
+    /// The ROM bank at 0x4000-0x7FFF.
+    pub romx: usize,
+    /// The RAM bank selected for 0xA000-0xBFFF, or `None` if there's no RAM
+    /// or something else (like a clock register) is selected.
+    pub ram: Option<usize>,
+    pub ram_enabled: bool,
+}
+
+/// A cartridge's mapper, which the MMU hands 0x0000-0x7FFF and
+/// 0xA000-0xBFFF to.
+///
+/// Addresses are passed through as the CPU sees them, not as offsets.
+pub trait Mbc {
+    /// Read from 0x0000-0x7FFF.
+    fn read_rom(&self, addr: u16) -> u8;
+
+    /// Write to 0x0000-0x7FFF. ROM can't be written, so this is how the
+    /// mapper's registers are set.
+    fn write_rom(&mut self, addr: u16, value: u8);
+
+    /// Read from 0xA000-0xBFFF. Missing or disabled RAM reads as 0xFF.
+    fn read_ram(&self, addr: u16) -> u8;
+
+    /// Write to 0xA000-0xBFFF.
+    fn write_ram(&mut self, addr: u16, value: u8);
+
+    /// The banks mapped in right now.
+    fn banks(&self) -> Banks;
+
+    /// Advance hardware that runs on its own, like a clock, by `cycles`
+    /// M-cycles.
+    fn step(&mut self, _cycles: u8) {}
+
+    /// Have the cartridge report its rumble motor to `handler`. Mappers
+    /// without a motor ignore this.
+    fn set_rumble_handler(&mut self, _handler: RumbleHandler) {}
+
+    /// What a battery would keep when the console is off, for writing to a
+    /// save file.
+    fn save_data(&self) -> Vec<u8>;
+
+    /// Restore what [`Mbc::save_data`] returned. Data from a different
+    /// sized save is loaded as far as it fits.
+    fn load_save_data(&mut self, data: &[u8]);
+
+    /// Tell the cartridge's accelerometer how far the console is tilted, in
+    /// g. Mappers without one ignore this.
+    fn set_tilt(&mut self, _x: f32, _y: f32) {}
+}
+
+/// Build the mapper a cartridge's header asks for, with `ram_size` bytes of
+/// RAM (see [`SaveType`](super::save_type::SaveType)). MBC2 and MBC7 have
+/// their own, and ignore it.
+pub fn for_header(
+    header: &CartridgeHeader,
+    ram_size: usize,
+    rom: &[u8],
+) -> Result<Box<dyn Mbc>, RomError> {
+    let kind = header.cartridge_type;
+
+    match kind.mapper {
+        MapperKind::RomOnly => Ok(Box::new(RomOnly::new(rom.to_vec(), ram_size))),
+        MapperKind::Mbc1 if mbc1::is_multicart(rom) => {
+            Ok(Box::new(Mbc1::multicart(rom.to_vec(), ram_size)))
+        }
+        MapperKind::Mbc1 => Ok(Box::new(Mbc1::new(rom.to_vec(), ram_size))),
+        MapperKind::Mbc2 => Ok(Box::new(Mbc2::new(rom.to_vec()))),
+        MapperKind::Mbc3 => {
+            let rtc = kind.timer.then(|| Rtc::new(Box::new(SystemClock)));
+            Ok(Box::new(Mbc3::new(rom.to_vec(), ram_size, rtc)))
+        }
+        MapperKind::Mbc7 => Ok(Box::new(Mbc7::new(rom.to_vec()))),
+        MapperKind::Mmm01 => Ok(Box::new(Mmm01::new(rom.to_vec(), ram_size))),
+        MapperKind::HuC1 => Ok(Box::new(HuC1::new(rom.to_vec(), ram_size))),
+        MapperKind::Mbc5 => Ok(Box::new(Mbc5::new(rom.to_vec(), ram_size, kind.rumble))),
+        mapper => Err(RomError::UnsupportedMapper(mapper)),
+    }
+}
+
+/// A byte of ROM, as seen through a 16KB window onto `bank`.
+///
+/// Banks past the end of the ROM wrap around, since the mapper's unused
+/// bank bits aren't connected.
+pub(crate) fn rom_byte(rom: &[u8], bank: usize, addr: u16) -> u8 {
+    rom[(bank * ROM_BANK_SIZE + (addr as usize & 0x3FFF)) % rom.len()]
+}
+
+/// Which bank of `rom` a mapper's bank number really picks, since banks past
+/// the end wrap around like in [`rom_byte`].
+pub(crate) fn rom_bank(rom: &[u8], bank: usize) -> usize {
+    bank % (rom.len() / ROM_BANK_SIZE).max(1)
+}
+
+/// Which bank of `ram` a mapper's bank number really picks, wrapping like
+/// [`ram_offset`], or `None` if there's no RAM.
+pub(crate) fn ram_bank(ram: &[u8], bank: usize) -> Option<usize> {
+    match ram.len() {
+        0 => None,
+        len => Some(bank % (len / RAM_BANK_SIZE).max(1)),
+    }
+}
+
+/// Copy as much of `data` as fits into the start of `ram`.
+pub(crate) fn load_prefix(ram: &mut [u8], data: &[u8]) {
+    let len = ram.len().min(data.len());
+    ram[..len].copy_from_slice(&data[..len]);
+}
+
+/// The offset of a RAM address within `bank`, wrapping like [`rom_byte`].
+///
+/// `None` if there's no RAM at all.
+pub(crate) fn ram_offset(ram: &[u8], bank: usize, addr: u16) -> Option<usize> {
+    match ram.len() {
+        0 => None,
+        len => Some((bank * RAM_BANK_SIZE + (addr as usize & 0x1FFF)) % len),
+    }
+}
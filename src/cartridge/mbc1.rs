@@ -0,0 +1,254 @@
+use super::{
+    header::NINTENDO_LOGO,
+    mbc::{load_prefix, ram_bank, ram_offset, rom_bank, rom_byte, Banks, Mbc},
+    ROM_BANK_SIZE,
+};
+
+/// The MBC1, used by most early cartridges: up to 2MB of ROM and 32KB of
+/// RAM.
+pub struct Mbc1 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    ram_enabled: bool,
+    /// The low 5 bits of the switchable ROM bank (0x2000-0x3FFF).
+    bank1: u8,
+    /// 2 more bits (0x4000-0x5FFF), which pick the RAM bank or the upper
+    /// bits of the ROM bank.
+    bank2: u8,
+    /// Whether `bank2` also applies to 0x0000-0x3FFF and RAM (0x6000-0x7FFF).
+    advanced: bool,
+    /// MBC1M multicarts leave bit 4 of `bank1` unconnected, and wire `bank2`
+    /// one bit lower, so it picks between 256KB games.
+    multicart: bool,
+}
+
+/// Whether a ROM looks like an MBC1M multicart.
+///
+/// Nothing in the header says so, but these are all 1MB, and each game has
+/// its own header (with the logo) at the start of its 256KB.
+pub fn is_multicart(rom: &[u8]) -> bool {
+    let second_game = 0x10 * ROM_BANK_SIZE + 0x0104;
+
+    rom.len() == 64 * ROM_BANK_SIZE
+        && rom[second_game..second_game + NINTENDO_LOGO.len()] == NINTENDO_LOGO
+}
+
+impl Mbc1 {
+    pub fn new(rom: Vec<u8>, ram_size: usize) -> Self {
+        Mbc1 {
+            rom,
+            ram: vec![0; ram_size],
+            ram_enabled: false,
+            bank1: 1,
+            bank2: 0,
+            advanced: false,
+            multicart: false,
+        }
+    }
+
+    /// An MBC1M, the multicart wiring of the MBC1. See [`is_multicart`].
+    pub fn multicart(rom: Vec<u8>, ram_size: usize) -> Self {
+        Mbc1 {
+            multicart: true,
+            ..Self::new(rom, ram_size)
+        }
+    }
+
+    /// The bank mapped at 0x4000-0x7FFF, or 0x0000-0x3FFF in advanced mode
+    /// (which only takes the `bank2` bits).
+    fn rom_bank(&self, bank1: u8) -> usize {
+        match self.multicart {
+            true => (self.bank2 as usize) << 4 | (bank1 & 0x0F) as usize,
+            false => (self.bank2 as usize) << 5 | bank1 as usize,
+        }
+    }
+
+    fn ram_bank(&self) -> usize {
+        match self.advanced {
+            true => self.bank2 as usize,
+            false => 0,
+        }
+    }
+}
+
+impl Mbc for Mbc1 {
+    fn read_rom(&self, addr: u16) -> u8 {
+        let bank = match addr {
+            0x0000..=0x3FFF if self.advanced => self.rom_bank(0),
+            0x0000..=0x3FFF => 0,
+            _ => self.rom_bank(self.bank1),
+        };
+
+        rom_byte(&self.rom, bank, addr)
+    }
+
+    fn write_rom(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            // bank 0 can't be selected here, so it becomes bank 1 (and 0x20
+            // becomes 0x21, etc.). On an MBC1M, this still looks at all 5
+            // bits, so 0x10 stays bank 0 of the game.
+            0x2000..=0x3FFF => self.bank1 = (value & 0x1F).max(1),
+            0x4000..=0x5FFF => self.bank2 = value & 0x03,
+            _ => self.advanced = value & 0x01 != 0,
+        }
+    }
+
+    fn read_ram(&self, addr: u16) -> u8 {
+        match ram_offset(&self.ram, self.ram_bank(), addr) {
+            Some(offset) if self.ram_enabled => self.ram[offset],
+            _ => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, addr: u16, value: u8) {
+        match ram_offset(&self.ram, self.ram_bank(), addr) {
+            Some(offset) if self.ram_enabled => self.ram[offset] = value,
+            _ => {}
+        }
+    }
+
+    fn banks(&self) -> Banks {
+        let rom0 = match self.advanced {
+            true => self.rom_bank(0),
+            false => 0,
+        };
+
+        Banks {
+            rom0: rom_bank(&self.rom, rom0),
+            romx: rom_bank(&self.rom, self.rom_bank(self.bank1)),
+            ram: ram_bank(&self.ram, self.ram_bank()),
+            ram_enabled: self.ram_enabled,
+        }
+    }
+
+    fn save_data(&self) -> Vec<u8> {
+        self.ram.clone()
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        load_prefix(&mut self.ram, data);
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// A ROM where every byte of each bank holds the bank's number.
+    pub(crate) fn banked_rom(banks: usize) -> Vec<u8> {
+        (0..banks * ROM_BANK_SIZE)
+            .map(|i| (i / ROM_BANK_SIZE) as u8)
+            .collect()
+    }
+
+    #[test]
+    fn test_rom_banking() {
+        let mut mbc = Mbc1::new(banked_rom(128), 0);
+        assert_eq!(mbc.read_rom(0x0000), 0);
+        assert_eq!(mbc.read_rom(0x4000), 1);
+
+        mbc.write_rom(0x2000, 0x05);
+        assert_eq!(mbc.read_rom(0x7FFF), 5);
+
+        // 0 is translated to 1, but only looking at the low 5 bits
+        mbc.write_rom(0x2000, 0x00);
+        assert_eq!(mbc.read_rom(0x4000), 1);
+        mbc.write_rom(0x2000, 0x20);
+        assert_eq!(mbc.read_rom(0x4000), 1);
+
+        // the upper bits come from the second register
+        mbc.write_rom(0x4000, 0x01);
+        assert_eq!(mbc.read_rom(0x4000), 0x21);
+        mbc.write_rom(0x2000, 0x02);
+        assert_eq!(mbc.read_rom(0x4000), 0x22);
+        assert_eq!(mbc.read_rom(0x0000), 0);
+
+        // which also applies to bank 0 in advanced mode
+        mbc.write_rom(0x6000, 0x01);
+        assert_eq!(mbc.read_rom(0x0000), 0x20);
+        assert_eq!(
+            mbc.banks(),
+            Banks {
+                rom0: 0x20,
+                romx: 0x22,
+                ram: None,
+                ram_enabled: false
+            }
+        );
+    }
+
+    /// A 1MB ROM with four 256KB games in it.
+    fn multicart_rom() -> Vec<u8> {
+        let mut rom = banked_rom(64);
+        for game in 0..4 {
+            let logo = game * 0x10 * ROM_BANK_SIZE + 0x0104;
+            rom[logo..logo + NINTENDO_LOGO.len()].copy_from_slice(&NINTENDO_LOGO);
+        }
+        rom
+    }
+
+    #[test]
+    fn test_multicart_detection() {
+        assert!(is_multicart(&multicart_rom()));
+        assert!(!is_multicart(&banked_rom(64)));
+        assert!(!is_multicart(&banked_rom(32)));
+    }
+
+    #[test]
+    fn test_multicart_banking() {
+        let mut mbc = Mbc1::multicart(multicart_rom(), 0);
+
+        mbc.write_rom(0x2000, 0x03);
+        assert_eq!(mbc.read_rom(0x4000), 0x03);
+
+        // the second register picks the game
+        mbc.write_rom(0x4000, 0x02);
+        assert_eq!(mbc.read_rom(0x4000), 0x23);
+        assert_eq!(mbc.read_rom(0x0000), 0x00);
+        mbc.write_rom(0x6000, 0x01);
+        assert_eq!(mbc.read_rom(0x0000), 0x20);
+
+        // bit 4 isn't connected, but still counts for the 0 to 1 translation
+        mbc.write_rom(0x2000, 0x10);
+        assert_eq!(mbc.read_rom(0x4000), 0x20);
+        mbc.write_rom(0x2000, 0x00);
+        assert_eq!(mbc.read_rom(0x4000), 0x21);
+    }
+
+    #[test]
+    fn test_bank_wraps_on_small_rom() {
+        let mut mbc = Mbc1::new(banked_rom(4), 0);
+
+        mbc.write_rom(0x2000, 0x06);
+        assert_eq!(mbc.read_rom(0x4000), 2);
+    }
+
+    #[test]
+    fn test_ram_enable_and_banking() {
+        let mut mbc = Mbc1::new(banked_rom(4), 0x8000);
+
+        mbc.write_ram(0xA000, 0x12);
+        assert_eq!(mbc.read_ram(0xA000), 0xFF);
+
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_ram(0xA000, 0x12);
+        assert_eq!(mbc.read_ram(0xA000), 0x12);
+
+        // RAM banking only applies in advanced mode
+        mbc.write_rom(0x4000, 0x02);
+        assert_eq!(mbc.read_ram(0xA000), 0x12);
+        mbc.write_rom(0x6000, 0x01);
+        assert_eq!(mbc.read_ram(0xA000), 0x00);
+        mbc.write_ram(0xA000, 0x34);
+
+        mbc.write_rom(0x4000, 0x00);
+        assert_eq!(mbc.read_ram(0xA000), 0x12);
+        mbc.write_rom(0x4000, 0x06);
+        assert_eq!(mbc.banks().ram, Some(2));
+        mbc.write_rom(0x4000, 0x00);
+
+        mbc.write_rom(0x0000, 0x00);
+        assert_eq!(mbc.read_ram(0xA000), 0xFF);
+    }
+}
@@ -0,0 +1,117 @@
+use super::mbc::{load_prefix, rom_bank, rom_byte, Banks, Mbc};
+
+/// The MBC2: up to 256KB of ROM, and 512 half-bytes of RAM built into the
+/// mapper itself.
+pub struct Mbc2 {
+    rom: Vec<u8>,
+    /// Only the low nibble of each byte is used.
+    ram: [u8; 512],
+    ram_enabled: bool,
+    rom_bank: u8,
+}
+
+impl Mbc2 {
+    pub fn new(rom: Vec<u8>) -> Self {
+        Mbc2 {
+            rom,
+            ram: [0; 512],
+            ram_enabled: false,
+            rom_bank: 1,
+        }
+    }
+}
+
+impl Mbc for Mbc2 {
+    fn read_rom(&self, addr: u16) -> u8 {
+        let bank = match addr {
+            0x0000..=0x3FFF => 0,
+            _ => self.rom_bank as usize,
+        };
+
+        rom_byte(&self.rom, bank, addr)
+    }
+
+    fn write_rom(&mut self, addr: u16, value: u8) {
+        // there's only one register, and bit 8 of the address picks whether
+        // a write goes to RAM enable or the ROM bank
+        match addr {
+            0x0000..=0x3FFF if addr & 0x0100 == 0 => self.ram_enabled = value & 0x0F == 0x0A,
+            0x0000..=0x3FFF => self.rom_bank = (value & 0x0F).max(1),
+            _ => {}
+        }
+    }
+
+    fn read_ram(&self, addr: u16) -> u8 {
+        match self.ram_enabled {
+            // the top nibble isn't connected, so it floats high
+            true => self.ram[addr as usize & 0x01FF] | 0xF0,
+            false => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, addr: u16, value: u8) {
+        if self.ram_enabled {
+            self.ram[addr as usize & 0x01FF] = value & 0x0F;
+        }
+    }
+
+    fn banks(&self) -> Banks {
+        Banks {
+            rom0: 0,
+            romx: rom_bank(&self.rom, self.rom_bank as usize),
+            ram: Some(0),
+            ram_enabled: self.ram_enabled,
+        }
+    }
+
+    fn save_data(&self) -> Vec<u8> {
+        self.ram.to_vec()
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        load_prefix(&mut self.ram, data);
+        for nibble in &mut self.ram {
+            *nibble &= 0x0F;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::mbc1::tests::banked_rom;
+
+    #[test]
+    fn test_register_select() {
+        let mut mbc = Mbc2::new(banked_rom(16));
+
+        // bit 8 set: ROM bank
+        mbc.write_rom(0x2100, 0x05);
+        assert_eq!(mbc.read_rom(0x4000), 5);
+        mbc.write_rom(0x0100, 0x10);
+        assert_eq!(mbc.read_rom(0x4000), 1);
+
+        // bit 8 clear: RAM enable, whatever the rest of the address
+        mbc.write_rom(0x2000, 0x0A);
+        assert_eq!(mbc.read_rom(0x4000), 1);
+        mbc.write_ram(0xA000, 0x03);
+        assert_eq!(mbc.read_ram(0xA000), 0xF3);
+    }
+
+    #[test]
+    fn test_half_byte_ram() {
+        let mut mbc = Mbc2::new(banked_rom(16));
+        mbc.write_rom(0x0000, 0x0A);
+
+        mbc.write_ram(0xA000, 0xAB);
+        assert_eq!(mbc.read_ram(0xA000), 0xFB);
+
+        // 512 entries, repeated through the rest of 0xA000-0xBFFF
+        mbc.write_ram(0xA1FF, 0x04);
+        assert_eq!(mbc.read_ram(0xA200), 0xFB);
+        assert_eq!(mbc.read_ram(0xBFFF), 0xF4);
+
+        mbc.write_rom(0x0000, 0x00);
+        assert_eq!(mbc.read_ram(0xA000), 0xFF);
+    }
+}
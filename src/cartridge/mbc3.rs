@@ -0,0 +1,222 @@
+use super::{
+    mbc::{load_prefix, ram_bank, ram_offset, rom_bank, rom_byte, Banks, Mbc},
+    rtc::{Rtc, RtcRegister},
+};
+
+/// The MBC3: up to 2MB of ROM, 32KB of RAM, and optionally a real-time
+/// clock.
+pub struct Mbc3 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    rtc: Option<Rtc>,
+    /// Enables both RAM and the clock.
+    ram_enabled: bool,
+    rom_bank: u8,
+    /// Either a RAM bank (0x00-0x07), or a clock register (0x08-0x0C).
+    select: u8,
+}
+
+impl Mbc3 {
+    pub fn new(rom: Vec<u8>, ram_size: usize, rtc: Option<Rtc>) -> Self {
+        Mbc3 {
+            rom,
+            ram: vec![0; ram_size],
+            rtc,
+            ram_enabled: false,
+            rom_bank: 1,
+            select: 0,
+        }
+    }
+}
+
+impl Mbc for Mbc3 {
+    fn read_rom(&self, addr: u16) -> u8 {
+        let bank = match addr {
+            0x0000..=0x3FFF => 0,
+            _ => self.rom_bank as usize,
+        };
+
+        rom_byte(&self.rom, bank, addr)
+    }
+
+    fn write_rom(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => self.rom_bank = (value & 0x7F).max(1),
+            0x4000..=0x5FFF => self.select = value,
+            _ => {
+                if let Some(rtc) = &mut self.rtc {
+                    rtc.write_latch(value);
+                }
+            }
+        }
+    }
+
+    fn read_ram(&self, addr: u16) -> u8 {
+        if !self.ram_enabled {
+            return 0xFF;
+        }
+
+        match (self.select, &self.rtc) {
+            (0x00..=0x07, _) => ram_offset(&self.ram, self.select as usize, addr)
+                .map_or(0xFF, |offset| self.ram[offset]),
+            (select, Some(rtc)) => RtcRegister::from_select(select).map_or(0xFF, |r| rtc.read(r)),
+            _ => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, addr: u16, value: u8) {
+        if !self.ram_enabled {
+            return;
+        }
+
+        match (self.select, &mut self.rtc) {
+            (0x00..=0x07, _) => {
+                if let Some(offset) = ram_offset(&self.ram, self.select as usize, addr) {
+                    self.ram[offset] = value;
+                }
+            }
+            (select, Some(rtc)) => {
+                if let Some(register) = RtcRegister::from_select(select) {
+                    rtc.write(register, value);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// While a clock register is selected, there's no RAM bank.
+    fn banks(&self) -> Banks {
+        Banks {
+            rom0: 0,
+            romx: rom_bank(&self.rom, self.rom_bank as usize),
+            ram: ram_bank(&self.ram, self.select as usize).filter(|_| self.select <= 0x07),
+            ram_enabled: self.ram_enabled,
+        }
+    }
+
+    /// RAM, followed by the clock's state if there is a clock.
+    fn save_data(&self) -> Vec<u8> {
+        let mut data = self.ram.clone();
+        if let Some(rtc) = &self.rtc {
+            data.extend_from_slice(&rtc.save());
+        }
+        data
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        load_prefix(&mut self.ram, data);
+        if let (Some(rtc), Some(footer)) = (&mut self.rtc, data.get(self.ram.len()..)) {
+            rtc.load(footer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::{
+        mbc1::tests::banked_rom,
+        rtc::{tests::ManualClock, RTC_FOOTER_SIZE},
+    };
+
+    #[test]
+    fn test_rom_banking() {
+        let mut mbc = Mbc3::new(banked_rom(128), 0, None);
+        assert_eq!(mbc.read_rom(0x4000), 1);
+
+        mbc.write_rom(0x2000, 0x00);
+        assert_eq!(mbc.read_rom(0x4000), 1);
+
+        // unlike the MBC1, banks 0x20, 0x40 and 0x60 are reachable
+        mbc.write_rom(0x2000, 0x20);
+        assert_eq!(mbc.read_rom(0x4000), 0x20);
+        mbc.write_rom(0x2000, 0x7F);
+        assert_eq!(mbc.read_rom(0x7FFF), 0x7F);
+        assert_eq!(mbc.read_rom(0x0000), 0);
+    }
+
+    #[test]
+    fn test_ram_banking() {
+        let mut mbc = Mbc3::new(banked_rom(4), 0x8000, None);
+
+        mbc.write_ram(0xA000, 0x12);
+        assert_eq!(mbc.read_ram(0xA000), 0xFF);
+
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_rom(0x4000, 0x03);
+        mbc.write_ram(0xA000, 0x12);
+        assert_eq!(mbc.read_ram(0xA000), 0x12);
+
+        mbc.write_rom(0x4000, 0x00);
+        assert_eq!(mbc.read_ram(0xA000), 0x00);
+
+        // without a clock, its registers read as open bus
+        mbc.write_rom(0x4000, 0x08);
+        assert_eq!(mbc.read_ram(0xA000), 0xFF);
+    }
+
+    #[test]
+    fn test_rtc_registers() {
+        let clock = ManualClock::default();
+        let rtc = Rtc::new(Box::new(clock.clone()));
+        let mut mbc = Mbc3::new(banked_rom(4), 0x2000, Some(rtc));
+        mbc.write_rom(0x0000, 0x0A);
+
+        mbc.write_rom(0x4000, 0x0A);
+        mbc.write_ram(0xA000, 23);
+        mbc.write_rom(0x4000, 0x09);
+        mbc.write_ram(0xA000, 59);
+        clock.advance(75);
+
+        // nothing changes until the clock is latched
+        assert_eq!(mbc.read_ram(0xA000), 0);
+        mbc.write_rom(0x6000, 0x00);
+        mbc.write_rom(0x6000, 0x01);
+
+        let read = |mbc: &mut Mbc3, select| {
+            mbc.write_rom(0x4000, select);
+            mbc.read_ram(0xA000)
+        };
+        assert_eq!(read(&mut mbc, 0x08), 15);
+        assert_eq!(read(&mut mbc, 0x09), 0);
+        assert_eq!(read(&mut mbc, 0x0A), 0);
+        assert_eq!(read(&mut mbc, 0x0B), 1);
+        assert_eq!(read(&mut mbc, 0x0C), 0x00);
+
+        // RAM is still there on the other side of the select register
+        mbc.write_rom(0x4000, 0x00);
+        mbc.write_ram(0xA000, 0x42);
+        assert_eq!(mbc.read_ram(0xA000), 0x42);
+        assert_eq!(read(&mut mbc, 0x08), 15);
+    }
+
+    #[test]
+    fn test_save_data_includes_rtc() {
+        let clock = ManualClock::default();
+        let mut mbc = Mbc3::new(banked_rom(4), 0x2000, None);
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_ram(0xA000, 0x42);
+        assert_eq!(mbc.save_data().len(), 0x2000);
+
+        let rtc = Rtc::new(Box::new(clock.clone()));
+        let mut with_rtc = Mbc3::new(banked_rom(4), 0x2000, Some(rtc));
+        with_rtc.load_save_data(&mbc.save_data());
+        with_rtc.write_rom(0x0000, 0x0A);
+        with_rtc.write_rom(0x4000, 0x0A);
+        with_rtc.write_ram(0xA000, 12);
+
+        let data = with_rtc.save_data();
+        assert_eq!(data.len(), 0x2000 + RTC_FOOTER_SIZE);
+
+        let rtc = Rtc::new(Box::new(clock.clone()));
+        let mut restored = Mbc3::new(banked_rom(4), 0x2000, Some(rtc));
+        restored.load_save_data(&data);
+        restored.write_rom(0x0000, 0x0A);
+        restored.write_rom(0x6000, 0x00);
+        restored.write_rom(0x6000, 0x01);
+        assert_eq!(restored.read_ram(0xA000), 0x42);
+        restored.write_rom(0x4000, 0x0A);
+        assert_eq!(restored.read_ram(0xA000), 12);
+    }
+}
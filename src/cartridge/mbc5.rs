@@ -0,0 +1,173 @@
+use super::mbc::{
+    load_prefix, ram_bank, ram_offset, rom_bank, rom_byte, Banks, Mbc, RumbleHandler,
+};
+
+/// The MBC5, used by most later and all CGB-era cartridges: up to 8MB of ROM
+/// and 128KB of RAM, and optionally a rumble motor.
+pub struct Mbc5 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    ram_enabled: bool,
+    /// 9 bits, split between 0x2000-0x2FFF and 0x3000-0x3FFF. Unlike the
+    /// older mappers, bank 0 can be mapped here too.
+    rom_bank: u16,
+    ram_bank: u8,
+    /// Cartridges with a motor use bit 3 of the RAM bank register for it.
+    has_rumble: bool,
+    rumbling: bool,
+    rumble_handler: Option<RumbleHandler>,
+}
+
+impl Mbc5 {
+    pub fn new(rom: Vec<u8>, ram_size: usize, has_rumble: bool) -> Self {
+        Mbc5 {
+            rom,
+            ram: vec![0; ram_size],
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            has_rumble,
+            rumbling: false,
+            rumble_handler: None,
+        }
+    }
+
+    fn set_ram_bank(&mut self, value: u8) {
+        if !self.has_rumble {
+            self.ram_bank = value & 0x0F;
+            return;
+        }
+
+        self.ram_bank = value & 0x07;
+        let rumbling = value & 0x08 != 0;
+        if rumbling != self.rumbling {
+            self.rumbling = rumbling;
+            if let Some(handler) = &mut self.rumble_handler {
+                handler(rumbling);
+            }
+        }
+    }
+}
+
+impl Mbc for Mbc5 {
+    fn read_rom(&self, addr: u16) -> u8 {
+        let bank = match addr {
+            0x0000..=0x3FFF => 0,
+            _ => self.rom_bank as usize,
+        };
+
+        rom_byte(&self.rom, bank, addr)
+    }
+
+    fn write_rom(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x2FFF => self.rom_bank = self.rom_bank & 0x100 | value as u16,
+            0x3000..=0x3FFF => self.rom_bank = self.rom_bank & 0xFF | ((value & 0x01) as u16) << 8,
+            0x4000..=0x5FFF => self.set_ram_bank(value),
+            _ => {}
+        }
+    }
+
+    fn read_ram(&self, addr: u16) -> u8 {
+        match ram_offset(&self.ram, self.ram_bank as usize, addr) {
+            Some(offset) if self.ram_enabled => self.ram[offset],
+            _ => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, addr: u16, value: u8) {
+        match ram_offset(&self.ram, self.ram_bank as usize, addr) {
+            Some(offset) if self.ram_enabled => self.ram[offset] = value,
+            _ => {}
+        }
+    }
+
+    fn set_rumble_handler(&mut self, handler: RumbleHandler) {
+        if self.has_rumble {
+            self.rumble_handler = Some(handler);
+        }
+    }
+
+    fn banks(&self) -> Banks {
+        Banks {
+            rom0: 0,
+            romx: rom_bank(&self.rom, self.rom_bank as usize),
+            ram: ram_bank(&self.ram, self.ram_bank as usize),
+            ram_enabled: self.ram_enabled,
+        }
+    }
+
+    fn save_data(&self) -> Vec<u8> {
+        self.ram.clone()
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        load_prefix(&mut self.ram, data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+    use crate::cartridge::mbc1::tests::banked_rom;
+
+    #[test]
+    fn test_rom_banking() {
+        // banked_rom only counts to 0xFF, so mark the second half
+        let mut rom = banked_rom(512);
+        for bank in 256..512 {
+            rom[bank * 0x4000 + 1] = 0x01;
+        }
+        let mut mbc = Mbc5::new(rom, 0, false);
+        assert_eq!(mbc.read_rom(0x4000), 1);
+
+        mbc.write_rom(0x2000, 0x00);
+        assert_eq!(mbc.read_rom(0x4000), 0);
+
+        mbc.write_rom(0x2000, 0x34);
+        mbc.write_rom(0x3000, 0x01);
+        assert_eq!((mbc.read_rom(0x4000), mbc.read_rom(0x4001)), (0x34, 0x01));
+        assert_eq!(mbc.read_rom(0x0001), 0x00);
+
+        mbc.write_rom(0x3000, 0x00);
+        assert_eq!((mbc.read_rom(0x4000), mbc.read_rom(0x4001)), (0x34, 0x34));
+    }
+
+    #[test]
+    fn test_ram_banking() {
+        let mut mbc = Mbc5::new(banked_rom(4), 0x20000, false);
+        mbc.write_rom(0x0000, 0x0A);
+
+        mbc.write_rom(0x4000, 0x0F);
+        mbc.write_ram(0xBFFF, 0x12);
+        assert_eq!(mbc.read_ram(0xBFFF), 0x12);
+        mbc.write_rom(0x4000, 0x07);
+        assert_eq!(mbc.read_ram(0xBFFF), 0x00);
+
+        mbc.write_rom(0x0000, 0x00);
+        assert_eq!(mbc.read_ram(0xBFFF), 0xFF);
+    }
+
+    #[test]
+    fn test_rumble() {
+        let mut mbc = Mbc5::new(banked_rom(4), 0x8000, true);
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let log = events.clone();
+        mbc.set_rumble_handler(Box::new(move |on| log.borrow_mut().push(on)));
+        mbc.write_rom(0x0000, 0x0A);
+
+        mbc.write_rom(0x4000, 0x02);
+        mbc.write_ram(0xA000, 0x12);
+        mbc.write_rom(0x4000, 0x0A);
+        mbc.write_rom(0x4000, 0x0B);
+        mbc.write_rom(0x4000, 0x00);
+
+        assert_eq!(*events.borrow(), [true, false]);
+        // the motor bit doesn't pick a bank
+        mbc.write_rom(0x4000, 0x0A);
+        assert_eq!(mbc.read_ram(0xA000), 0x12);
+    }
+}
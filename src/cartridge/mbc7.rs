@@ -0,0 +1,358 @@
+use super::mbc::{rom_bank, rom_byte, Banks, Mbc};
+
+/// What the accelerometer reads when level.
+const TILT_CENTER: f32 = 0x81D0 as f32;
+/// How far the accelerometer reading moves per g.
+const TILT_PER_G: f32 = 0x70 as f32;
+
+/// The MBC7: up to 2MB of ROM, with a two-axis accelerometer and a 256 byte
+/// serial EEPROM in place of RAM.
+pub struct Mbc7 {
+    rom: Vec<u8>,
+    rom_bank: u8,
+    /// RAM needs two enables: 0x0A to 0x0000-0x1FFF, and 0x40 to
+    /// 0x4000-0x5FFF.
+    ram_enabled: (bool, bool),
+    /// The current tilt, in g.
+    tilt: (f32, f32),
+    /// The accelerometer reading the game sees.
+    latched: (u16, u16),
+    /// Whether the latch has been reset, which has to happen before each
+    /// new reading.
+    latch_armed: bool,
+    eeprom: Eeprom,
+}
+
+impl Mbc7 {
+    pub fn new(rom: Vec<u8>) -> Self {
+        Mbc7 {
+            rom,
+            rom_bank: 1,
+            ram_enabled: (false, false),
+            tilt: (0.0, 0.0),
+            latched: (0x8000, 0x8000),
+            latch_armed: false,
+            eeprom: Eeprom::new(),
+        }
+    }
+}
+
+fn tilt_reading(g: f32) -> u16 {
+    (TILT_CENTER + g * TILT_PER_G).clamp(0.0, u16::MAX as f32) as u16
+}
+
+impl Mbc for Mbc7 {
+    fn read_rom(&self, addr: u16) -> u8 {
+        let bank = match addr {
+            0x0000..=0x3FFF => 0,
+            _ => self.rom_bank as usize,
+        };
+
+        rom_byte(&self.rom, bank, addr)
+    }
+
+    fn write_rom(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x0000..=0x1FFF => self.ram_enabled.0 = value == 0x0A,
+            0x2000..=0x3FFF => self.rom_bank = value & 0x7F,
+            0x4000..=0x5FFF => self.ram_enabled.1 = value == 0x40,
+            _ => {}
+        }
+    }
+
+    fn read_ram(&self, addr: u16) -> u8 {
+        if self.ram_enabled != (true, true) || addr >= 0xB000 {
+            return 0xFF;
+        }
+
+        let (x, y) = self.latched;
+        match (addr >> 4) & 0x0F {
+            0x2 => x as u8,
+            0x3 => (x >> 8) as u8,
+            0x4 => y as u8,
+            0x5 => (y >> 8) as u8,
+            0x6 => 0x00,
+            0x8 => self.eeprom.read(),
+            _ => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, addr: u16, value: u8) {
+        if self.ram_enabled != (true, true) || addr >= 0xB000 {
+            return;
+        }
+
+        match ((addr >> 4) & 0x0F, value) {
+            (0x0, 0x55) => {
+                self.latched = (0x8000, 0x8000);
+                self.latch_armed = true;
+            }
+            (0x1, 0xAA) if self.latch_armed => {
+                self.latched = (tilt_reading(self.tilt.0), tilt_reading(self.tilt.1));
+                self.latch_armed = false;
+            }
+            (0x8, _) => self.eeprom.write(value),
+            _ => {}
+        }
+    }
+
+    fn set_tilt(&mut self, x: f32, y: f32) {
+        self.tilt = (x, y);
+    }
+
+    /// The EEPROM isn't RAM, so there's never a RAM bank.
+    fn banks(&self) -> Banks {
+        Banks {
+            rom0: 0,
+            romx: rom_bank(&self.rom, self.rom_bank as usize),
+            ram: None,
+            ram_enabled: self.ram_enabled == (true, true),
+        }
+    }
+
+    /// The EEPROM, as little-endian words.
+    fn save_data(&self) -> Vec<u8> {
+        self.eeprom
+            .words
+            .iter()
+            .flat_map(|word| word.to_le_bytes())
+            .collect()
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        for (word, bytes) in self.eeprom.words.iter_mut().zip(data.chunks_exact(2)) {
+            *word = u16::from_le_bytes([bytes[0], bytes[1]]);
+        }
+    }
+}
+
+/// Where the EEPROM is in a command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EepromState {
+    /// Waiting for a start bit.
+    Idle,
+    /// Shifting in a 2-bit opcode and 8-bit address.
+    Command { bits: u16, count: u8 },
+    /// Shifting a word out.
+    Read { word: u16, remaining: u8 },
+    /// Shifting in a word to write, to one address or to all of them.
+    Write {
+        addr: Option<u8>,
+        bits: u16,
+        count: u8,
+    },
+}
+
+/// A 93LC56 EEPROM: 128 16-bit words, accessed one bit at a time.
+///
+/// Its pins are mapped to 0xA080-0xA08F: bit 7 is chip select, bit 6 is the
+/// clock, bit 1 is data in, and bit 0 is data out.
+struct Eeprom {
+    words: [u16; 128],
+    select: bool,
+    clock: bool,
+    data_in: bool,
+    data_out: bool,
+    write_enabled: bool,
+    state: EepromState,
+}
+
+impl Eeprom {
+    fn new() -> Self {
+        Eeprom {
+            words: [0xFFFF; 128],
+            select: false,
+            clock: false,
+            data_in: false,
+            data_out: true,
+            write_enabled: false,
+            state: EepromState::Idle,
+        }
+    }
+
+    fn read(&self) -> u8 {
+        (self.select as u8) << 7
+            | (self.clock as u8) << 6
+            | (self.data_in as u8) << 1
+            | self.data_out as u8
+    }
+
+    fn write(&mut self, value: u8) {
+        let rising = !self.clock && value & 0x40 != 0;
+        self.select = value & 0x80 != 0;
+        self.clock = value & 0x40 != 0;
+        self.data_in = value & 0x02 != 0;
+
+        if !self.select {
+            self.state = EepromState::Idle;
+        } else if rising {
+            self.shift();
+        }
+    }
+
+    /// Handle a rising clock edge.
+    fn shift(&mut self) {
+        let bit = self.data_in as u16;
+
+        self.state = match self.state {
+            EepromState::Idle if self.data_in => EepromState::Command { bits: 0, count: 0 },
+            EepromState::Idle => EepromState::Idle,
+            EepromState::Command { bits, count: 9 } => self.execute(bits << 1 | bit),
+            EepromState::Command { bits, count } => EepromState::Command {
+                bits: bits << 1 | bit,
+                count: count + 1,
+            },
+            EepromState::Read { word, remaining } => {
+                self.data_out = word & 0x8000 != 0;
+                match remaining {
+                    1 => EepromState::Idle,
+                    _ => EepromState::Read {
+                        word: word << 1,
+                        remaining: remaining - 1,
+                    },
+                }
+            }
+            EepromState::Write {
+                addr,
+                bits,
+                count: 15,
+            } => {
+                let word = bits << 1 | bit;
+                if self.write_enabled {
+                    match addr {
+                        Some(addr) => self.words[addr as usize] = word,
+                        None => self.words = [word; 128],
+                    }
+                }
+                self.data_out = true;
+                EepromState::Idle
+            }
+            EepromState::Write { addr, bits, count } => EepromState::Write {
+                addr,
+                bits: bits << 1 | bit,
+                count: count + 1,
+            },
+        };
+    }
+
+    fn execute(&mut self, command: u16) -> EepromState {
+        let addr = (command & 0x7F) as u8;
+
+        match (command >> 8, (command >> 6) & 0x03) {
+            // read, starting with a dummy 0
+            (0b10, _) => {
+                self.data_out = false;
+                EepromState::Read {
+                    word: self.words[addr as usize],
+                    remaining: 16,
+                }
+            }
+            (0b01, _) => EepromState::Write {
+                addr: Some(addr),
+                bits: 0,
+                count: 0,
+            },
+            (0b11, _) => {
+                if self.write_enabled {
+                    self.words[addr as usize] = 0xFFFF;
+                }
+                EepromState::Idle
+            }
+            (_, 0b00) => {
+                self.write_enabled = false;
+                EepromState::Idle
+            }
+            (_, 0b01) => EepromState::Write {
+                addr: None,
+                bits: 0,
+                count: 0,
+            },
+            (_, 0b10) => {
+                if self.write_enabled {
+                    self.words = [0xFFFF; 128];
+                }
+                EepromState::Idle
+            }
+            _ => {
+                self.write_enabled = true;
+                EepromState::Idle
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::mbc1::tests::banked_rom;
+
+    fn enabled() -> Mbc7 {
+        let mut mbc = Mbc7::new(banked_rom(64));
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_rom(0x4000, 0x40);
+        mbc
+    }
+
+    /// Clock `bits` (most significant first) into the EEPROM, returning what
+    /// it sent back.
+    fn clock_bits(mbc: &mut Mbc7, value: u32, bits: u8) -> u32 {
+        let mut out = 0;
+        for i in (0..bits).rev() {
+            let data_in = ((value >> i) & 1) as u8;
+            mbc.write_ram(0xA080, 0x80 | data_in << 1);
+            mbc.write_ram(0xA080, 0xC0 | data_in << 1);
+            out = out << 1 | (mbc.read_ram(0xA080) & 0x01) as u32;
+        }
+        out
+    }
+
+    fn command(mbc: &mut Mbc7, command: u32, bits: u8) -> u32 {
+        let out = clock_bits(mbc, command, bits);
+        mbc.write_ram(0xA080, 0x00);
+        out
+    }
+
+    #[test]
+    fn test_accelerometer() {
+        let mut mbc = enabled();
+        mbc.set_tilt(1.0, -0.5);
+
+        // nothing until the latch is reset and then latched
+        mbc.write_ram(0xA010, 0xAA);
+        assert_eq!(mbc.read_ram(0xA020), 0x00);
+        mbc.write_ram(0xA000, 0x55);
+        mbc.write_ram(0xA010, 0xAA);
+
+        let read =
+            |mbc: &Mbc7, addr| u16::from_le_bytes([mbc.read_ram(addr), mbc.read_ram(addr + 0x10)]);
+        assert_eq!(read(&mbc, 0xA020), 0x8240);
+        assert_eq!(read(&mbc, 0xA040), 0x8198);
+
+        mbc.set_tilt(0.0, 0.0);
+        assert_eq!(read(&mbc, 0xA020), 0x8240);
+
+        // both enables are needed
+        mbc.write_rom(0x4000, 0x00);
+        assert_eq!(mbc.read_ram(0xA020), 0xFF);
+    }
+
+    #[test]
+    fn test_eeprom() {
+        let mut mbc = enabled();
+        // start bit, opcode, and address
+        let op = |opcode: u32, addr: u32| 1 << 10 | opcode << 8 | addr;
+        // the dummy 0, then the word
+        let read_word = |mbc: &mut Mbc7, addr| command(mbc, op(0b10, addr) << 16, 27) & 0x1FFFF;
+
+        // writes are ignored until enabled
+        command(&mut mbc, op(0b01, 0x05) << 16 | 0x1234, 27);
+        assert_eq!(read_word(&mut mbc, 0x05), 0xFFFF);
+
+        command(&mut mbc, op(0b00, 0xC0), 11);
+        command(&mut mbc, op(0b01, 0x05) << 16 | 0x1234, 27);
+        assert_eq!(read_word(&mut mbc, 0x05), 0x1234);
+
+        command(&mut mbc, op(0b11, 0x05), 11);
+        assert_eq!(read_word(&mut mbc, 0x05), 0xFFFF);
+    }
+}
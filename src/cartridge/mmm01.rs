@@ -0,0 +1,211 @@
+use super::mbc::{load_prefix, ram_bank, ram_offset, rom_bank, rom_byte, Banks, Mbc};
+
+/// The MMM01, a mapper for multicarts that acts like an MBC1 once a game is
+/// picked.
+///
+/// It starts out with the last 32KB of ROM (the menu) mapped. The menu sets
+/// which part of the ROM and RAM the game gets, then sets bit 6 of
+/// 0x0000-0x1FFF, which locks that in until the console is reset.
+pub struct Mmm01 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    locked: bool,
+    ram_enabled: bool,
+    /// Bits 0-4 of the ROM bank, which the game switches like on an MBC1.
+    rom_low: u8,
+    /// Bits 5-8 of the ROM bank, which pick the game.
+    rom_high: u8,
+    /// Bits of `rom_low` that the game can't change, shifted down by 1.
+    rom_mask: u8,
+    ram_low: u8,
+    ram_high: u8,
+    /// Bits of `ram_low` that the game can't change.
+    ram_mask: u8,
+    advanced: bool,
+    /// Whether the game can change `advanced`.
+    mode_frozen: bool,
+}
+
+impl Mmm01 {
+    pub fn new(rom: Vec<u8>, ram_size: usize) -> Self {
+        Mmm01 {
+            rom,
+            ram: vec![0; ram_size],
+            locked: false,
+            ram_enabled: false,
+            rom_low: 0,
+            rom_high: 0,
+            rom_mask: 0,
+            ram_low: 0,
+            ram_high: 0,
+            ram_mask: 0,
+            advanced: false,
+            mode_frozen: false,
+        }
+    }
+
+    /// Replace the bits of `old` outside `frozen` (once locked).
+    fn update(&self, old: u8, new: u8, frozen: u8) -> u8 {
+        match self.locked {
+            true => old & frozen | new & !frozen,
+            false => new,
+        }
+    }
+
+    fn ram_bank(&self) -> usize {
+        let low = match self.advanced {
+            true => self.ram_low,
+            false => 0,
+        };
+
+        (self.ram_high << 2 | low) as usize
+    }
+}
+
+impl Mbc for Mmm01 {
+    fn read_rom(&self, addr: u16) -> u8 {
+        // the menu, in the last two banks (since the bank wraps)
+        if !self.locked {
+            let bank = match addr {
+                0x0000..=0x3FFF => 0x1FE,
+                _ => 0x1FF,
+            };
+            return rom_byte(&self.rom, bank, addr);
+        }
+
+        let base = (self.rom_high as usize) << 5;
+        let low = match addr {
+            0x0000..=0x3FFF => self.rom_low & (self.rom_mask << 1),
+            _ => self.rom_low.max(1),
+        };
+
+        rom_byte(&self.rom, base | low as usize, addr)
+    }
+
+    fn write_rom(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x0000..=0x1FFF => {
+                self.ram_enabled = value & 0x0F == 0x0A;
+                if !self.locked {
+                    self.ram_mask = (value >> 4) & 0x03;
+                    self.locked = value & 0x40 != 0;
+                }
+            }
+            0x2000..=0x3FFF => {
+                self.rom_low = self.update(self.rom_low, value & 0x1F, self.rom_mask << 1);
+                if !self.locked {
+                    self.rom_high = self.rom_high & 0x0C | (value >> 5) & 0x03;
+                }
+            }
+            0x4000..=0x5FFF => {
+                self.ram_low = self.update(self.ram_low, value & 0x03, self.ram_mask);
+                if !self.locked {
+                    self.ram_high = (value >> 2) & 0x03;
+                    self.rom_high = self.rom_high & 0x03 | (value >> 2) & 0x0C;
+                    self.mode_frozen = value & 0x40 != 0;
+                }
+            }
+            _ => {
+                if !(self.locked && self.mode_frozen) {
+                    self.advanced = value & 0x01 != 0;
+                }
+                if !self.locked {
+                    self.rom_mask = (value >> 2) & 0x0F;
+                }
+            }
+        }
+    }
+
+    fn read_ram(&self, addr: u16) -> u8 {
+        match ram_offset(&self.ram, self.ram_bank(), addr) {
+            Some(offset) if self.ram_enabled => self.ram[offset],
+            _ => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, addr: u16, value: u8) {
+        match ram_offset(&self.ram, self.ram_bank(), addr) {
+            Some(offset) if self.ram_enabled => self.ram[offset] = value,
+            _ => {}
+        }
+    }
+
+    fn banks(&self) -> Banks {
+        let (rom0, romx) = match self.locked {
+            false => (0x1FE, 0x1FF),
+            true => {
+                let base = (self.rom_high as usize) << 5;
+                let rom0 = self.rom_low & (self.rom_mask << 1);
+                (base | rom0 as usize, base | self.rom_low.max(1) as usize)
+            }
+        };
+
+        Banks {
+            rom0: rom_bank(&self.rom, rom0),
+            romx: rom_bank(&self.rom, romx),
+            ram: ram_bank(&self.ram, self.ram_bank()),
+            ram_enabled: self.ram_enabled,
+        }
+    }
+
+    fn save_data(&self) -> Vec<u8> {
+        self.ram.clone()
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        load_prefix(&mut self.ram, data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::mbc1::tests::banked_rom;
+
+    #[test]
+    fn test_menu_then_game() {
+        let mut mbc = Mmm01::new(banked_rom(128), 0x8000);
+
+        // the menu is at the end of the ROM
+        assert_eq!(mbc.read_rom(0x0000), 126);
+        assert_eq!(mbc.read_rom(0x4000), 127);
+
+        // pick the game at 0x40, and keep it from changing bit 4 of the bank
+        mbc.write_rom(0x2000, 0x40);
+        mbc.write_rom(0x6000, 0x20);
+        mbc.write_rom(0x0000, 0x4A);
+
+        assert_eq!(mbc.read_rom(0x0000), 0x40);
+        assert_eq!(mbc.read_rom(0x4000), 0x41);
+        mbc.write_rom(0x2000, 0x05);
+        assert_eq!(mbc.read_rom(0x4000), 0x45);
+        mbc.write_rom(0x2000, 0x1F);
+        assert_eq!(mbc.read_rom(0x4000), 0x4F);
+
+        // the game can't get out of its part of the ROM
+        mbc.write_rom(0x2000, 0xE0);
+        mbc.write_rom(0x4000, 0xFF);
+        mbc.write_rom(0x0000, 0x00);
+        assert_eq!(mbc.read_rom(0x4000), 0x41);
+    }
+
+    #[test]
+    fn test_ram() {
+        let mut mbc = Mmm01::new(banked_rom(128), 0x20000);
+
+        // give the game RAM banks 4-7, then lock
+        mbc.write_rom(0x4000, 0x04);
+        mbc.write_rom(0x0000, 0x4A);
+        mbc.write_ram(0xA000, 0x12);
+        assert_eq!(mbc.read_ram(0xA000), 0x12);
+
+        mbc.write_rom(0x6000, 0x01);
+        mbc.write_rom(0x4000, 0x01);
+        assert_eq!(mbc.read_ram(0xA000), 0x00);
+        mbc.write_rom(0x4000, 0x00);
+        assert_eq!(mbc.read_ram(0xA000), 0x12);
+
+        mbc.write_rom(0x0000, 0x00);
+        assert_eq!(mbc.read_ram(0xA000), 0xFF);
+    }
+}
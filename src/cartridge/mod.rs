@@ -0,0 +1,113 @@
+pub mod dat;
+pub mod header;
+pub mod huc1;
+pub mod mbc;
+pub mod mbc1;
+pub mod mbc2;
+pub mod mbc3;
+pub mod mbc5;
+pub mod mbc7;
+pub mod mmm01;
+pub mod rom_only;
+pub mod rtc;
+pub mod save_type;
+
+use std::{error::Error, fmt, io};
+
+use header::{HeaderProblem, MapperKind};
+
+/// The size of the DMG boot ROM, mapped over 0x0000-0x00FF.
+pub const DMG_BOOT_ROM_SIZE: usize = 0x100;
+/// The size of the CGB boot ROM, which is also mapped over 0x0200-0x08FF.
+pub const CGB_BOOT_ROM_SIZE: usize = 0x900;
+
+/// The size of one ROM bank.
+pub const ROM_BANK_SIZE: usize = 0x4000;
+/// The largest ROM any mapper can address (512 banks, on MBC5).
+pub const MAX_ROM_SIZE: usize = 512 * ROM_BANK_SIZE;
+
+/// Why a ROM couldn't be loaded.
+#[derive(Debug)]
+pub enum RomError {
+    Io(io::Error),
+    /// ROMs are at least two banks, and a whole number of banks long.
+    InvalidSize(usize),
+    /// The header failed the boot ROM's checks, while loading strictly.
+    InvalidHeader(Vec<HeaderProblem>),
+    /// The header asks for a mapper that isn't emulated.
+    UnsupportedMapper(MapperKind),
+    /// Boot ROMs are 256 bytes (DMG), or 2304 bytes (CGB).
+    InvalidBootRomSize(usize),
+    /// The ROM was fine, but its save file (or the previous cartridge's)
+    /// couldn't be read or written.
+    SaveFile(io::Error),
+}
+
+/// How to treat a ROM whose header fails the boot ROM's checks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Validation {
+    /// Refuse to load it.
+    #[default]
+    Strict,
+    /// Load it anyway, and report the problems as warnings. Test ROMs and
+    /// homebrew often don't bother with a valid header.
+    Permissive,
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "couldn't read ROM: {}", error),
+            Self::InvalidSize(size) => write!(
+                f,
+                "ROM is {} bytes, which isn't a whole number of 16KB banks between 32KB and 8MB",
+                size
+            ),
+            Self::InvalidHeader(problems) => {
+                write!(f, "not a valid Game Boy ROM: ")?;
+                for (i, problem) in problems.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", problem)?;
+                }
+                Ok(())
+            }
+            Self::UnsupportedMapper(mapper) => {
+                write!(f, "{:?} cartridges aren't supported", mapper)
+            }
+            Self::InvalidBootRomSize(size) => write!(
+                f,
+                "a boot ROM is 256 or 2304 bytes, but this one is {} bytes",
+                size
+            ),
+            Self::SaveFile(error) => write!(f, "couldn't access save file: {}", error),
+        }
+    }
+}
+
+impl Error for RomError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(error) | Self::SaveFile(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for RomError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+/// Check that a ROM image could plausibly be a cartridge.
+pub fn validate_size(rom: &[u8]) -> Result<(), RomError> {
+    let size = rom.len();
+
+    if !(2 * ROM_BANK_SIZE..=MAX_ROM_SIZE).contains(&size) || !size.is_multiple_of(ROM_BANK_SIZE) {
+        return Err(RomError::InvalidSize(size));
+    }
+
+    Ok(())
+}
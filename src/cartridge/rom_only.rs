@@ -0,0 +1,74 @@
+use super::mbc::{load_prefix, ram_bank, ram_offset, rom_bank, rom_byte, Banks, Mbc};
+
+/// A cartridge without a mapper: 32KB of ROM, and optionally up to 8KB of
+/// RAM.
+pub struct RomOnly {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+}
+
+impl RomOnly {
+    pub fn new(rom: Vec<u8>, ram_size: usize) -> Self {
+        RomOnly {
+            rom,
+            ram: vec![0; ram_size],
+        }
+    }
+}
+
+impl Mbc for RomOnly {
+    fn read_rom(&self, addr: u16) -> u8 {
+        rom_byte(&self.rom, addr as usize >> 14, addr)
+    }
+
+    fn write_rom(&mut self, _addr: u16, _value: u8) {}
+
+    fn read_ram(&self, addr: u16) -> u8 {
+        ram_offset(&self.ram, 0, addr).map_or(0xFF, |offset| self.ram[offset])
+    }
+
+    fn write_ram(&mut self, addr: u16, value: u8) {
+        if let Some(offset) = ram_offset(&self.ram, 0, addr) {
+            self.ram[offset] = value;
+        }
+    }
+
+    fn banks(&self) -> Banks {
+        Banks {
+            rom0: 0,
+            romx: rom_bank(&self.rom, 1),
+            ram: ram_bank(&self.ram, 0),
+            ram_enabled: true,
+        }
+    }
+
+    fn save_data(&self) -> Vec<u8> {
+        self.ram.clone()
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        load_prefix(&mut self.ram, data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rom_only() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x7FFF] = 0x42;
+        let mut cartridge = RomOnly::new(rom, 0);
+
+        cartridge.write_rom(0x7FFF, 0x00);
+        assert_eq!(cartridge.read_rom(0x7FFF), 0x42);
+
+        cartridge.write_ram(0xA000, 0x12);
+        assert_eq!(cartridge.read_ram(0xA000), 0xFF);
+
+        let mut cartridge = RomOnly::new(vec![0; 0x8000], 0x2000);
+        cartridge.write_ram(0xBFFF, 0x12);
+        assert_eq!(cartridge.read_ram(0xBFFF), 0x12);
+    }
+}
@@ -0,0 +1,391 @@
+//! The real-time clock on MBC3 cartridges.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The size of [`Rtc::save`]'s output.
+pub const RTC_FOOTER_SIZE: usize = 48;
+
+/// Where the clock gets the current time from.
+pub trait ClockSource {
+    /// Seconds since some fixed point, which only has to be consistent
+    /// between calls.
+    fn now(&self) -> u64;
+}
+
+/// The host's wall clock.
+pub struct SystemClock;
+
+impl ClockSource for SystemClock {
+    fn now(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs())
+    }
+}
+
+/// One of the clock's registers, selected by writing 0x08-0x0C to
+/// 0x4000-0x5FFF.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RtcRegister {
+    Seconds,
+    Minutes,
+    Hours,
+    /// The low 8 bits of the day counter.
+    DayLow,
+    /// Bit 0 is the top bit of the day counter, bit 6 halts the clock, and
+    /// bit 7 is set when the day counter overflows.
+    DayHigh,
+}
+
+impl RtcRegister {
+    /// In the order they're selected in.
+    pub const ALL: [Self; 5] = [
+        Self::Seconds,
+        Self::Minutes,
+        Self::Hours,
+        Self::DayLow,
+        Self::DayHigh,
+    ];
+
+    pub fn from_select(value: u8) -> Option<Self> {
+        match value {
+            0x08 => Some(Self::Seconds),
+            0x09 => Some(Self::Minutes),
+            0x0A => Some(Self::Hours),
+            0x0B => Some(Self::DayLow),
+            0x0C => Some(Self::DayHigh),
+            _ => None,
+        }
+    }
+}
+
+/// The clock's counters, as seen through its registers.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RtcTime {
+    pub seconds: u8,
+    pub minutes: u8,
+    pub hours: u8,
+    /// 9 bits.
+    pub days: u16,
+    pub halted: bool,
+    /// Set when `days` overflows, until the game clears it.
+    pub carry: bool,
+}
+
+impl RtcTime {
+    /// Whether every counter is within its normal range. Games can write
+    /// anything that fits in a counter's bits.
+    fn in_range(&self) -> bool {
+        self.seconds < 60 && self.minutes < 60 && self.hours < 24
+    }
+
+    /// Count one second. A counter only carries when it reaches its normal
+    /// limit; one that's already past it counts up to the limit of its bits
+    /// and wraps to 0 without carrying.
+    fn tick(&mut self) {
+        self.seconds = (self.seconds + 1) & 0x3F;
+        if self.seconds != 60 {
+            return;
+        }
+        self.seconds = 0;
+
+        self.minutes = (self.minutes + 1) & 0x3F;
+        if self.minutes != 60 {
+            return;
+        }
+        self.minutes = 0;
+
+        self.hours = (self.hours + 1) & 0x1F;
+        if self.hours != 24 {
+            return;
+        }
+        self.hours = 0;
+
+        self.days = (self.days + 1) & 0x1FF;
+        self.carry |= self.days == 0;
+    }
+
+    fn advance(&mut self, mut elapsed: u64) {
+        // out of range counters take at most a few hours to wrap, so count
+        // those a second at a time
+        while elapsed > 0 && !self.in_range() {
+            self.tick();
+            elapsed -= 1;
+        }
+        if elapsed == 0 {
+            return;
+        }
+
+        let seconds = self.seconds as u64 + elapsed;
+        let minutes = self.minutes as u64 + seconds / 60;
+        let hours = self.hours as u64 + minutes / 60;
+        let days = self.days as u64 + hours / 24;
+
+        self.seconds = (seconds % 60) as u8;
+        self.minutes = (minutes % 60) as u8;
+        self.hours = (hours % 24) as u8;
+        self.days = (days % 512) as u16;
+        self.carry |= days >= 512;
+    }
+
+    fn read(&self, register: RtcRegister) -> u8 {
+        match register {
+            RtcRegister::Seconds => self.seconds,
+            RtcRegister::Minutes => self.minutes,
+            RtcRegister::Hours => self.hours,
+            RtcRegister::DayLow => self.days as u8,
+            RtcRegister::DayHigh => {
+                (self.days >> 8) as u8 | (self.halted as u8) << 6 | (self.carry as u8) << 7
+            }
+        }
+    }
+
+    fn write(&mut self, register: RtcRegister, value: u8) {
+        match register {
+            RtcRegister::Seconds => self.seconds = value & 0x3F,
+            RtcRegister::Minutes => self.minutes = value & 0x3F,
+            RtcRegister::Hours => self.hours = value & 0x1F,
+            RtcRegister::DayLow => self.days = self.days & 0x100 | value as u16,
+            RtcRegister::DayHigh => {
+                self.days = self.days & 0xFF | ((value & 0x01) as u16) << 8;
+                self.halted = value & 0x40 != 0;
+                self.carry = value & 0x80 != 0;
+            }
+        }
+    }
+}
+
+/// The clock itself, which keeps counting while the emulator isn't running
+/// (just like the battery-backed original).
+///
+/// Games read a latched copy of the counters, which only updates when 0x00
+/// then 0x01 are written to 0x6000-0x7FFF.
+pub struct Rtc {
+    clock: Box<dyn ClockSource>,
+    /// The counters as of `synced`.
+    time: RtcTime,
+    synced: u64,
+    latched: RtcTime,
+    /// Whether the last latch write was 0x00.
+    latch_armed: bool,
+}
+
+impl Rtc {
+    pub fn new(clock: Box<dyn ClockSource>) -> Self {
+        let synced = clock.now();
+
+        Rtc {
+            clock,
+            time: RtcTime::default(),
+            synced,
+            latched: RtcTime::default(),
+            latch_armed: false,
+        }
+    }
+
+    /// Bring the counters up to date with the clock source.
+    fn sync(&mut self) {
+        let now = self.clock.now();
+        if !self.time.halted {
+            self.time.advance(now.saturating_sub(self.synced));
+        }
+        self.synced = now;
+    }
+
+    /// The live counters.
+    pub fn time(&mut self) -> RtcTime {
+        self.sync();
+        self.time
+    }
+
+    pub fn write_latch(&mut self, value: u8) {
+        if self.latch_armed && value == 0x01 {
+            self.latched = self.time();
+        }
+        self.latch_armed = value == 0x00;
+    }
+
+    pub fn read(&self, register: RtcRegister) -> u8 {
+        self.latched.read(register)
+    }
+
+    /// Set one of the live counters.
+    pub fn write(&mut self, register: RtcRegister, value: u8) {
+        self.sync();
+        self.time.write(register, value);
+    }
+
+    /// The clock's state, in the footer other emulators (VBA, BGB, SameBoy)
+    /// append to MBC3 saves: the live and then latched registers, each as a
+    /// 32-bit word, then the time they're as of.
+    pub fn save(&self) -> [u8; RTC_FOOTER_SIZE] {
+        let registers = [self.time, self.latched]
+            .into_iter()
+            .flat_map(|time| RtcRegister::ALL.map(|register| time.read(register) as u32));
+
+        let mut footer = [0; RTC_FOOTER_SIZE];
+        for (i, value) in registers.enumerate() {
+            footer[i * 4..i * 4 + 4].copy_from_slice(&value.to_le_bytes());
+        }
+        footer[40..].copy_from_slice(&self.synced.to_le_bytes());
+        footer
+    }
+
+    /// Restore a footer from [`Rtc::save`]. Some emulators only write the
+    /// low 32 bits of the time, making it 4 bytes shorter.
+    ///
+    /// Returns `false` (and changes nothing) if `footer` is the wrong size.
+    pub fn load(&mut self, footer: &[u8]) -> bool {
+        if footer.len() != RTC_FOOTER_SIZE && footer.len() != RTC_FOOTER_SIZE - 4 {
+            return false;
+        }
+
+        // only the low byte of each register's word means anything
+        let mut bytes = footer[..40].iter().step_by(4);
+        for time in [&mut self.time, &mut self.latched] {
+            for (register, &value) in RtcRegister::ALL.iter().zip(&mut bytes) {
+                time.write(*register, value);
+            }
+        }
+
+        let mut synced = [0; 8];
+        synced[..footer.len() - 40].copy_from_slice(&footer[40..]);
+        self.synced = u64::from_le_bytes(synced);
+
+        true
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{cell::Cell, rc::Rc};
+
+    use super::*;
+
+    /// A clock that only moves when told to.
+    #[derive(Clone, Default)]
+    pub(crate) struct ManualClock(pub Rc<Cell<u64>>);
+
+    impl ManualClock {
+        pub fn advance(&self, seconds: u64) {
+            self.0.set(self.0.get() + seconds);
+        }
+    }
+
+    impl ClockSource for ManualClock {
+        fn now(&self) -> u64 {
+            self.0.get()
+        }
+    }
+
+    fn latched(rtc: &mut Rtc) -> [u8; 5] {
+        rtc.write_latch(0x00);
+        rtc.write_latch(0x01);
+        RtcRegister::ALL.map(|register| rtc.read(register))
+    }
+
+    #[test]
+    fn test_counts_and_carries() {
+        let clock = ManualClock::default();
+        let mut rtc = Rtc::new(Box::new(clock.clone()));
+
+        clock.advance(((2 * 24 + 3) * 60 + 4) * 60 + 5);
+        assert_eq!(latched(&mut rtc), [5, 4, 3, 2, 0x00]);
+
+        clock.advance(300 * 24 * 60 * 60);
+        assert_eq!(latched(&mut rtc), [5, 4, 3, 46, 0x01]);
+
+        // the day counter overflows into the carry bit, which sticks
+        clock.advance(210 * 24 * 60 * 60);
+        assert_eq!(latched(&mut rtc), [5, 4, 3, 0, 0x80]);
+        clock.advance(1);
+        assert_eq!(latched(&mut rtc), [6, 4, 3, 0, 0x80]);
+
+        rtc.write(RtcRegister::DayHigh, 0x00);
+        assert_eq!(latched(&mut rtc), [6, 4, 3, 0, 0x00]);
+    }
+
+    #[test]
+    fn test_latch() {
+        let clock = ManualClock::default();
+        let mut rtc = Rtc::new(Box::new(clock.clone()));
+
+        clock.advance(10);
+        assert_eq!(latched(&mut rtc)[0], 10);
+
+        // reads don't change until the next 0x00, 0x01 sequence
+        clock.advance(10);
+        assert_eq!(rtc.read(RtcRegister::Seconds), 10);
+        rtc.write_latch(0x01);
+        assert_eq!(rtc.read(RtcRegister::Seconds), 10);
+        rtc.write_latch(0x00);
+        rtc.write_latch(0x01);
+        assert_eq!(rtc.read(RtcRegister::Seconds), 20);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let clock = ManualClock::default();
+        clock.advance(1_000_000);
+        let mut rtc = Rtc::new(Box::new(clock.clone()));
+
+        rtc.write(RtcRegister::Hours, 5);
+        rtc.write(RtcRegister::DayHigh, 0x81);
+        latched(&mut rtc);
+        clock.advance(30);
+        let footer = rtc.save();
+        assert_eq!(&footer[8..12], &[5, 0, 0, 0]);
+        assert_eq!(&footer[16..20], &[0x81, 0, 0, 0]);
+
+        // the clock keeps running while the emulator is closed
+        let mut restored = Rtc::new(Box::new(clock.clone()));
+        clock.advance(3600);
+        assert!(restored.load(&footer[..44]));
+        assert_eq!(restored.read(RtcRegister::Hours), 5);
+        assert_eq!(latched(&mut restored), [30, 0, 6, 0, 0x81]);
+
+        assert!(!restored.load(&footer[..40]));
+    }
+
+    #[test]
+    fn test_halt() {
+        let clock = ManualClock::default();
+        let mut rtc = Rtc::new(Box::new(clock.clone()));
+
+        rtc.write(RtcRegister::Minutes, 30);
+        rtc.write(RtcRegister::DayHigh, 0x40);
+        clock.advance(100);
+        assert_eq!(latched(&mut rtc), [0, 30, 0, 0, 0x40]);
+
+        rtc.write(RtcRegister::DayHigh, 0x00);
+        clock.advance(100);
+        assert_eq!(latched(&mut rtc), [40, 31, 0, 0, 0x00]);
+    }
+
+    #[test]
+    fn test_out_of_range_counters_wrap_without_carrying() {
+        let clock = ManualClock::default();
+        let mut rtc = Rtc::new(Box::new(clock.clone()));
+
+        rtc.write(RtcRegister::Seconds, 62);
+        clock.advance(1);
+        assert_eq!(latched(&mut rtc), [63, 0, 0, 0, 0x00]);
+        clock.advance(1);
+        assert_eq!(latched(&mut rtc), [0, 0, 0, 0, 0x00]);
+
+        // a valid counter below still carries into it
+        rtc.write(RtcRegister::Minutes, 63);
+        rtc.write(RtcRegister::Seconds, 59);
+        clock.advance(1);
+        assert_eq!(latched(&mut rtc), [0, 0, 0, 0, 0x00]);
+
+        rtc.write(RtcRegister::Hours, 31);
+        clock.advance(60 * 60);
+        assert_eq!(latched(&mut rtc), [0, 0, 0, 0, 0x00]);
+
+        // and once everything's back in range, it counts normally
+        rtc.write(RtcRegister::Hours, 30);
+        clock.advance(3 * 60 * 60 + 1);
+        assert_eq!(latched(&mut rtc), [1, 0, 1, 0, 0x00]);
+    }
+}
@@ -0,0 +1,137 @@
+//! Working out what a cartridge saves, when its header can't be trusted.
+//!
+//! Some headers give a cartridge type with RAM but a RAM size of 0, or get
+//! the battery wrong. Going by the header alone, those games lose their
+//! saves. [`SaveType::detect`] fills in what the mapper is known to have,
+//! and [`SaveOverrides`] covers the games it can't work out.
+
+use std::collections::HashMap;
+
+use super::{
+    header::{CartridgeHeader, MapperKind},
+    mbc::RAM_BANK_SIZE,
+};
+
+/// MBC2's built-in RAM, 512 half-bytes.
+const MBC2_RAM_SIZE: usize = 0x200;
+/// MBC7's EEPROM.
+const MBC7_EEPROM_SIZE: usize = 0x100;
+
+/// What a cartridge keeps its save data in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SaveType {
+    /// How much RAM (or EEPROM) the cartridge has, in bytes.
+    pub ram_size: usize,
+    /// Whether a battery keeps the RAM (and any clock) going with the
+    /// console off, so it needs a save file.
+    pub battery: bool,
+}
+
+impl SaveType {
+    /// What the cartridge most likely has: what the header says, except
+    /// that a cartridge type with RAM gets RAM even when the size says
+    /// there's none. The mapper decides how much, as much as it can bank
+    /// in, so none of it is lost.
+    pub fn detect(header: &CartridgeHeader) -> Self {
+        let kind = header.cartridge_type;
+        let stated = header.ram_size.unwrap_or(0);
+
+        let ram_size = match kind.mapper {
+            MapperKind::Mbc2 => MBC2_RAM_SIZE,
+            MapperKind::Mbc7 => MBC7_EEPROM_SIZE,
+            _ if !kind.ram || stated > 0 => stated,
+            // no banking, so just the one bank
+            MapperKind::RomOnly => RAM_BANK_SIZE,
+            _ => 4 * RAM_BANK_SIZE,
+        };
+
+        SaveType {
+            ram_size,
+            battery: kind.battery,
+        }
+    }
+}
+
+/// Save types for particular games, used instead of detecting them.
+///
+/// Games are told apart by their title and global checksum, so an
+/// override doesn't also catch other revisions.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SaveOverrides {
+    games: HashMap<(String, u16), SaveType>,
+}
+
+impl SaveOverrides {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use `save_type` for the game with this title and global checksum.
+    pub fn insert(&mut self, title: impl Into<String>, global_checksum: u16, save_type: SaveType) {
+        self.games
+            .insert((title.into(), global_checksum), save_type);
+    }
+
+    /// The save type for a cartridge: its override if it has one, and
+    /// otherwise whatever [`SaveType::detect`] makes of it.
+    pub fn save_type(&self, header: &CartridgeHeader) -> SaveType {
+        self.games
+            .get(&(header.title.clone(), header.global_checksum))
+            .copied()
+            .unwrap_or_else(|| SaveType::detect(header))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::ROM_BANK_SIZE;
+
+    fn header(cartridge_type: u8, ram_size: u8) -> CartridgeHeader {
+        let mut rom = vec![0; 2 * ROM_BANK_SIZE];
+        rom[0x0134..0x0138].copy_from_slice(b"GAME");
+        rom[0x0147] = cartridge_type;
+        rom[0x0149] = ram_size;
+        rom[0x014E..0x0150].copy_from_slice(&[0x12, 0x34]);
+        CartridgeHeader::parse(&rom).unwrap()
+    }
+
+    #[test]
+    fn test_detect() {
+        let detect = |cartridge_type, ram_size| {
+            let save_type = SaveType::detect(&header(cartridge_type, ram_size));
+            (save_type.ram_size, save_type.battery)
+        };
+
+        // honest headers
+        assert_eq!(detect(0x03, 0x03), (0x8000, true));
+        assert_eq!(detect(0x01, 0x00), (0, false));
+        assert_eq!(detect(0x0F, 0x00), (0, true)); // MBC3+TIMER+BATTERY
+        assert_eq!(detect(0x06, 0x00), (0x200, true)); // MBC2+BATTERY
+
+        // battery RAM, but a RAM size of 0
+        assert_eq!(detect(0x03, 0x00), (0x8000, true));
+        assert_eq!(detect(0x1B, 0x00), (0x8000, true));
+        assert_eq!(detect(0x09, 0x00), (0x2000, true));
+    }
+
+    #[test]
+    fn test_overrides() {
+        let mut overrides = SaveOverrides::new();
+        let save_type = SaveType {
+            ram_size: 0x2000,
+            battery: true,
+        };
+        overrides.insert("GAME", 0x1234, save_type);
+
+        // claims RAM without a battery
+        assert_eq!(overrides.save_type(&header(0x02, 0x02)), save_type);
+        assert_eq!(
+            SaveOverrides::new().save_type(&header(0x02, 0x02)),
+            SaveType {
+                ram_size: 0x2000,
+                battery: false
+            }
+        );
+    }
+}
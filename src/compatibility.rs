@@ -0,0 +1,90 @@
+//! What to do when a program uses hardware that isn't emulated.
+//!
+//! Carrying on with a safe default keeps more games running, at the risk of
+//! them misbehaving later; stopping points straight at what's missing. A
+//! [`Compatibility`] policy on the [`MMU`](crate::mmu::MMU) picks one or
+//! the other for each kind of missing hardware.
+
+use std::fmt;
+
+use crate::cartridge::header::MapperKind;
+
+/// Hardware a program used that isn't emulated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unimplemented {
+    /// An address in 0xFF00-0xFF7F with no register behind it.
+    IoRegister(u16),
+    /// A cartridge mapper, which was swapped for an MBC5.
+    Mapper(MapperKind),
+}
+
+impl fmt::Display for Unimplemented {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::IoRegister(addr) => write!(f, "unmapped I/O register {:04X}", addr),
+            Self::Mapper(mapper) => write!(f, "unsupported {:?} mapper", mapper),
+        }
+    }
+}
+
+/// What to do about one kind of [`Unimplemented`] hardware.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnUnimplemented {
+    /// Note it (see [`MMU::unimplemented`](crate::mmu::MMU::unimplemented))
+    /// and carry on with a safe default.
+    Continue,
+    /// Stop with an error: [`RomError::UnsupportedMapper`] when loading,
+    /// or [`ExecutionError::Unimplemented`] from [`SM83::run`].
+    ///
+    /// [`RomError::UnsupportedMapper`]: crate::cartridge::RomError::UnsupportedMapper
+    /// [`ExecutionError::Unimplemented`]: crate::cpu::error::ExecutionError::Unimplemented
+    /// [`SM83::run`]: crate::cpu::sm83::SM83::run
+    Stop,
+}
+
+/// What to do about each kind of hardware that isn't emulated.
+///
+/// The default carries on past unmapped I/O registers, which read 0xFF and
+/// ignore writes like on hardware, but refuses cartridges with an
+/// unsupported mapper.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Compatibility {
+    pub io_registers: OnUnimplemented,
+    /// Cartridges with a mapper that isn't emulated are run as MBC5s, the
+    /// most capable mapper that is, when continuing.
+    pub mappers: OnUnimplemented,
+}
+
+impl Compatibility {
+    /// Carry on past everything.
+    pub fn lenient() -> Self {
+        Compatibility {
+            io_registers: OnUnimplemented::Continue,
+            mappers: OnUnimplemented::Continue,
+        }
+    }
+
+    /// Stop at everything.
+    pub fn strict() -> Self {
+        Compatibility {
+            io_registers: OnUnimplemented::Stop,
+            mappers: OnUnimplemented::Stop,
+        }
+    }
+
+    pub(crate) fn on(&self, hardware: Unimplemented) -> OnUnimplemented {
+        match hardware {
+            Unimplemented::IoRegister(_) => self.io_registers,
+            Unimplemented::Mapper(_) => self.mappers,
+        }
+    }
+}
+
+impl Default for Compatibility {
+    fn default() -> Self {
+        Compatibility {
+            io_registers: OnUnimplemented::Continue,
+            mappers: OnUnimplemented::Stop,
+        }
+    }
+}
@@ -0,0 +1,39 @@
+use std::{error::Error, fmt};
+
+use crate::{compatibility::Unimplemented, watchdog::ProbableHang};
+
+/// Why the CPU couldn't carry on executing the program.
+#[derive(Debug, Clone)]
+pub enum ExecutionError {
+    /// One of the unused opcodes was executed, locking up the CPU.
+    UnknownOpcode { pc: u16, byte: u8 },
+    /// The instruction at `pc` couldn't access memory at `addr`.
+    MemoryFault { pc: u16, addr: u16 },
+    /// The watchdog decided the program is stuck in a loop.
+    Hang(ProbableHang),
+    /// The instruction at `pc` used hardware that isn't emulated, and the
+    /// [`Compatibility`](crate::compatibility::Compatibility) policy says to
+    /// stop.
+    Unimplemented { pc: u16, hardware: Unimplemented },
+    /// The program ran an `LD B,B` at `pc` while source breakpoints were on.
+    /// Execution can carry on from the next instruction.
+    Breakpoint { pc: u16 },
+}
+
+impl fmt::Display for ExecutionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownOpcode { pc, byte } => {
+                write!(f, "unknown opcode {:02X} at {:04X}", byte, pc)
+            }
+            Self::MemoryFault { pc, addr } => {
+                write!(f, "couldn't access {:04X}, at {:04X}", addr, pc)
+            }
+            Self::Hang(hang) => write!(f, "{}", hang),
+            Self::Unimplemented { pc, hardware } => write!(f, "{}, at {:04X}", hardware, pc),
+            Self::Breakpoint { pc } => write!(f, "breakpoint at {:04X}", pc),
+        }
+    }
+}
+
+impl Error for ExecutionError {}
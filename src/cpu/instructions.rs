@@ -0,0 +1,674 @@
+//! Decoding SM83 machine code into [`Instruction`]s.
+//!
+//! Decoding is kept apart from execution so that the same decoder can back
+//! the interpreter, a disassembler, and a debugger.
+
+use std::fmt;
+
+/// An 8-bit register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    A,
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+}
+
+/// A 16-bit register, or pair of 8-bit registers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegisterPair {
+    AF,
+    BC,
+    DE,
+    HL,
+    SP,
+}
+
+/// A flag condition for a conditional branch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Condition {
+    NotZero,
+    Zero,
+    NotCarry,
+    Carry,
+}
+
+/// The source or destination of an 8-bit load or arithmetic operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    Register(Register),
+    /// The byte at the address in BC, DE or HL.
+    Indirect(RegisterPair),
+    /// The byte at HL, incrementing HL afterwards (HL+).
+    IndirectIncrement,
+    /// The byte at HL, decrementing HL afterwards (HL-).
+    IndirectDecrement,
+    Immediate(u8),
+    /// The byte at an absolute address (a16).
+    Address(u16),
+    /// The byte at 0xFF00 plus an immediate offset (a8), as used by LDH.
+    HighAddress(u8),
+    /// The byte at 0xFF00 plus C.
+    HighC,
+}
+
+impl Operand {
+    /// The number of bytes the operand adds to an instruction.
+    fn length(&self) -> u16 {
+        match self {
+            Operand::Immediate(_) | Operand::HighAddress(_) => 1,
+            Operand::Address(_) => 2,
+            _ => 0,
+        }
+    }
+
+    /// The number of M-cycles spent fetching and accessing the operand.
+    fn cycles(&self) -> u8 {
+        match self {
+            Operand::Register(_) => 0,
+            Operand::Address(_) => 3,
+            Operand::HighAddress(_) => 2,
+            _ => 1,
+        }
+    }
+}
+
+/// A single decoded SM83 instruction, with its operands.
+///
+/// Jumps, calls and returns carry an optional [`Condition`], which is `None`
+/// for the unconditional forms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    Nop,
+    Stop,
+    Halt,
+    DisableInterrupts,
+    EnableInterrupts,
+    /// One of the unused opcodes, which hang the CPU.
+    Illegal(u8),
+
+    /// LD, with the destination first.
+    Load(Operand, Operand),
+    /// LD rr,d16
+    LoadWord(RegisterPair, u16),
+    /// LD (a16),SP
+    StoreSp(u16),
+    /// LD SP,HL
+    LoadSpFromHl,
+    /// LD HL,SP+e
+    LoadHlFromSpOffset(i8),
+    /// ADD SP,e
+    AddSpOffset(i8),
+    Push(RegisterPair),
+    Pop(RegisterPair),
+
+    Increment(Operand),
+    Decrement(Operand),
+    IncrementWord(RegisterPair),
+    DecrementWord(RegisterPair),
+    /// ADD HL,rr
+    AddToHl(RegisterPair),
+
+    Add(Operand),
+    AddWithCarry(Operand),
+    Subtract(Operand),
+    SubtractWithCarry(Operand),
+    And(Operand),
+    Xor(Operand),
+    Or(Operand),
+    Compare(Operand),
+
+    RotateALeftWithCarry,
+    RotateARightWithCarry,
+    RotateALeftThroughCarry,
+    RotateARightThroughCarry,
+    DecimalAdjustA,
+    ComplementA,
+    SetCarryFlag,
+    ComplementCarryFlag,
+
+    Jump(Option<Condition>, u16),
+    JumpToHl,
+    JumpRelative(Option<Condition>, i8),
+    Call(Option<Condition>, u16),
+    Return(Option<Condition>),
+    ReturnFromInterrupt,
+    /// RST, with the address of the vector.
+    Restart(u16),
+
+    // The CB-prefixed instructions, which all work on an 8-bit operand.
+    /// RLC
+    RotateLeftWithCarry(Operand),
+    /// RRC
+    RotateRightWithCarry(Operand),
+    /// RL
+    RotateLeftThroughCarry(Operand),
+    /// RR
+    RotateRightThroughCarry(Operand),
+    /// SLA
+    ShiftLeftArithmetic(Operand),
+    /// SRA, which keeps the sign bit.
+    ShiftRightArithmetic(Operand),
+    /// SWAP, exchanging the high and low nibbles.
+    Swap(Operand),
+    /// SRL
+    ShiftRightLogical(Operand),
+    /// BIT, with the bit number.
+    TestBit(u8, Operand),
+    /// RES, with the bit number.
+    ResetBit(u8, Operand),
+    /// SET, with the bit number.
+    SetBit(u8, Operand),
+}
+
+impl Instruction {
+    /// The length of the instruction in bytes, including the opcode.
+    pub fn length(&self) -> u16 {
+        match self {
+            Instruction::Load(destination, source) => 1 + destination.length() + source.length(),
+            Instruction::Add(operand)
+            | Instruction::AddWithCarry(operand)
+            | Instruction::Subtract(operand)
+            | Instruction::SubtractWithCarry(operand)
+            | Instruction::And(operand)
+            | Instruction::Xor(operand)
+            | Instruction::Or(operand)
+            | Instruction::Compare(operand) => 1 + operand.length(),
+            Instruction::Stop
+            | Instruction::LoadHlFromSpOffset(_)
+            | Instruction::AddSpOffset(_)
+            | Instruction::JumpRelative(..) => 2,
+            _ if self.is_prefixed() => 2,
+            Instruction::LoadWord(..)
+            | Instruction::StoreSp(_)
+            | Instruction::Jump(..)
+            | Instruction::Call(..) => 3,
+            _ => 1,
+        }
+    }
+
+    /// The number of M-cycles the instruction takes.
+    ///
+    /// For conditional branches, this is the cost when the branch isn't
+    /// taken; the executor adds the difference when it is.
+    #[inline(always)]
+    pub fn cycles(&self) -> u8 {
+        match self {
+            Instruction::Load(destination, source) => 1 + destination.cycles() + source.cycles(),
+            // read-modify-write, so memory is accessed twice
+            Instruction::Increment(operand) | Instruction::Decrement(operand) => {
+                1 + operand.cycles() * 2
+            }
+            Instruction::Add(operand)
+            | Instruction::AddWithCarry(operand)
+            | Instruction::Subtract(operand)
+            | Instruction::SubtractWithCarry(operand)
+            | Instruction::And(operand)
+            | Instruction::Xor(operand)
+            | Instruction::Or(operand)
+            | Instruction::Compare(operand) => 1 + operand.cycles(),
+            Instruction::IncrementWord(_)
+            | Instruction::DecrementWord(_)
+            | Instruction::AddToHl(_)
+            | Instruction::LoadSpFromHl
+            | Instruction::JumpRelative(Some(_), _)
+            | Instruction::Return(Some(_)) => 2,
+            Instruction::LoadWord(..)
+            | Instruction::LoadHlFromSpOffset(_)
+            | Instruction::Pop(_)
+            | Instruction::Jump(Some(_), _)
+            | Instruction::JumpRelative(None, _)
+            | Instruction::Call(Some(_), _) => 3,
+            Instruction::AddSpOffset(_)
+            | Instruction::Push(_)
+            | Instruction::Jump(None, _)
+            | Instruction::Return(None)
+            | Instruction::ReturnFromInterrupt
+            | Instruction::Restart(_) => 4,
+            Instruction::StoreSp(_) => 5,
+            Instruction::Call(None, _) => 6,
+            // BIT only reads its operand
+            Instruction::TestBit(_, operand) => 2 + operand.cycles(),
+            Instruction::RotateLeftWithCarry(operand)
+            | Instruction::RotateRightWithCarry(operand)
+            | Instruction::RotateLeftThroughCarry(operand)
+            | Instruction::RotateRightThroughCarry(operand)
+            | Instruction::ShiftLeftArithmetic(operand)
+            | Instruction::ShiftRightArithmetic(operand)
+            | Instruction::Swap(operand)
+            | Instruction::ShiftRightLogical(operand)
+            | Instruction::ResetBit(_, operand)
+            | Instruction::SetBit(_, operand) => 2 + operand.cycles() * 2,
+            _ => 1,
+        }
+    }
+
+    /// Whether this is one of the instructions behind the 0xCB prefix.
+    pub fn is_prefixed(&self) -> bool {
+        matches!(
+            self,
+            Instruction::RotateLeftWithCarry(_)
+                | Instruction::RotateRightWithCarry(_)
+                | Instruction::RotateLeftThroughCarry(_)
+                | Instruction::RotateRightThroughCarry(_)
+                | Instruction::ShiftLeftArithmetic(_)
+                | Instruction::ShiftRightArithmetic(_)
+                | Instruction::Swap(_)
+                | Instruction::ShiftRightLogical(_)
+                | Instruction::TestBit(..)
+                | Instruction::ResetBit(..)
+                | Instruction::SetBit(..)
+        )
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Register::A => "A",
+            Register::B => "B",
+            Register::C => "C",
+            Register::D => "D",
+            Register::E => "E",
+            Register::H => "H",
+            Register::L => "L",
+        };
+
+        f.write_str(name)
+    }
+}
+
+impl fmt::Display for RegisterPair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            RegisterPair::AF => "AF",
+            RegisterPair::BC => "BC",
+            RegisterPair::DE => "DE",
+            RegisterPair::HL => "HL",
+            RegisterPair::SP => "SP",
+        };
+
+        f.write_str(name)
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Condition::NotZero => "NZ",
+            Condition::Zero => "Z",
+            Condition::NotCarry => "NC",
+            Condition::Carry => "C",
+        };
+
+        f.write_str(name)
+    }
+}
+
+/// Operands are written in hex, without a prefix, and memory operands are
+/// wrapped in parentheses: `A`, `(HL+)`, `3F`, `(FF44)`.
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Register(register) => write!(f, "{}", register),
+            Operand::Indirect(pair) => write!(f, "({})", pair),
+            Operand::IndirectIncrement => write!(f, "(HL+)"),
+            Operand::IndirectDecrement => write!(f, "(HL-)"),
+            Operand::Immediate(value) => write!(f, "{:02X}", value),
+            Operand::Address(addr) => write!(f, "({:04X})", addr),
+            Operand::HighAddress(offset) => write!(f, "(FF{:02X})", offset),
+            Operand::HighC => write!(f, "(FF00+C)"),
+        }
+    }
+}
+
+/// The condition of a branch, if it has one, followed by a comma.
+fn condition_prefix(condition: &Option<Condition>) -> String {
+    condition
+        .map(|condition| format!("{},", condition))
+        .unwrap_or_default()
+}
+
+/// Instructions are written with the usual SM83 mnemonics, as in
+/// `LD A,(FF44)` or `JR NZ,-5`. Relative offsets are signed decimal, since
+/// the target depends on where the instruction is.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Instruction::*;
+
+        match self {
+            Nop => write!(f, "NOP"),
+            Stop => write!(f, "STOP"),
+            Halt => write!(f, "HALT"),
+            DisableInterrupts => write!(f, "DI"),
+            EnableInterrupts => write!(f, "EI"),
+            Illegal(opcode) => write!(f, "ILLEGAL {:02X}", opcode),
+
+            Load(destination, source) => write!(f, "LD {},{}", destination, source),
+            LoadWord(pair, value) => write!(f, "LD {},{:04X}", pair, value),
+            StoreSp(addr) => write!(f, "LD ({:04X}),SP", addr),
+            LoadSpFromHl => write!(f, "LD SP,HL"),
+            LoadHlFromSpOffset(offset) => write!(f, "LD HL,SP{:+}", offset),
+            AddSpOffset(offset) => write!(f, "ADD SP,{}", offset),
+            Push(pair) => write!(f, "PUSH {}", pair),
+            Pop(pair) => write!(f, "POP {}", pair),
+
+            Increment(operand) => write!(f, "INC {}", operand),
+            Decrement(operand) => write!(f, "DEC {}", operand),
+            IncrementWord(pair) => write!(f, "INC {}", pair),
+            DecrementWord(pair) => write!(f, "DEC {}", pair),
+            AddToHl(pair) => write!(f, "ADD HL,{}", pair),
+
+            Add(operand) => write!(f, "ADD A,{}", operand),
+            AddWithCarry(operand) => write!(f, "ADC A,{}", operand),
+            Subtract(operand) => write!(f, "SUB {}", operand),
+            SubtractWithCarry(operand) => write!(f, "SBC A,{}", operand),
+            And(operand) => write!(f, "AND {}", operand),
+            Xor(operand) => write!(f, "XOR {}", operand),
+            Or(operand) => write!(f, "OR {}", operand),
+            Compare(operand) => write!(f, "CP {}", operand),
+
+            RotateALeftWithCarry => write!(f, "RLCA"),
+            RotateARightWithCarry => write!(f, "RRCA"),
+            RotateALeftThroughCarry => write!(f, "RLA"),
+            RotateARightThroughCarry => write!(f, "RRA"),
+            DecimalAdjustA => write!(f, "DAA"),
+            ComplementA => write!(f, "CPL"),
+            SetCarryFlag => write!(f, "SCF"),
+            ComplementCarryFlag => write!(f, "CCF"),
+
+            Jump(condition, target) => {
+                write!(f, "JP {}{:04X}", condition_prefix(condition), target)
+            }
+            JumpToHl => write!(f, "JP HL"),
+            JumpRelative(condition, offset) => {
+                write!(f, "JR {}{}", condition_prefix(condition), offset)
+            }
+            Call(condition, target) => {
+                write!(f, "CALL {}{:04X}", condition_prefix(condition), target)
+            }
+            Return(Some(condition)) => write!(f, "RET {}", condition),
+            Return(None) => write!(f, "RET"),
+            ReturnFromInterrupt => write!(f, "RETI"),
+            Restart(vector) => write!(f, "RST {:02X}", vector),
+
+            RotateLeftWithCarry(operand) => write!(f, "RLC {}", operand),
+            RotateRightWithCarry(operand) => write!(f, "RRC {}", operand),
+            RotateLeftThroughCarry(operand) => write!(f, "RL {}", operand),
+            RotateRightThroughCarry(operand) => write!(f, "RR {}", operand),
+            ShiftLeftArithmetic(operand) => write!(f, "SLA {}", operand),
+            ShiftRightArithmetic(operand) => write!(f, "SRA {}", operand),
+            Swap(operand) => write!(f, "SWAP {}", operand),
+            ShiftRightLogical(operand) => write!(f, "SRL {}", operand),
+            TestBit(bit, operand) => write!(f, "BIT {},{}", bit, operand),
+            ResetBit(bit, operand) => write!(f, "RES {},{}", bit, operand),
+            SetBit(bit, operand) => write!(f, "SET {},{}", bit, operand),
+        }
+    }
+}
+
+/// The length in bytes of the instruction starting with `opcode`.
+///
+/// This is known from the opcode alone, so it can be used to find out how
+/// many immediate bytes to fetch before decoding. The byte after a 0xCB
+/// prefix is fetched like an immediate.
+pub fn length(opcode: u8) -> u16 {
+    match opcode {
+        0x01 | 0x11 | 0x21 | 0x31 | 0x08 | 0xC2 | 0xC3 | 0xC4 | 0xCA | 0xCC | 0xCD | 0xD2
+        | 0xD4 | 0xDA | 0xDC | 0xEA | 0xFA => 3,
+        0x10 | 0x18 | 0x20 | 0x28 | 0x30 | 0x38 | 0xCB | 0xE0 | 0xE8 | 0xF0 | 0xF8 => 2,
+        // LD r,d8 and ALU A,d8
+        _ if opcode & 0b1100_0111 == 0b0000_0110 || opcode & 0b1100_0111 == 0b1100_0110 => 2,
+        _ => 1,
+    }
+}
+
+/// Decode an instruction from its opcode and the two bytes following it.
+///
+/// Bytes the instruction doesn't use are ignored. 16-bit immediates are
+/// little-endian, so `low` comes first in memory. For the 0xCB prefix,
+/// `low` is the second opcode byte (see [`decode_prefixed`]).
+#[inline(always)]
+pub fn decode(opcode: u8, low: u8, high: u8) -> Instruction {
+    use Instruction::*;
+
+    let word = u16::from_le_bytes([low, high]);
+    let offset = low as i8;
+    // the opcode table is laid out in octal, as xx yyy zzz
+    let y = (opcode >> 3) & 0b111;
+    let z = opcode & 0b111;
+
+    match opcode {
+        0x00 => Nop,
+        0x10 => Stop,
+        0x76 => Halt,
+        0xF3 => DisableInterrupts,
+        0xFB => EnableInterrupts,
+        0xCB => decode_prefixed(low),
+        0xD3 | 0xDB | 0xDD | 0xE3 | 0xE4 | 0xEB | 0xEC | 0xED | 0xF4 | 0xFC | 0xFD => {
+            Illegal(opcode)
+        }
+
+        0x01 | 0x11 | 0x21 | 0x31 => LoadWord(pair(y >> 1), word),
+        0x02 => Load(Operand::Indirect(RegisterPair::BC), a()),
+        0x12 => Load(Operand::Indirect(RegisterPair::DE), a()),
+        0x22 => Load(Operand::IndirectIncrement, a()),
+        0x32 => Load(Operand::IndirectDecrement, a()),
+        0x0A => Load(a(), Operand::Indirect(RegisterPair::BC)),
+        0x1A => Load(a(), Operand::Indirect(RegisterPair::DE)),
+        0x2A => Load(a(), Operand::IndirectIncrement),
+        0x3A => Load(a(), Operand::IndirectDecrement),
+        0x03 | 0x13 | 0x23 | 0x33 => IncrementWord(pair(y >> 1)),
+        0x0B | 0x1B | 0x2B | 0x3B => DecrementWord(pair(y >> 1)),
+        0x09 | 0x19 | 0x29 | 0x39 => AddToHl(pair(y >> 1)),
+        0x04 | 0x0C | 0x14 | 0x1C | 0x24 | 0x2C | 0x34 | 0x3C => Increment(operand(y)),
+        0x05 | 0x0D | 0x15 | 0x1D | 0x25 | 0x2D | 0x35 | 0x3D => Decrement(operand(y)),
+        0x06 | 0x0E | 0x16 | 0x1E | 0x26 | 0x2E | 0x36 | 0x3E => {
+            Load(operand(y), Operand::Immediate(low))
+        }
+
+        0x07 => RotateALeftWithCarry,
+        0x0F => RotateARightWithCarry,
+        0x17 => RotateALeftThroughCarry,
+        0x1F => RotateARightThroughCarry,
+        0x27 => DecimalAdjustA,
+        0x2F => ComplementA,
+        0x37 => SetCarryFlag,
+        0x3F => ComplementCarryFlag,
+
+        0x08 => StoreSp(word),
+        0x18 => JumpRelative(None, offset),
+        0x20 | 0x28 | 0x30 | 0x38 => JumpRelative(Some(condition(y - 4)), offset),
+
+        // LD r,r'
+        0x40..=0x7F => Load(operand(y), operand(z)),
+        // ALU A,r
+        0x80..=0xBF => alu(y, operand(z)),
+        // ALU A,d8
+        0xC6 | 0xCE | 0xD6 | 0xDE | 0xE6 | 0xEE | 0xF6 | 0xFE => alu(y, Operand::Immediate(low)),
+
+        0xC0 | 0xC8 | 0xD0 | 0xD8 => Return(Some(condition(y))),
+        0xC9 => Return(None),
+        0xD9 => ReturnFromInterrupt,
+        0xC2 | 0xCA | 0xD2 | 0xDA => Jump(Some(condition(y)), word),
+        0xC3 => Jump(None, word),
+        0xE9 => JumpToHl,
+        0xC4 | 0xCC | 0xD4 | 0xDC => Call(Some(condition(y)), word),
+        0xCD => Call(None, word),
+        0xC7 | 0xCF | 0xD7 | 0xDF | 0xE7 | 0xEF | 0xF7 | 0xFF => Restart(y as u16 * 8),
+
+        0xC1 | 0xD1 | 0xE1 => Pop(pair(y >> 1)),
+        0xF1 => Pop(RegisterPair::AF),
+        0xC5 | 0xD5 | 0xE5 => Push(pair(y >> 1)),
+        0xF5 => Push(RegisterPair::AF),
+
+        0xE0 => Load(Operand::HighAddress(low), a()),
+        0xF0 => Load(a(), Operand::HighAddress(low)),
+        0xE2 => Load(Operand::HighC, a()),
+        0xF2 => Load(a(), Operand::HighC),
+        0xEA => Load(Operand::Address(word), a()),
+        0xFA => Load(a(), Operand::Address(word)),
+
+        0xE8 => AddSpOffset(offset),
+        0xF8 => LoadHlFromSpOffset(offset),
+        0xF9 => LoadSpFromHl,
+    }
+}
+
+/// Decode the instruction for the byte following a 0xCB prefix.
+///
+/// The whole table is regular: the top two bits pick a group, the middle
+/// three the operation (or bit number) and the low three the operand.
+#[inline(always)]
+pub fn decode_prefixed(opcode: u8) -> Instruction {
+    use Instruction::*;
+
+    let y = (opcode >> 3) & 0b111;
+    let operand = operand(opcode & 0b111);
+
+    match opcode >> 6 {
+        0 => match y {
+            0 => RotateLeftWithCarry(operand),
+            1 => RotateRightWithCarry(operand),
+            2 => RotateLeftThroughCarry(operand),
+            3 => RotateRightThroughCarry(operand),
+            4 => ShiftLeftArithmetic(operand),
+            5 => ShiftRightArithmetic(operand),
+            6 => Swap(operand),
+            7 => ShiftRightLogical(operand),
+            _ => unreachable!(),
+        },
+        1 => TestBit(y, operand),
+        2 => ResetBit(y, operand),
+        3 => SetBit(y, operand),
+        _ => unreachable!(),
+    }
+}
+
+fn a() -> Operand {
+    Operand::Register(Register::A)
+}
+
+/// B, C, D, E, H, L, (HL), A
+fn operand(index: u8) -> Operand {
+    match index {
+        0 => Operand::Register(Register::B),
+        1 => Operand::Register(Register::C),
+        2 => Operand::Register(Register::D),
+        3 => Operand::Register(Register::E),
+        4 => Operand::Register(Register::H),
+        5 => Operand::Register(Register::L),
+        6 => Operand::Indirect(RegisterPair::HL),
+        7 => Operand::Register(Register::A),
+        _ => unreachable!(),
+    }
+}
+
+/// BC, DE, HL, SP
+fn pair(index: u8) -> RegisterPair {
+    match index {
+        0 => RegisterPair::BC,
+        1 => RegisterPair::DE,
+        2 => RegisterPair::HL,
+        3 => RegisterPair::SP,
+        _ => unreachable!(),
+    }
+}
+
+/// NZ, Z, NC, C
+fn condition(index: u8) -> Condition {
+    match index {
+        0 => Condition::NotZero,
+        1 => Condition::Zero,
+        2 => Condition::NotCarry,
+        3 => Condition::Carry,
+        _ => unreachable!(),
+    }
+}
+
+/// ADD, ADC, SUB, SBC, AND, XOR, OR, CP
+fn alu(index: u8, operand: Operand) -> Instruction {
+    match index {
+        0 => Instruction::Add(operand),
+        1 => Instruction::AddWithCarry(operand),
+        2 => Instruction::Subtract(operand),
+        3 => Instruction::SubtractWithCarry(operand),
+        4 => Instruction::And(operand),
+        5 => Instruction::Xor(operand),
+        6 => Instruction::Or(operand),
+        7 => Instruction::Compare(operand),
+        _ => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_operands() {
+        assert_eq!(
+            decode(0xF0, 0x44, 0x00),
+            Instruction::Load(Operand::Register(Register::A), Operand::HighAddress(0x44))
+        );
+        assert_eq!(
+            decode(0x31, 0xFE, 0xFF),
+            Instruction::LoadWord(RegisterPair::SP, 0xFFFE)
+        );
+        assert_eq!(
+            decode(0x20, 0xFE, 0x00),
+            Instruction::JumpRelative(Some(Condition::NotZero), -2)
+        );
+        assert_eq!(
+            decode(0x36, 0x99, 0x00),
+            Instruction::Load(
+                Operand::Indirect(RegisterPair::HL),
+                Operand::Immediate(0x99)
+            )
+        );
+        assert_eq!(decode(0xEF, 0x00, 0x00), Instruction::Restart(0x28));
+        assert_eq!(decode(0xFD, 0x00, 0x00), Instruction::Illegal(0xFD));
+    }
+
+    #[test]
+    fn test_decode_prefixed() {
+        let hl = Operand::Indirect(RegisterPair::HL);
+        let a = Operand::Register(Register::A);
+
+        assert_eq!(decode(0xCB, 0x37, 0x00), Instruction::Swap(a));
+        assert_eq!(decode_prefixed(0x06), Instruction::RotateLeftWithCarry(hl));
+        assert_eq!(decode_prefixed(0x3F), Instruction::ShiftRightLogical(a));
+        assert_eq!(decode_prefixed(0x7E), Instruction::TestBit(7, hl));
+        assert_eq!(decode_prefixed(0x87), Instruction::ResetBit(0, a));
+        assert_eq!(decode_prefixed(0xF6), Instruction::SetBit(6, hl));
+
+        assert_eq!(decode_prefixed(0x7E).to_string(), "BIT 7,(HL)");
+        assert_eq!(decode_prefixed(0x1A).to_string(), "RR D");
+        for opcode in 0..=255u8 {
+            assert_eq!(decode_prefixed(opcode).length(), 2, "CB {opcode:02X}");
+        }
+    }
+
+    #[test]
+    fn test_length_matches_decoded_length() {
+        for opcode in 0..=255u8 {
+            assert_eq!(
+                length(opcode),
+                decode(opcode, 0x00, 0x00).length(),
+                "{opcode:02X}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_decode_ignores_unused_bytes() {
+        for opcode in 0..=255u8 {
+            if length(opcode) == 1 {
+                assert_eq!(decode(opcode, 0x12, 0x34), decode(opcode, 0x00, 0x00));
+            }
+        }
+    }
+}
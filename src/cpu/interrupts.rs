@@ -0,0 +1,166 @@
+use crate::bus::Bus;
+
+/// Address of the interrupt flag register (IF).
+pub const INTERRUPT_FLAG: u16 = 0xFF0F;
+/// Address of the interrupt enable register (IE).
+pub const INTERRUPT_ENABLE: u16 = 0xFFFF;
+
+/// The interrupt sources, in priority order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interrupt {
+    VBlank,
+    Stat,
+    Timer,
+    Serial,
+    Joypad,
+}
+
+impl Interrupt {
+    /// Every interrupt, from highest to lowest priority.
+    pub const ALL: [Interrupt; 5] = [
+        Interrupt::VBlank,
+        Interrupt::Stat,
+        Interrupt::Timer,
+        Interrupt::Serial,
+        Interrupt::Joypad,
+    ];
+
+    /// The interrupt's bit in IF and IE.
+    pub fn mask(&self) -> u8 {
+        1 << *self as u8
+    }
+
+    /// The address the CPU jumps to when servicing the interrupt.
+    pub fn vector(&self) -> u16 {
+        0x0040 + 8 * *self as u16
+    }
+
+    /// The highest priority interrupt that is both enabled and requested.
+    ///
+    /// The CPU checks this between instructions without going through the
+    /// bus, so IE and IF are peeked and it doesn't show up as an access.
+    pub fn pending(bus: &impl Bus) -> Option<Self> {
+        let enabled = bus.peek_byte(INTERRUPT_ENABLE).unwrap_or(0);
+        let requested = bus.peek_byte(INTERRUPT_FLAG).unwrap_or(0);
+
+        Self::ALL
+            .into_iter()
+            .find(|interrupt| enabled & requested & interrupt.mask() != 0)
+    }
+
+    /// Raise the interrupt's bit in IF.
+    pub fn request(&self, bus: &impl Bus) {
+        let requested = bus.peek_byte(INTERRUPT_FLAG).unwrap_or(0);
+        bus.poke_byte(INTERRUPT_FLAG, requested | self.mask());
+    }
+
+    /// Clear the interrupt's bit in IF, once it has been serviced.
+    pub fn acknowledge(&self, bus: &impl Bus) {
+        let requested = bus.peek_byte(INTERRUPT_FLAG).unwrap_or(0);
+        bus.poke_byte(INTERRUPT_FLAG, requested & !self.mask());
+    }
+}
+
+/// How long one interrupt source has waited to be serviced, in M-cycles:
+/// from its bit in IF being set, to the first instruction of its handler.
+///
+/// IF is checked between instructions, so an interrupt raised partway
+/// through one counts from the end of it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Latency {
+    /// How many times the interrupt was serviced.
+    pub count: u64,
+    pub min: u64,
+    pub max: u64,
+    pub total: u64,
+}
+
+impl Latency {
+    fn record(&mut self, cycles: u64) {
+        self.min = match self.count {
+            0 => cycles,
+            _ => self.min.min(cycles),
+        };
+        self.max = self.max.max(cycles);
+        self.total += cycles;
+        self.count += 1;
+    }
+
+    pub fn mean(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.total as f64 / self.count as f64)
+    }
+}
+
+/// Measures [`Latency`] for each interrupt source, from the CPU.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct LatencyTracker {
+    /// When each interrupt's IF bit was first seen set, in M-cycles.
+    raised: [Option<u64>; 5],
+    /// The interrupt being dispatched, and when it was raised.
+    dispatched: Option<(Interrupt, u64)>,
+    latencies: [Latency; 5],
+}
+
+impl LatencyTracker {
+    /// Note which interrupts are requested at `now`, between instructions.
+    pub fn observe(&mut self, bus: &impl Bus, now: u64) {
+        let requested = bus.peek_byte(INTERRUPT_FLAG).unwrap_or(0);
+
+        for interrupt in Interrupt::ALL {
+            let raised = &mut self.raised[interrupt as usize];
+            match requested & interrupt.mask() != 0 {
+                true => *raised = Some(raised.unwrap_or(now)),
+                // cleared by the program before it was serviced
+                false => *raised = None,
+            }
+        }
+    }
+
+    /// Note that the CPU has started dispatching `interrupt`, at `now`.
+    pub fn dispatch(&mut self, interrupt: Interrupt, now: u64) {
+        let raised = self.raised[interrupt as usize].take().unwrap_or(now);
+        self.dispatched = Some((interrupt, raised));
+    }
+
+    /// Note that the CPU is about to run an instruction, at `now`. If an
+    /// interrupt was just dispatched, this is its handler's first.
+    pub fn fetch(&mut self, now: u64) {
+        if let Some((interrupt, raised)) = self.dispatched.take() {
+            self.latencies[interrupt as usize].record(now - raised);
+        }
+    }
+
+    pub fn latency(&self, interrupt: Interrupt) -> Latency {
+        self.latencies[interrupt as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mmu::MMU;
+
+    #[test]
+    fn test_priority() {
+        let mmu = MMU::new();
+        assert_eq!(Interrupt::pending(&mmu), None);
+
+        mmu.write_byte(INTERRUPT_ENABLE, 0x1F);
+        Interrupt::Joypad.request(&mmu);
+        Interrupt::Timer.request(&mmu);
+        assert_eq!(Interrupt::pending(&mmu), Some(Interrupt::Timer));
+
+        Interrupt::Timer.acknowledge(&mmu);
+        assert_eq!(Interrupt::pending(&mmu), Some(Interrupt::Joypad));
+
+        // requested but not enabled
+        mmu.write_byte(INTERRUPT_ENABLE, 0x01);
+        assert_eq!(Interrupt::pending(&mmu), None);
+    }
+
+    #[test]
+    fn test_vectors() {
+        let vectors: Vec<u16> = Interrupt::ALL.iter().map(Interrupt::vector).collect();
+        assert_eq!(vectors, [0x40, 0x48, 0x50, 0x58, 0x60]);
+    }
+}
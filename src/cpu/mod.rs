@@ -1,3 +1,10 @@
-pub mod opcodes;
+pub mod error;
+pub mod instructions;
+pub mod interrupts;
+pub(crate) mod opcodes;
+#[cfg(test)]
+mod reference;
 pub mod registers;
+#[cfg(test)]
+mod single_step;
 pub mod sm83;
@@ -1,528 +1,622 @@
 use crate::{
-    cpu::{registers::Flag, sm83::SM83},
-    mmu::MMU,
+    bus::Bus,
+    cpu::{
+        instructions::{
+            decode, decode_prefixed, Condition, Instruction, Operand, Register, RegisterPair,
+        },
+        registers::Flag,
+        sm83::SM83,
+    },
 };
 
-use lazy_static::lazy_static;
-use paste::paste;
-use std::collections::HashMap;
+/// A match on `$opcode` with an arm for every byte, each calling
+/// `$run::<OPCODE>` with `$args`.
+///
+/// This is how both dispatch tables get built, so there's no table of
+/// per-opcode entries to keep in sync: lengths and cycle counts come from
+/// the decoded [`Instruction`], and `test_instruction_timing` checks them
+/// against Blargg's tables.
+macro_rules! per_opcode {
+    ($opcode:expr, $run:ident $args:tt) => {
+        per_opcode!(@arms $opcode, $run, $args,
+            0x00 0x01 0x02 0x03 0x04 0x05 0x06 0x07 0x08 0x09 0x0A 0x0B 0x0C 0x0D 0x0E 0x0F
+            0x10 0x11 0x12 0x13 0x14 0x15 0x16 0x17 0x18 0x19 0x1A 0x1B 0x1C 0x1D 0x1E 0x1F
+            0x20 0x21 0x22 0x23 0x24 0x25 0x26 0x27 0x28 0x29 0x2A 0x2B 0x2C 0x2D 0x2E 0x2F
+            0x30 0x31 0x32 0x33 0x34 0x35 0x36 0x37 0x38 0x39 0x3A 0x3B 0x3C 0x3D 0x3E 0x3F
+            0x40 0x41 0x42 0x43 0x44 0x45 0x46 0x47 0x48 0x49 0x4A 0x4B 0x4C 0x4D 0x4E 0x4F
+            0x50 0x51 0x52 0x53 0x54 0x55 0x56 0x57 0x58 0x59 0x5A 0x5B 0x5C 0x5D 0x5E 0x5F
+            0x60 0x61 0x62 0x63 0x64 0x65 0x66 0x67 0x68 0x69 0x6A 0x6B 0x6C 0x6D 0x6E 0x6F
+            0x70 0x71 0x72 0x73 0x74 0x75 0x76 0x77 0x78 0x79 0x7A 0x7B 0x7C 0x7D 0x7E 0x7F
+            0x80 0x81 0x82 0x83 0x84 0x85 0x86 0x87 0x88 0x89 0x8A 0x8B 0x8C 0x8D 0x8E 0x8F
+            0x90 0x91 0x92 0x93 0x94 0x95 0x96 0x97 0x98 0x99 0x9A 0x9B 0x9C 0x9D 0x9E 0x9F
+            0xA0 0xA1 0xA2 0xA3 0xA4 0xA5 0xA6 0xA7 0xA8 0xA9 0xAA 0xAB 0xAC 0xAD 0xAE 0xAF
+            0xB0 0xB1 0xB2 0xB3 0xB4 0xB5 0xB6 0xB7 0xB8 0xB9 0xBA 0xBB 0xBC 0xBD 0xBE 0xBF
+            0xC0 0xC1 0xC2 0xC3 0xC4 0xC5 0xC6 0xC7 0xC8 0xC9 0xCA 0xCB 0xCC 0xCD 0xCE 0xCF
+            0xD0 0xD1 0xD2 0xD3 0xD4 0xD5 0xD6 0xD7 0xD8 0xD9 0xDA 0xDB 0xDC 0xDD 0xDE 0xDF
+            0xE0 0xE1 0xE2 0xE3 0xE4 0xE5 0xE6 0xE7 0xE8 0xE9 0xEA 0xEB 0xEC 0xED 0xEE 0xEF
+            0xF0 0xF1 0xF2 0xF3 0xF4 0xF5 0xF6 0xF7 0xF8 0xF9 0xFA 0xFB 0xFC 0xFD 0xFE 0xFF
+        )
+    };
+    (@arms $opcode:expr, $run:ident, $args:tt, $($value:literal)*) => {
+        match $opcode {
+            $($value => $run::<$value> $args,)*
+        }
+    };
+}
 
-/// An individual instruction for the SM83.
+/// Decode and execute an instruction, returning its base cycle count.
 ///
-/// The first element of the enum is the handler function for the operation,
-/// and the second element is the number of cycles the operation takes.
-#[derive(Debug, Clone, Copy)]
-pub enum Opcode<CPU> {
-    Unary(fn(&mut CPU, &MMU), u8),
-    Binary(fn(&mut CPU, &MMU, u8), u8),
-    Ternary(fn(&mut CPU, &MMU, u8, u8), u8),
+/// This is the same as [`execute`] on [`decode`]'s output, but with a copy
+/// of both for each opcode, so the compiler can fold the decoding away and
+/// skip matching on the [`Instruction`] at run time. `cargo bench --bench
+/// step` shows the difference.
+pub(crate) fn dispatch(cpu: &mut SM83, bus: &impl Bus, opcode: u8, low: u8, high: u8) -> u8 {
+    per_opcode!(opcode, run(cpu, bus, low, high))
 }
 
-impl<T> Opcode<T> {
-    pub fn cycle_count(&self) -> u8 {
-        match self {
-            Opcode::Unary(_, cycles) => *cycles,
-            Opcode::Binary(_, cycles) => *cycles,
-            Opcode::Ternary(_, cycles) => *cycles,
-        }
-    }
+/// [`dispatch`] for the byte following a 0xCB prefix, with a copy for each
+/// of the 256 CB-prefixed instructions.
+fn dispatch_prefixed(cpu: &mut SM83, bus: &impl Bus, opcode: u8) -> u8 {
+    per_opcode!(opcode, run_prefixed(cpu, bus))
 }
 
-pub type OperationsMap<CPU> = HashMap<u8, Opcode<CPU>>;
+#[inline(always)]
+fn run<const OPCODE: u8>(cpu: &mut SM83, bus: &impl Bus, low: u8, high: u8) -> u8 {
+    // the prefix's "immediate" picks from the second table
+    if OPCODE == 0xCB {
+        return dispatch_prefixed(cpu, bus, low);
+    }
 
-// trait Op<'a, CPU, const OPERANDS: usize> {
-//     fn execute(&self, cpu: &CPU, operands: [u8; OPERANDS]) -> ();
-// }
-//
-// struct Nop;
-//
-// impl Op<'static, SM83, 0> for Nop {
-//     fn execute(&self, cpu: &SM83, _: [u8; 0]) -> () {
-//         //
-//     }
-// }
-
-fn nop(_: &mut SM83, _: &MMU) {
-    //
-}
-
-fn increment_hl_addr(cpu: &mut SM83, mmu: &MMU) {
-    cpu.registers.flags.clear();
-    let addr = cpu.registers.hl();
-
-    mmu.write_byte(
-        addr,
-        match mmu
-            .read_byte(addr)
-            .expect("should be able to read byte")
-            .checked_add(1)
-        {
-            Some(0) => {
-                cpu.registers.flags.set(Flag::Zero);
-                0
-            }
-            Some(val) => val,
-            None => {
-                cpu.registers.flags.set(Flag::Carry);
-                return;
-            }
-        },
-    )
+    let instruction = decode(OPCODE, low, high);
+    execute(cpu, bus, instruction);
+    instruction.cycles()
 }
 
-macro_rules! increment8 {
-    ($reg:ident) => {
-        paste! {
-            fn [<increment_ $reg>](cpu: &mut SM83, _: &MMU) {
-                cpu.registers.flags.clear();
-
-                cpu.registers.$reg = match cpu.registers.$reg.checked_add(1) {
-                    Some(0) => {
-                        cpu.registers.flags.set(Flag::Zero);
-                        0
-                    },
-                    Some(val) => val,
-                    None => {
-                        cpu.registers.flags.set(Flag::Carry);
-                        return;
-                    }
-                }
-            }
-        }
-    };
+#[inline(always)]
+fn run_prefixed<const OPCODE: u8>(cpu: &mut SM83, bus: &impl Bus) -> u8 {
+    let instruction = decode_prefixed(OPCODE);
+    execute(cpu, bus, instruction);
+    instruction.cycles()
 }
 
-macro_rules! increment16 {
-    ($regA:ident, $regB:ident) => {
-        paste! {
-            fn [<increment_ $regA $regB>](cpu: &mut SM83, _: &MMU) {
-                let combined = cpu.registers.combined(cpu.registers.$regA, cpu.registers.$regB);
-                let result = match combined.checked_add(1) {
-                    Some(0) => {
-                        cpu.registers.flags.set(Flag::Zero);
-                        0
-                    },
-                    Some(val) => val,
-                    None => {
-                        cpu.registers.flags.set(Flag::Carry);
-                        return;
-                    }
-                };
+/// Execute a decoded instruction.
+///
+/// Like the hardware, this expects PC to already point past the instruction
+/// (and its operands). Conditional branches bump `extra_cycles` when taken.
+#[inline(always)]
+pub(crate) fn execute(cpu: &mut SM83, bus: &impl Bus, instruction: Instruction) {
+    use Instruction::*;
 
-                let [a, b] = cpu.registers.split(result);
-                cpu.registers.$regA = a;
-                cpu.registers.$regB = b;
-            }
+    match instruction {
+        Nop => {}
+        Stop => stop(cpu, bus),
+        Halt => halt(cpu, bus),
+        DisableInterrupts => disable_interrupts(cpu),
+        EnableInterrupts => enable_interrupts(cpu),
+        Illegal(_) => lock_up(cpu),
+
+        Load(destination, source) => {
+            let value = read(cpu, bus, source);
+            write(cpu, bus, destination, value);
+        }
+        LoadWord(pair, value) => set_pair(cpu, pair, value),
+        StoreSp(addr) => store_sp(cpu, bus, addr),
+        LoadSpFromHl => cpu.registers.sp = cpu.registers.hl(),
+        LoadHlFromSpOffset(offset) => {
+            let value = sp_plus_offset(cpu, offset);
+            cpu.registers.set_hl(value);
+        }
+        AddSpOffset(offset) => cpu.registers.sp = sp_plus_offset(cpu, offset),
+        Push(pair) => cpu.push_word(bus, pair_value(cpu, pair)),
+        Pop(pair) => {
+            let value = cpu.pop_word(bus);
+            set_pair(cpu, pair, value);
         }
-    };
-}
 
-macro_rules! add_to_hl {
-    ($regA:ident, $regB:ident) => {
-        paste! {
-            fn [<add_ $regA $regB _to_hl>](cpu: &mut SM83, _: &MMU) {
-                let value = match cpu.registers.hl().checked_add(cpu.registers.hl()) {
-                    Some(0) => {
-                        cpu.registers.flags.set(Flag::Zero);
-                        0
-                    },
-                    Some(val) => val,
-                    None => {
-                        cpu.registers.flags.set(Flag::Carry);
-                        return;
-                    }
-                };
+        Increment(operand) => {
+            let value = read(cpu, bus, operand);
+            let result = increment_with_flags(cpu, value);
+            write(cpu, bus, operand, result);
+        }
+        Decrement(operand) => {
+            let value = read(cpu, bus, operand);
+            let result = decrement_with_flags(cpu, value);
+            write(cpu, bus, operand, result);
+        }
+        // 16-bit INC/DEC don't affect any flags
+        IncrementWord(pair) => set_pair(cpu, pair, pair_value(cpu, pair).wrapping_add(1)),
+        DecrementWord(pair) => set_pair(cpu, pair, pair_value(cpu, pair).wrapping_sub(1)),
+        AddToHl(pair) => add_word_to_hl(cpu, pair_value(cpu, pair)),
 
-                cpu.registers.set_hl(value);
-            }
+        Add(operand) => {
+            let value = read(cpu, bus, operand);
+            add_to_a(cpu, value, false);
+        }
+        AddWithCarry(operand) => {
+            let value = read(cpu, bus, operand);
+            add_to_a(cpu, value, true);
+        }
+        Subtract(operand) => {
+            let value = read(cpu, bus, operand);
+            cpu.registers.a = subtract_from_a(cpu, value, false);
+        }
+        SubtractWithCarry(operand) => {
+            let value = read(cpu, bus, operand);
+            cpu.registers.a = subtract_from_a(cpu, value, true);
+        }
+        And(operand) => {
+            let result = cpu.registers.a & read(cpu, bus, operand);
+            bitwise_into_a(cpu, result, true);
+        }
+        Xor(operand) => {
+            let result = cpu.registers.a ^ read(cpu, bus, operand);
+            bitwise_into_a(cpu, result, false);
+        }
+        Or(operand) => {
+            let result = cpu.registers.a | read(cpu, bus, operand);
+            bitwise_into_a(cpu, result, false);
+        }
+        Compare(operand) => {
+            let value = read(cpu, bus, operand);
+            subtract_from_a(cpu, value, false);
         }
-    };
-}
 
-macro_rules! decrement8 {
-    ($reg:ident) => {
-        paste! {
-            fn [<decrement_ $reg>](cpu: &mut SM83, _: &MMU) {
-                cpu.registers.flags.clear();
+        RotateALeftWithCarry => rotate_a_left_with_carry(cpu),
+        RotateARightWithCarry => rotate_a_right_with_carry(cpu),
+        RotateALeftThroughCarry => rotate_a_left_through_carry(cpu),
+        RotateARightThroughCarry => rotate_a_right_through_carry(cpu),
+        DecimalAdjustA => decimal_adjust_a(cpu),
+        ComplementA => complement_a(cpu),
+        SetCarryFlag => set_carry_flag(cpu),
+        ComplementCarryFlag => complement_carry_flag(cpu),
 
-                cpu.registers.$reg = match cpu.registers.$reg.checked_sub(1) {
-                    Some(0) => {
-                        cpu.registers.flags.set(Flag::Zero);
-                        0
-                    },
-                    Some(val) => val,
-                    None => {
-                        cpu.registers.flags.set(Flag::Carry);
-                        return;
-                    }
+        Jump(condition, target) => {
+            if satisfied(cpu, condition) {
+                cpu.registers.pc = target;
+                if condition.is_some() {
+                    cpu.extra_cycles = 1;
                 }
             }
         }
-    };
+        JumpToHl => cpu.registers.pc = cpu.registers.hl(),
+        JumpRelative(condition, offset) => {
+            if satisfied(cpu, condition) {
+                cpu.registers.pc = cpu.registers.pc.wrapping_add_signed(offset as i16);
+                if condition.is_some() {
+                    cpu.extra_cycles = 1;
+                }
+            }
+        }
+        Call(condition, target) => {
+            if satisfied(cpu, condition) {
+                call(cpu, bus, target);
+                if condition.is_some() {
+                    cpu.extra_cycles = 3;
+                }
+            }
+        }
+        Return(condition) => {
+            if satisfied(cpu, condition) {
+                cpu.registers.pc = cpu.pop_word(bus);
+                if condition.is_some() {
+                    cpu.extra_cycles = 3;
+                }
+            }
+        }
+        ReturnFromInterrupt => {
+            cpu.registers.pc = cpu.pop_word(bus);
+            // unlike EI, RETI enables interrupts immediately
+            cpu.ime = true;
+        }
+        Restart(vector) => call(cpu, bus, vector),
+
+        RotateLeftWithCarry(operand)
+        | RotateRightWithCarry(operand)
+        | RotateLeftThroughCarry(operand)
+        | RotateRightThroughCarry(operand)
+        | ShiftLeftArithmetic(operand)
+        | ShiftRightArithmetic(operand)
+        | Swap(operand)
+        | ShiftRightLogical(operand) => {
+            let value = read(cpu, bus, operand);
+            let result = shift_with_flags(cpu, instruction, value);
+            write(cpu, bus, operand, result);
+        }
+        TestBit(bit, operand) => {
+            let value = read(cpu, bus, operand);
+            test_bit(cpu, bit, value);
+        }
+        ResetBit(bit, operand) => {
+            let value = read(cpu, bus, operand);
+            write(cpu, bus, operand, value & !(1 << bit));
+        }
+        SetBit(bit, operand) => {
+            let value = read(cpu, bus, operand);
+            write(cpu, bus, operand, value | 1 << bit);
+        }
+    }
 }
 
-macro_rules! decrement16 {
-    ($regA:ident, $regB:ident) => {
-        paste! {
-            fn [<decrement_ $regA $regB>](cpu: &mut SM83, _: &MMU) {
-                let combined = cpu.registers.combined(cpu.registers.$regA, cpu.registers.$regB);
-                let result = match combined.checked_sub(1) {
-                    Some(0) => {
-                        cpu.registers.flags.set(Flag::Zero);
-                        0
-                    },
-                    Some(val) => val,
-                    None => {
-                        cpu.registers.flags.set(Flag::Carry);
-                        return;
-                    }
-                };
+/// Whether a branch should be taken. Unconditional branches always are.
+fn satisfied(cpu: &SM83, condition: Option<Condition>) -> bool {
+    condition.is_none_or(|condition| cpu.registers.flags.satisfies(condition))
+}
 
-                let [a, b] = cpu.registers.split(result);
-                cpu.registers.$regA = a;
-                cpu.registers.$regB = b;
-            }
+// Operand access is on the path of nearly every instruction, so these are
+// forced inline to let the register matches fold away in `execute`.
+#[inline(always)]
+fn register(cpu: &mut SM83, register: Register) -> &mut u8 {
+    match register {
+        Register::A => &mut cpu.registers.a,
+        Register::B => &mut cpu.registers.b,
+        Register::C => &mut cpu.registers.c,
+        Register::D => &mut cpu.registers.d,
+        Register::E => &mut cpu.registers.e,
+        Register::H => &mut cpu.registers.h,
+        Register::L => &mut cpu.registers.l,
+    }
+}
+
+fn pair_value(cpu: &SM83, pair: RegisterPair) -> u16 {
+    let registers = &cpu.registers;
+
+    match pair {
+        RegisterPair::AF => registers.af(),
+        RegisterPair::BC => registers.bc(),
+        RegisterPair::DE => registers.de(),
+        RegisterPair::HL => registers.hl(),
+        RegisterPair::SP => registers.sp,
+    }
+}
+
+fn set_pair(cpu: &mut SM83, pair: RegisterPair, value: u16) {
+    let registers = &mut cpu.registers;
+
+    match pair {
+        RegisterPair::AF => registers.set_af(value),
+        RegisterPair::BC => registers.set_bc(value),
+        RegisterPair::DE => registers.set_de(value),
+        RegisterPair::HL => registers.set_hl(value),
+        RegisterPair::SP => registers.sp = value,
+    }
+}
+
+/// The address a memory operand refers to.
+///
+/// (HL+) and (HL-) adjust HL as a side effect, so this should be called once
+/// per access.
+fn address(cpu: &mut SM83, operand: Operand) -> u16 {
+    match operand {
+        Operand::Indirect(pair) => pair_value(cpu, pair),
+        Operand::IndirectIncrement => {
+            let hl = cpu.registers.hl();
+            cpu.registers.set_hl(hl.wrapping_add(1));
+            hl
         }
-    };
+        Operand::IndirectDecrement => {
+            let hl = cpu.registers.hl();
+            cpu.registers.set_hl(hl.wrapping_sub(1));
+            hl
+        }
+        Operand::Address(addr) => addr,
+        Operand::HighAddress(offset) => high_address(offset),
+        Operand::HighC => high_address(cpu.registers.c),
+        Operand::Register(_) | Operand::Immediate(_) => {
+            unreachable!("{:?} isn't a memory operand", operand)
+        }
+    }
 }
 
-macro_rules! load_immediate8 {
-    ($reg:ident) => {
-        paste! {
-            fn [<load_immediate_into_ $reg>](cpu: &mut SM83, _: &MMU, immediate: u8) {
-                cpu.registers.$reg = immediate;
-            }
+#[inline(always)]
+fn read(cpu: &mut SM83, bus: &impl Bus, operand: Operand) -> u8 {
+    match operand {
+        Operand::Register(reg) => *register(cpu, reg),
+        Operand::Immediate(value) => value,
+        _ => {
+            let addr = address(cpu, operand);
+            cpu.read_or_fault(bus, addr)
         }
-    };
+    }
 }
 
-macro_rules! load_immediate16 {
-    ($regA:ident,$regB:ident) => {
-        paste! {
-            fn [<load_immediate_into_ $regA $regB>](cpu: &mut SM83, _: &MMU, a: u8, b: u8) {
-                cpu.registers.$regA = a;
-                cpu.registers.$regB = b;
-            }
+#[inline(always)]
+fn write(cpu: &mut SM83, bus: &impl Bus, operand: Operand, value: u8) {
+    match operand {
+        Operand::Register(reg) => *register(cpu, reg) = value,
+        Operand::Immediate(_) => unreachable!("immediates can't be written to"),
+        _ => {
+            let addr = address(cpu, operand);
+            bus.write_byte(addr, value);
         }
-    };
+    }
 }
 
-macro_rules! load_reg_into_reg16_addr {
-    ($source:ident,$destA:ident,$destB:ident) => {
-        paste! {
-            fn [<load_ $source _into_ $destA $destB _address>](cpu: &mut SM83, mmu: &MMU) {
-                let addr = cpu.registers.combined(cpu.registers.$destA, cpu.registers.$destB);
+// LDH (a8),A / LDH A,(a8) / LD (C),A / LD A,(C)
+//
+// These address the I/O and HRAM page at 0xFF00 + offset.
+fn high_address(offset: u8) -> u16 {
+    0xFF00 | offset as u16
+}
 
-                mmu.write_byte(addr, cpu.registers.$source);
-            }
+/// Increment an 8-bit value, setting Z/N/H. The carry flag is untouched.
+fn increment_with_flags(cpu: &mut SM83, value: u8) -> u8 {
+    let result = value.wrapping_add(1);
+
+    let flags = &mut cpu.registers.flags;
+    flags.unset(Flag::Subtract);
+    flags.set_if(Flag::Zero, result == 0);
+    flags.set_if(Flag::HalfCarry, value & 0x0F == 0x0F);
+
+    result
+}
+
+/// Decrement an 8-bit value, setting Z/N/H. The carry flag is untouched.
+fn decrement_with_flags(cpu: &mut SM83, value: u8) -> u8 {
+    let result = value.wrapping_sub(1);
+
+    let flags = &mut cpu.registers.flags;
+    flags.set(Flag::Subtract);
+    flags.set_if(Flag::Zero, result == 0);
+    flags.set_if(Flag::HalfCarry, value & 0x0F == 0);
+
+    result
+}
+
+/// Add a 16-bit value to HL (ADD HL,rr).
+///
+/// H and C come from bits 11 and 15, and Z is left untouched.
+fn add_word_to_hl(cpu: &mut SM83, value: u16) {
+    let hl = cpu.registers.hl();
+    let (result, carry) = hl.overflowing_add(value);
+
+    let flags = &mut cpu.registers.flags;
+    flags.unset(Flag::Subtract);
+    flags.set_if(Flag::HalfCarry, (hl & 0x0FFF) + (value & 0x0FFF) > 0x0FFF);
+    flags.set_if(Flag::Carry, carry);
+
+    cpu.registers.set_hl(result);
+}
+
+/// Add a value to A, optionally including the carry flag (ADD/ADC).
+fn add_to_a(cpu: &mut SM83, value: u8, use_carry: bool) {
+    let a = cpu.registers.a;
+    let carry = (use_carry && cpu.registers.flags.check(Flag::Carry)) as u8;
+    let result = a.wrapping_add(value).wrapping_add(carry);
+
+    cpu.registers.flags.assign(
+        result == 0,
+        false,
+        (a & 0x0F) + (value & 0x0F) + carry > 0x0F,
+        a as u16 + value as u16 + carry as u16 > 0xFF,
+    );
+
+    cpu.registers.a = result;
+}
+
+/// Subtract a value from A, optionally including the carry flag (SUB/SBC/CP).
+///
+/// The flags are updated, but the result is returned rather than stored so
+/// that CP can share the logic.
+fn subtract_from_a(cpu: &mut SM83, value: u8, use_carry: bool) -> u8 {
+    let a = cpu.registers.a;
+    let carry = (use_carry && cpu.registers.flags.check(Flag::Carry)) as u8;
+    let result = a.wrapping_sub(value).wrapping_sub(carry);
+
+    cpu.registers.flags.assign(
+        result == 0,
+        true,
+        (a & 0x0F) < (value & 0x0F) + carry,
+        (a as u16) < value as u16 + carry as u16,
+    );
+
+    result
+}
+
+/// Store the result of a bitwise operation in A (AND/XOR/OR).
+///
+/// Only AND sets the half carry flag.
+fn bitwise_into_a(cpu: &mut SM83, result: u8, half_carry: bool) {
+    cpu.registers
+        .flags
+        .assign(result == 0, false, half_carry, false);
+
+    cpu.registers.a = result;
+}
+
+/// LD (a16),SP, which stores the low byte first.
+fn store_sp(cpu: &mut SM83, bus: &impl Bus, addr: u16) {
+    let [sp_low, sp_high] = cpu.registers.sp.to_le_bytes();
+
+    bus.write_byte(addr, sp_low);
+    bus.write_byte(addr.wrapping_add(1), sp_high);
+}
+
+/// Add a signed offset to SP, setting flags.
+///
+/// Unlike the other 16-bit additions, H and C come from an unsigned 8-bit
+/// addition of the offset to the low byte of SP (bits 3 and 7), and Z is
+/// always cleared.
+fn sp_plus_offset(cpu: &mut SM83, offset: i8) -> u16 {
+    let sp = cpu.registers.sp;
+    let unsigned = offset as u8 as u16;
+
+    cpu.registers.flags.assign(
+        false,
+        false,
+        (sp & 0x000F) + (unsigned & 0x000F) > 0x000F,
+        (sp & 0x00FF) + unsigned > 0x00FF,
+    );
+
+    sp.wrapping_add_signed(offset as i16)
+}
+
+fn call(cpu: &mut SM83, bus: &impl Bus, target: u16) {
+    cpu.push_word(bus, cpu.registers.pc);
+    cpu.registers.pc = target;
+}
+
+/// Disable interrupts. This also cancels an EI that hasn't taken effect yet.
+fn disable_interrupts(cpu: &mut SM83) {
+    cpu.ime = false;
+    cpu.ime_scheduled = false;
+}
+
+/// Enable interrupts once the following instruction has finished.
+fn enable_interrupts(cpu: &mut SM83) {
+    cpu.ime_scheduled = true;
+}
+
+/// Hang the CPU, as the unused opcodes do on hardware.
+///
+/// Nothing short of a reset recovers from this, not even an interrupt.
+fn lock_up(cpu: &mut SM83) {
+    cpu.locked = true;
+}
+
+/// Stop fetching instructions until an interrupt is pending.
+///
+/// If one is already pending, the CPU doesn't halt at all. With IME set the
+/// interrupt is simply serviced, but with IME clear this trips the halt bug:
+/// the following byte is read twice.
+fn halt(cpu: &mut SM83, bus: &impl Bus) {
+    if !SM83::interrupt_pending(bus) {
+        cpu.halted = true;
+    } else if !cpu.ime {
+        cpu.halt_bug = true;
+    }
+}
+
+// CPL/SCF/CCF
+fn complement_a(cpu: &mut SM83) {
+    cpu.registers.a = !cpu.registers.a;
+    cpu.registers.flags.set(Flag::Subtract);
+    cpu.registers.flags.set(Flag::HalfCarry);
+}
+
+fn set_carry_flag(cpu: &mut SM83) {
+    cpu.registers.flags.unset(Flag::Subtract);
+    cpu.registers.flags.unset(Flag::HalfCarry);
+    cpu.registers.flags.set(Flag::Carry);
+}
+
+fn complement_carry_flag(cpu: &mut SM83) {
+    cpu.registers.flags.unset(Flag::Subtract);
+    cpu.registers.flags.unset(Flag::HalfCarry);
+    cpu.registers.flags.toggle(Flag::Carry);
+}
+
+/// Adjust A back into binary-coded decimal after an addition or subtraction.
+///
+/// The previous instruction's N flag picks the direction, and H/C say which
+/// nibbles overflowed (or borrowed).
+fn decimal_adjust_a(cpu: &mut SM83) {
+    let mut a = cpu.registers.a;
+    let mut carry = cpu.registers.flags.check(Flag::Carry);
+    let half_carry = cpu.registers.flags.check(Flag::HalfCarry);
+
+    if cpu.registers.flags.check(Flag::Subtract) {
+        if carry {
+            a = a.wrapping_sub(0x60);
         }
+        if half_carry {
+            a = a.wrapping_sub(0x06);
+        }
+    } else {
+        if carry || a > 0x99 {
+            a = a.wrapping_add(0x60);
+            carry = true;
+        }
+        if half_carry || (a & 0x0F) > 0x09 {
+            a = a.wrapping_add(0x06);
+        }
+    }
+
+    let flags = &mut cpu.registers.flags;
+    flags.unset(Flag::HalfCarry);
+    flags.set_if(Flag::Zero, a == 0);
+    flags.set_if(Flag::Carry, carry);
+
+    cpu.registers.a = a;
+}
+
+// RLCA/RRCA/RLA/RRA
+//
+// Unlike their CB-prefixed counterparts, these always clear the zero flag.
+fn store_rotated_a(cpu: &mut SM83, result: u8, carry: bool) {
+    cpu.registers.flags.assign(false, false, false, carry);
+
+    cpu.registers.a = result;
+}
+
+/// RLC/RRC/RL/RR/SLA/SRA/SWAP/SRL, setting Z from the result and C from
+/// the bit shifted out. N and H are cleared.
+#[inline(always)]
+fn shift_with_flags(cpu: &mut SM83, instruction: Instruction, value: u8) -> u8 {
+    use Instruction::*;
+
+    let carry = cpu.registers.flags.check(Flag::Carry) as u8;
+    let (result, carry) = match instruction {
+        RotateLeftWithCarry(_) => (value.rotate_left(1), value & 0x80 != 0),
+        RotateRightWithCarry(_) => (value.rotate_right(1), value & 0x01 != 0),
+        RotateLeftThroughCarry(_) => (value << 1 | carry, value & 0x80 != 0),
+        RotateRightThroughCarry(_) => (value >> 1 | carry << 7, value & 0x01 != 0),
+        ShiftLeftArithmetic(_) => (value << 1, value & 0x80 != 0),
+        ShiftRightArithmetic(_) => (value >> 1 | value & 0x80, value & 0x01 != 0),
+        Swap(_) => (value.rotate_left(4), false),
+        ShiftRightLogical(_) => (value >> 1, value & 0x01 != 0),
+        _ => unreachable!("{:?} isn't a shift", instruction),
     };
+
+    cpu.registers.flags.assign(result == 0, false, false, carry);
+
+    result
 }
 
-increment8!(a);
-increment8!(b);
-increment8!(c);
-increment8!(d);
-increment8!(e);
-increment8!(h);
-increment8!(l);
-
-increment16!(b, c);
-increment16!(d, e);
-increment16!(h, l);
-// increment16!(s, p);
-
-decrement8!(b);
-
-decrement16!(b, c);
-decrement16!(d, e);
-decrement16!(h, l);
-
-add_to_hl!(b, c);
-add_to_hl!(d, e);
-add_to_hl!(h, l);
-// add_to_hl!(s, p);
-
-load_immediate8!(b);
-load_immediate16!(b, c);
-load_reg_into_reg16_addr!(a, b, c);
-
-fn rotate_a_left_with_carry(_cpu: &mut SM83, _mmu: &MMU) {
-    //
-}
-
-fn load_sp_into_immediate_address(_cpu: &mut SM83, _mmu: &MMU, _a: u8, _b: u8) {
-    //
-}
-
-fn stop(_cpu: &mut SM83, _mmu: &MMU, _: u8) {
-    //
-}
-
-lazy_static! {
-    pub static ref SM83_OPERATIONS: OperationsMap<SM83> = HashMap::from([
-        (0x00u8, Opcode::Unary(nop, 1)),
-        (0x01u8, Opcode::Ternary(load_immediate_into_bc, 1)),
-        (0x02u8, Opcode::Unary(load_a_into_bc_address, 1)),
-        (0x03u8, Opcode::Unary(increment_bc, 1)),
-        (0x04u8, Opcode::Unary(increment_b, 1)),
-        (0x05u8, Opcode::Unary(decrement_b, 1)),
-        (0x06u8, Opcode::Binary(load_immediate_into_b, 1)),
-        (0x07u8, Opcode::Unary(rotate_a_left_with_carry, 1)),
-        (0x08u8, Opcode::Ternary(load_sp_into_immediate_address, 1)),
-        (0x09u8, Opcode::Unary(add_bc_to_hl, 1)),
-        (0x0Au8, Opcode::Unary(nop, 1)),
-        (0x0Bu8, Opcode::Unary(decrement_bc, 1)),
-        (0x0Cu8, Opcode::Unary(increment_c, 1)),
-        (0x0Du8, Opcode::Unary(nop, 1)),
-        (0x0Eu8, Opcode::Unary(nop, 1)),
-        (0x0Fu8, Opcode::Unary(nop, 1)),
-        (0x10u8, Opcode::Binary(stop, 1)),
-        (0x11u8, Opcode::Unary(nop, 1)),
-        (0x12u8, Opcode::Unary(nop, 1)),
-        (0x13u8, Opcode::Unary(increment_de, 1)),
-        (0x14u8, Opcode::Unary(increment_d, 1)),
-        (0x15u8, Opcode::Unary(nop, 1)),
-        (0x16u8, Opcode::Unary(nop, 1)),
-        (0x17u8, Opcode::Unary(nop, 1)),
-        (0x18u8, Opcode::Unary(nop, 1)),
-        (0x19u8, Opcode::Unary(add_de_to_hl, 1)),
-        (0x1Au8, Opcode::Unary(nop, 1)),
-        (0x1Bu8, Opcode::Unary(decrement_de, 1)),
-        (0x1Cu8, Opcode::Unary(increment_e, 1)),
-        (0x1Du8, Opcode::Unary(nop, 1)),
-        (0x1Eu8, Opcode::Unary(nop, 1)),
-        (0x1Fu8, Opcode::Unary(nop, 1)),
-        (0x20u8, Opcode::Unary(nop, 1)),
-        (0x21u8, Opcode::Unary(nop, 1)),
-        (0x22u8, Opcode::Unary(nop, 1)),
-        (0x23u8, Opcode::Unary(increment_hl, 1)),
-        (0x24u8, Opcode::Unary(increment_h, 1)),
-        (0x25u8, Opcode::Unary(nop, 1)),
-        (0x26u8, Opcode::Unary(nop, 1)),
-        (0x27u8, Opcode::Unary(nop, 1)),
-        (0x28u8, Opcode::Unary(nop, 1)),
-        (0x29u8, Opcode::Unary(add_hl_to_hl, 1)),
-        (0x2Au8, Opcode::Unary(nop, 1)),
-        (0x2Bu8, Opcode::Unary(decrement_hl, 1)),
-        (0x2Cu8, Opcode::Unary(increment_l, 1)),
-        (0x2Du8, Opcode::Unary(nop, 1)),
-        (0x2Eu8, Opcode::Unary(nop, 1)),
-        (0x2Fu8, Opcode::Unary(nop, 1)),
-        (0x30u8, Opcode::Unary(nop, 1)),
-        (0x31u8, Opcode::Unary(nop, 1)),
-        (0x32u8, Opcode::Unary(nop, 1)),
-        (0x33u8, Opcode::Unary(nop, 1)),
-        (0x34u8, Opcode::Unary(increment_hl_addr, 1)),
-        (0x35u8, Opcode::Unary(nop, 1)),
-        (0x36u8, Opcode::Unary(nop, 1)),
-        (0x37u8, Opcode::Unary(nop, 1)),
-        (0x38u8, Opcode::Unary(nop, 1)),
-        (0x39u8, Opcode::Unary(nop, 1)),
-        (0x3Au8, Opcode::Unary(nop, 1)),
-        (0x3Bu8, Opcode::Unary(nop, 1)),
-        (0x3Cu8, Opcode::Unary(increment_a, 1)),
-        (0x3Du8, Opcode::Unary(nop, 1)),
-        (0x3Eu8, Opcode::Unary(nop, 1)),
-        (0x3Fu8, Opcode::Unary(nop, 1)),
-        (0x40u8, Opcode::Unary(nop, 1)),
-        (0x41u8, Opcode::Unary(nop, 1)),
-        (0x42u8, Opcode::Unary(nop, 1)),
-        (0x43u8, Opcode::Unary(nop, 1)),
-        (0x44u8, Opcode::Unary(nop, 1)),
-        (0x45u8, Opcode::Unary(nop, 1)),
-        (0x46u8, Opcode::Unary(nop, 1)),
-        (0x47u8, Opcode::Unary(nop, 1)),
-        (0x48u8, Opcode::Unary(nop, 1)),
-        (0x49u8, Opcode::Unary(nop, 1)),
-        (0x4Au8, Opcode::Unary(nop, 1)),
-        (0x4Bu8, Opcode::Unary(nop, 1)),
-        (0x4Cu8, Opcode::Unary(nop, 1)),
-        (0x4Du8, Opcode::Unary(nop, 1)),
-        (0x4Eu8, Opcode::Unary(nop, 1)),
-        (0x4Fu8, Opcode::Unary(nop, 1)),
-        (0x50u8, Opcode::Unary(nop, 1)),
-        (0x51u8, Opcode::Unary(nop, 1)),
-        (0x52u8, Opcode::Unary(nop, 1)),
-        (0x53u8, Opcode::Unary(nop, 1)),
-        (0x54u8, Opcode::Unary(nop, 1)),
-        (0x55u8, Opcode::Unary(nop, 1)),
-        (0x56u8, Opcode::Unary(nop, 1)),
-        (0x57u8, Opcode::Unary(nop, 1)),
-        (0x58u8, Opcode::Unary(nop, 1)),
-        (0x59u8, Opcode::Unary(nop, 1)),
-        (0x5Au8, Opcode::Unary(nop, 1)),
-        (0x5Bu8, Opcode::Unary(nop, 1)),
-        (0x5Cu8, Opcode::Unary(nop, 1)),
-        (0x5Du8, Opcode::Unary(nop, 1)),
-        (0x5Eu8, Opcode::Unary(nop, 1)),
-        (0x5Fu8, Opcode::Unary(nop, 1)),
-        (0x60u8, Opcode::Unary(nop, 1)),
-        (0x61u8, Opcode::Unary(nop, 1)),
-        (0x62u8, Opcode::Unary(nop, 1)),
-        (0x63u8, Opcode::Unary(nop, 1)),
-        (0x64u8, Opcode::Unary(nop, 1)),
-        (0x65u8, Opcode::Unary(nop, 1)),
-        (0x66u8, Opcode::Unary(nop, 1)),
-        (0x67u8, Opcode::Unary(nop, 1)),
-        (0x68u8, Opcode::Unary(nop, 1)),
-        (0x69u8, Opcode::Unary(nop, 1)),
-        (0x6Au8, Opcode::Unary(nop, 1)),
-        (0x6Bu8, Opcode::Unary(nop, 1)),
-        (0x6Cu8, Opcode::Unary(nop, 1)),
-        (0x6Du8, Opcode::Unary(nop, 1)),
-        (0x6Eu8, Opcode::Unary(nop, 1)),
-        (0x6Fu8, Opcode::Unary(nop, 1)),
-        (0x70u8, Opcode::Unary(nop, 1)),
-        (0x71u8, Opcode::Unary(nop, 1)),
-        (0x72u8, Opcode::Unary(nop, 1)),
-        (0x73u8, Opcode::Unary(nop, 1)),
-        (0x74u8, Opcode::Unary(nop, 1)),
-        (0x75u8, Opcode::Unary(nop, 1)),
-        (0x76u8, Opcode::Unary(nop, 1)),
-        (0x77u8, Opcode::Unary(nop, 1)),
-        (0x78u8, Opcode::Unary(nop, 1)),
-        (0x79u8, Opcode::Unary(nop, 1)),
-        (0x7Au8, Opcode::Unary(nop, 1)),
-        (0x7Bu8, Opcode::Unary(nop, 1)),
-        (0x7Cu8, Opcode::Unary(nop, 1)),
-        (0x7Du8, Opcode::Unary(nop, 1)),
-        (0x7Eu8, Opcode::Unary(nop, 1)),
-        (0x7Fu8, Opcode::Unary(nop, 1)),
-        (0x80u8, Opcode::Unary(nop, 1)),
-        (0x81u8, Opcode::Unary(nop, 1)),
-        (0x82u8, Opcode::Unary(nop, 1)),
-        (0x83u8, Opcode::Unary(nop, 1)),
-        (0x84u8, Opcode::Unary(nop, 1)),
-        (0x85u8, Opcode::Unary(nop, 1)),
-        (0x86u8, Opcode::Unary(nop, 1)),
-        (0x87u8, Opcode::Unary(nop, 1)),
-        (0x88u8, Opcode::Unary(nop, 1)),
-        (0x89u8, Opcode::Unary(nop, 1)),
-        (0x8Au8, Opcode::Unary(nop, 1)),
-        (0x8Bu8, Opcode::Unary(nop, 1)),
-        (0x8Cu8, Opcode::Unary(nop, 1)),
-        (0x8Du8, Opcode::Unary(nop, 1)),
-        (0x8Eu8, Opcode::Unary(nop, 1)),
-        (0x8Fu8, Opcode::Unary(nop, 1)),
-        (0x90u8, Opcode::Unary(nop, 1)),
-        (0x91u8, Opcode::Unary(nop, 1)),
-        (0x92u8, Opcode::Unary(nop, 1)),
-        (0x93u8, Opcode::Unary(nop, 1)),
-        (0x94u8, Opcode::Unary(nop, 1)),
-        (0x95u8, Opcode::Unary(nop, 1)),
-        (0x96u8, Opcode::Unary(nop, 1)),
-        (0x97u8, Opcode::Unary(nop, 1)),
-        (0x98u8, Opcode::Unary(nop, 1)),
-        (0x99u8, Opcode::Unary(nop, 1)),
-        (0x9Au8, Opcode::Unary(nop, 1)),
-        (0x9Bu8, Opcode::Unary(nop, 1)),
-        (0x9Cu8, Opcode::Unary(nop, 1)),
-        (0x9Du8, Opcode::Unary(nop, 1)),
-        (0x9Eu8, Opcode::Unary(nop, 1)),
-        (0x9Fu8, Opcode::Unary(nop, 1)),
-        (0xA0u8, Opcode::Unary(nop, 1)),
-        (0xA1u8, Opcode::Unary(nop, 1)),
-        (0xA2u8, Opcode::Unary(nop, 1)),
-        (0xA3u8, Opcode::Unary(nop, 1)),
-        (0xA4u8, Opcode::Unary(nop, 1)),
-        (0xA5u8, Opcode::Unary(nop, 1)),
-        (0xA6u8, Opcode::Unary(nop, 1)),
-        (0xA7u8, Opcode::Unary(nop, 1)),
-        (0xA8u8, Opcode::Unary(nop, 1)),
-        (0xA9u8, Opcode::Unary(nop, 1)),
-        (0xAAu8, Opcode::Unary(nop, 1)),
-        (0xABu8, Opcode::Unary(nop, 1)),
-        (0xACu8, Opcode::Unary(nop, 1)),
-        (0xADu8, Opcode::Unary(nop, 1)),
-        (0xAEu8, Opcode::Unary(nop, 1)),
-        (0xAFu8, Opcode::Unary(nop, 1)),
-        (0xB0u8, Opcode::Unary(nop, 1)),
-        (0xB1u8, Opcode::Unary(nop, 1)),
-        (0xB2u8, Opcode::Unary(nop, 1)),
-        (0xB3u8, Opcode::Unary(nop, 1)),
-        (0xB4u8, Opcode::Unary(nop, 1)),
-        (0xB5u8, Opcode::Unary(nop, 1)),
-        (0xB6u8, Opcode::Unary(nop, 1)),
-        (0xB7u8, Opcode::Unary(nop, 1)),
-        (0xB8u8, Opcode::Unary(nop, 1)),
-        (0xB9u8, Opcode::Unary(nop, 1)),
-        (0xBAu8, Opcode::Unary(nop, 1)),
-        (0xBBu8, Opcode::Unary(nop, 1)),
-        (0xBCu8, Opcode::Unary(nop, 1)),
-        (0xBDu8, Opcode::Unary(nop, 1)),
-        (0xBEu8, Opcode::Unary(nop, 1)),
-        (0xBFu8, Opcode::Unary(nop, 1)),
-        (0xC0u8, Opcode::Unary(nop, 1)),
-        (0xC1u8, Opcode::Unary(nop, 1)),
-        (0xC2u8, Opcode::Unary(nop, 1)),
-        (0xC3u8, Opcode::Unary(nop, 1)),
-        (0xC4u8, Opcode::Unary(nop, 1)),
-        (0xC5u8, Opcode::Unary(nop, 1)),
-        (0xC6u8, Opcode::Unary(nop, 1)),
-        (0xC7u8, Opcode::Unary(nop, 1)),
-        (0xC8u8, Opcode::Unary(nop, 1)),
-        (0xC9u8, Opcode::Unary(nop, 1)),
-        (0xCAu8, Opcode::Unary(nop, 1)),
-        (0xCBu8, Opcode::Unary(nop, 1)),
-        (0xCCu8, Opcode::Unary(nop, 1)),
-        (0xCDu8, Opcode::Unary(nop, 1)),
-        (0xCEu8, Opcode::Unary(nop, 1)),
-        (0xCFu8, Opcode::Unary(nop, 1)),
-        (0xD0u8, Opcode::Unary(nop, 1)),
-        (0xD1u8, Opcode::Unary(nop, 1)),
-        (0xD2u8, Opcode::Unary(nop, 1)),
-        (0xD3u8, Opcode::Unary(nop, 1)),
-        (0xD4u8, Opcode::Unary(nop, 1)),
-        (0xD5u8, Opcode::Unary(nop, 1)),
-        (0xD6u8, Opcode::Unary(nop, 1)),
-        (0xD7u8, Opcode::Unary(nop, 1)),
-        (0xD8u8, Opcode::Unary(nop, 1)),
-        (0xD9u8, Opcode::Unary(nop, 1)),
-        (0xDAu8, Opcode::Unary(nop, 1)),
-        (0xDBu8, Opcode::Unary(nop, 1)),
-        (0xDCu8, Opcode::Unary(nop, 1)),
-        (0xDDu8, Opcode::Unary(nop, 1)),
-        (0xDEu8, Opcode::Unary(nop, 1)),
-        (0xDFu8, Opcode::Unary(nop, 1)),
-        (0xE0u8, Opcode::Unary(nop, 1)),
-        (0xE1u8, Opcode::Unary(nop, 1)),
-        (0xE2u8, Opcode::Unary(nop, 1)),
-        (0xE3u8, Opcode::Unary(nop, 1)),
-        (0xE4u8, Opcode::Unary(nop, 1)),
-        (0xE5u8, Opcode::Unary(nop, 1)),
-        (0xE6u8, Opcode::Unary(nop, 1)),
-        (0xE7u8, Opcode::Unary(nop, 1)),
-        (0xE8u8, Opcode::Unary(nop, 1)),
-        (0xE9u8, Opcode::Unary(nop, 1)),
-        (0xEAu8, Opcode::Unary(nop, 1)),
-        (0xEBu8, Opcode::Unary(nop, 1)),
-        (0xECu8, Opcode::Unary(nop, 1)),
-        (0xEDu8, Opcode::Unary(nop, 1)),
-        (0xEEu8, Opcode::Unary(nop, 1)),
-        (0xEFu8, Opcode::Unary(nop, 1)),
-        (0xF0u8, Opcode::Unary(nop, 1)),
-        (0xF1u8, Opcode::Unary(nop, 1)),
-        (0xF2u8, Opcode::Unary(nop, 1)),
-        (0xF3u8, Opcode::Unary(nop, 1)),
-        (0xF4u8, Opcode::Unary(nop, 1)),
-        (0xF5u8, Opcode::Unary(nop, 1)),
-        (0xF6u8, Opcode::Unary(nop, 1)),
-        (0xF7u8, Opcode::Unary(nop, 1)),
-        (0xF8u8, Opcode::Unary(nop, 1)),
-        (0xF9u8, Opcode::Unary(nop, 1)),
-        (0xFAu8, Opcode::Unary(nop, 1)),
-        (0xFBu8, Opcode::Unary(nop, 1)),
-        (0xFCu8, Opcode::Unary(nop, 1)),
-        (0xFDu8, Opcode::Unary(nop, 1)),
-        (0xFEu8, Opcode::Unary(nop, 1)),
-        (0xFFu8, Opcode::Unary(nop, 1)),
-    ]);
+/// BIT: set Z if the bit is clear. H is set, N cleared and C untouched.
+fn test_bit(cpu: &mut SM83, bit: u8, value: u8) {
+    let flags = &mut cpu.registers.flags;
+    flags.set_if(Flag::Zero, value & 1 << bit == 0);
+    flags.unset(Flag::Subtract);
+    flags.set(Flag::HalfCarry);
+}
+
+fn rotate_a_left_with_carry(cpu: &mut SM83) {
+    let a = cpu.registers.a;
+    store_rotated_a(cpu, a.rotate_left(1), a & 0x80 != 0);
+}
+
+fn rotate_a_right_with_carry(cpu: &mut SM83) {
+    let a = cpu.registers.a;
+    store_rotated_a(cpu, a.rotate_right(1), a & 0x01 != 0);
+}
+
+fn rotate_a_left_through_carry(cpu: &mut SM83) {
+    let a = cpu.registers.a;
+    let carry = cpu.registers.flags.check(Flag::Carry) as u8;
+    store_rotated_a(cpu, a << 1 | carry, a & 0x80 != 0);
+}
+
+fn rotate_a_right_through_carry(cpu: &mut SM83) {
+    let a = cpu.registers.a;
+    let carry = cpu.registers.flags.check(Flag::Carry) as u8;
+    store_rotated_a(cpu, a >> 1 | carry << 7, a & 0x01 != 0);
+}
+
+/// Enter low-power stop mode, or perform a pending CGB speed switch.
+///
+/// KEY1 belongs to the bus, which knows the hardware model: a speed switch
+/// only happens on the CGB (see [`Bus::switch_speed`]), so on the DMG, MGB
+/// and SGB STOP always stops.
+fn stop(cpu: &mut SM83, bus: &impl Bus) {
+    match bus.switch_speed() {
+        Some(double_speed) => cpu.double_speed = double_speed,
+        None => cpu.stopped = true,
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::mmu::MMU;
+    use crate::{bus::FlatMemory, cpu::instructions::decode, mmu::MMU};
 
     use super::*;
 
@@ -530,12 +624,841 @@ mod tests {
     fn test_nop() {
         let mut cpu = SM83::new();
         let mmu = MMU::new();
-        let opcode = 0x00u8;
-        let operation = SM83_OPERATIONS.get(&opcode).unwrap();
 
-        match operation {
-            Opcode::Unary(op, _) => op(&mut cpu, &mmu),
-            _ => panic!("Expected unary operation"),
+        execute_opcode(&mut cpu, &mmu, 0x00);
+        assert_eq!(cpu.registers, SM83::new().registers);
+    }
+
+    /// Decode and execute an instruction without any immediate operands.
+    fn execute_opcode(cpu: &mut SM83, bus: &impl Bus, opcode: u8) {
+        execute(cpu, bus, decode(opcode, 0x00, 0x00));
+    }
+
+    #[test]
+    fn test_load_reg_into_reg() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+        cpu.registers.c = 0x42;
+
+        execute_opcode(&mut cpu, &mmu, 0x41); // LD B,C
+
+        assert_eq!(cpu.registers.b, 0x42);
+        assert_eq!(cpu.registers.c, 0x42);
+    }
+
+    #[test]
+    fn test_load_via_hl_address() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+        cpu.registers.set_hl(0xC123);
+        cpu.registers.a = 0x99;
+
+        execute_opcode(&mut cpu, &mmu, 0x77); // LD (HL),A
+        assert_eq!(mmu.read_byte(cpu.registers.hl()), Some(0x99));
+
+        execute_opcode(&mut cpu, &mmu, 0x5E); // LD E,(HL)
+        assert_eq!(cpu.registers.e, 0x99);
+    }
+
+    #[test]
+    fn test_flat_memory_bus() {
+        let mut cpu = SM83::new();
+        let memory = FlatMemory::new();
+        cpu.registers.sp = 0xFEB0;
+        cpu.registers.set_hl(0xFEA0);
+        cpu.registers.a = 0x99;
+
+        // both addresses are in the unusable area, where the MMU drops writes
+        execute_opcode(&mut cpu, &memory, 0x77); // LD (HL),A
+        execute_opcode(&mut cpu, &memory, 0xE5); // PUSH HL
+        assert_eq!(memory.read_byte(0xFEA0), Some(0x99));
+        assert_eq!(memory.read_byte(0xFEAF), Some(0xFE));
+    }
+
+    fn flags(cpu: &SM83) -> [bool; 4] {
+        [
+            cpu.registers.flags.check(Flag::Zero),
+            cpu.registers.flags.check(Flag::Subtract),
+            cpu.registers.flags.check(Flag::HalfCarry),
+            cpu.registers.flags.check(Flag::Carry),
+        ]
+    }
+
+    #[test]
+    fn test_add_flags() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+
+        cpu.registers.a = 0x0F;
+        cpu.registers.b = 0x01;
+        execute_opcode(&mut cpu, &mmu, 0x80); // ADD A,B
+        assert_eq!(cpu.registers.a, 0x10);
+        assert_eq!(flags(&cpu), [false, false, true, false]);
+
+        cpu.registers.a = 0xF0;
+        cpu.registers.b = 0x10;
+        execute_opcode(&mut cpu, &mmu, 0x80);
+        assert_eq!(cpu.registers.a, 0x00);
+        assert_eq!(flags(&cpu), [true, false, false, true]);
+
+        cpu.registers.a = 0x80;
+        execute_opcode(&mut cpu, &mmu, 0x87); // ADD A,A
+        assert_eq!(cpu.registers.a, 0x00);
+        assert_eq!(flags(&cpu), [true, false, false, true]);
+    }
+
+    #[test]
+    fn test_add_with_carry_flags() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+
+        // the carry-in alone can trigger both carries
+        cpu.registers.a = 0xFF;
+        cpu.registers.c = 0x00;
+        cpu.registers.flags.set(Flag::Carry);
+        execute_opcode(&mut cpu, &mmu, 0x89); // ADC A,C
+        assert_eq!(cpu.registers.a, 0x00);
+        assert_eq!(flags(&cpu), [true, false, true, true]);
+
+        // without a carry-in, ADC behaves like ADD
+        cpu.registers.flags.clear();
+        cpu.registers.a = 0x0E;
+        cpu.registers.c = 0x01;
+        execute_opcode(&mut cpu, &mmu, 0x89);
+        assert_eq!(cpu.registers.a, 0x0F);
+        assert_eq!(flags(&cpu), [false, false, false, false]);
+    }
+
+    #[test]
+    fn test_subtract_flags() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+
+        cpu.registers.a = 0x10;
+        cpu.registers.d = 0x01;
+        execute_opcode(&mut cpu, &mmu, 0x92); // SUB D
+        assert_eq!(cpu.registers.a, 0x0F);
+        assert_eq!(flags(&cpu), [false, true, true, false]);
+
+        cpu.registers.a = 0x00;
+        execute_opcode(&mut cpu, &mmu, 0x92);
+        assert_eq!(cpu.registers.a, 0xFF);
+        assert_eq!(flags(&cpu), [false, true, true, true]);
+
+        cpu.registers.a = 0x3C;
+        execute_opcode(&mut cpu, &mmu, 0x97); // SUB A
+        assert_eq!(cpu.registers.a, 0x00);
+        assert_eq!(flags(&cpu), [true, true, false, false]);
+    }
+
+    #[test]
+    fn test_subtract_with_carry_flags() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+
+        cpu.registers.a = 0x10;
+        cpu.registers.e = 0x0F;
+        cpu.registers.flags.set(Flag::Carry);
+        execute_opcode(&mut cpu, &mmu, 0x9B); // SBC A,E
+        assert_eq!(cpu.registers.a, 0x00);
+        assert_eq!(flags(&cpu), [true, true, true, false]);
+
+        cpu.registers.a = 0x00;
+        cpu.registers.e = 0x00;
+        cpu.registers.flags.set(Flag::Carry);
+        execute_opcode(&mut cpu, &mmu, 0x9B);
+        assert_eq!(cpu.registers.a, 0xFF);
+        assert_eq!(flags(&cpu), [false, true, true, true]);
+    }
+
+    #[test]
+    fn test_bitwise_flags() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+
+        cpu.registers.a = 0b1010_1010;
+        cpu.registers.h = 0b0101_0101;
+        cpu.registers.flags.set(Flag::Carry);
+        execute_opcode(&mut cpu, &mmu, 0xA4); // AND H
+        assert_eq!(cpu.registers.a, 0x00);
+        assert_eq!(flags(&cpu), [true, false, true, false]);
+
+        cpu.registers.a = 0b1010_1010;
+        execute_opcode(&mut cpu, &mmu, 0xB4); // OR H
+        assert_eq!(cpu.registers.a, 0xFF);
+        assert_eq!(flags(&cpu), [false, false, false, false]);
+
+        execute_opcode(&mut cpu, &mmu, 0xAF); // XOR A
+        assert_eq!(cpu.registers.a, 0x00);
+        assert_eq!(flags(&cpu), [true, false, false, false]);
+    }
+
+    #[test]
+    fn test_compare_leaves_a_untouched() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+        cpu.registers.set_hl(0xC000);
+        mmu.write_byte(0xC000, 0x42);
+
+        cpu.registers.a = 0x42;
+        execute_opcode(&mut cpu, &mmu, 0xBE); // CP (HL)
+        assert_eq!(cpu.registers.a, 0x42);
+        assert_eq!(flags(&cpu), [true, true, false, false]);
+
+        cpu.registers.a = 0x41;
+        execute_opcode(&mut cpu, &mmu, 0xBE);
+        assert_eq!(cpu.registers.a, 0x41);
+        assert_eq!(flags(&cpu), [false, true, true, true]);
+    }
+
+    #[test]
+    fn test_alu_immediates() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+        let mut execute_immediate = |opcode: u8, immediate: u8| {
+            execute(&mut cpu, &mmu, decode(opcode, immediate, 0x00));
+            cpu.registers.a
+        };
+
+        assert_eq!(execute_immediate(0xC6, 0x20), 0x20); // ADD A,d8
+        assert_eq!(execute_immediate(0xD6, 0x30), 0xF0); // SUB d8 (sets carry)
+        assert_eq!(execute_immediate(0xCE, 0x0F), 0x00); // ADC A,d8
+        assert_eq!(execute_immediate(0xDE, 0x00), 0xFF); // SBC A,d8 (carry in)
+        assert_eq!(execute_immediate(0xE6, 0x0F), 0x0F); // AND d8
+        assert_eq!(execute_immediate(0xF6, 0x30), 0x3F); // OR d8
+        assert_eq!(execute_immediate(0xEE, 0x3F), 0x00); // XOR d8
+        assert_eq!(execute_immediate(0xFE, 0x01), 0x00); // CP d8
+        assert!(cpu.registers.flags.check(Flag::Carry));
+    }
+
+    #[test]
+    fn test_jumps() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+
+        execute(&mut cpu, &mmu, decode(0xC3, 0x50, 0x01)); // JP a16
+        assert_eq!(cpu.registers.pc, 0x0150);
+
+        execute(&mut cpu, &mmu, decode(0x18, 0xFE, 0x00)); // JR -2
+        assert_eq!(cpu.registers.pc, 0x014E);
+
+        execute(&mut cpu, &mmu, decode(0x18, 0x10, 0x00));
+        assert_eq!(cpu.registers.pc, 0x015E);
+
+        cpu.registers.set_hl(0xC000);
+        execute_opcode(&mut cpu, &mmu, 0xE9); // JP (HL)
+        assert_eq!(cpu.registers.pc, 0xC000);
+    }
+
+    #[test]
+    fn test_conditional_jumps() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+        cpu.registers.pc = 0x0100;
+
+        cpu.registers.flags.set(Flag::Zero);
+        execute(&mut cpu, &mmu, decode(0x20, 0x05, 0x00)); // JR NZ
+        assert_eq!(cpu.registers.pc, 0x0100);
+        assert_eq!(cpu.extra_cycles, 0);
+
+        execute(&mut cpu, &mmu, decode(0x28, 0x05, 0x00)); // JR Z
+        assert_eq!(cpu.registers.pc, 0x0105);
+        assert_eq!(cpu.extra_cycles, 1);
+
+        cpu.extra_cycles = 0;
+        execute(&mut cpu, &mmu, decode(0xDA, 0x00, 0x20)); // JP C
+        assert_eq!(cpu.registers.pc, 0x0105);
+        assert_eq!(cpu.extra_cycles, 0);
+
+        execute(&mut cpu, &mmu, decode(0xD2, 0x00, 0x20)); // JP NC
+        assert_eq!(cpu.registers.pc, 0x2000);
+        assert_eq!(cpu.extra_cycles, 1);
+    }
+
+    #[test]
+    fn test_call_and_return() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+        cpu.registers.sp = 0xFFFE;
+        cpu.registers.pc = 0x0153;
+
+        execute(&mut cpu, &mmu, decode(0xCD, 0x00, 0x20)); // CALL a16
+        assert_eq!(cpu.registers.pc, 0x2000);
+        assert_eq!(cpu.registers.sp, 0xFFFC);
+        assert_eq!(mmu.read_byte(0xFFFD), Some(0x01));
+        assert_eq!(mmu.read_byte(0xFFFC), Some(0x53));
+
+        execute_opcode(&mut cpu, &mmu, 0xFF); // RST 38h
+        assert_eq!(cpu.registers.pc, 0x0038);
+        assert_eq!(cpu.registers.sp, 0xFFFA);
+
+        execute_opcode(&mut cpu, &mmu, 0xC9); // RET
+        assert_eq!(cpu.registers.pc, 0x2000);
+        execute_opcode(&mut cpu, &mmu, 0xD9); // RETI
+        assert_eq!(cpu.registers.pc, 0x0153);
+        assert_eq!(cpu.registers.sp, 0xFFFE);
+    }
+
+    #[test]
+    fn test_conditional_call_and_return() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+        cpu.registers.sp = 0xD000;
+        cpu.registers.pc = 0x0200;
+
+        execute(&mut cpu, &mmu, decode(0xDC, 0x00, 0x30)); // CALL C
+        assert_eq!(cpu.registers.pc, 0x0200);
+        assert_eq!(cpu.registers.sp, 0xD000);
+        assert_eq!(cpu.extra_cycles, 0);
+
+        execute(&mut cpu, &mmu, decode(0xD4, 0x00, 0x30)); // CALL NC
+        assert_eq!(cpu.registers.pc, 0x3000);
+        assert_eq!(cpu.registers.sp, 0xCFFE);
+        assert_eq!(cpu.extra_cycles, 3);
+
+        cpu.extra_cycles = 0;
+        execute_opcode(&mut cpu, &mmu, 0xC8); // RET Z
+        assert_eq!(cpu.registers.pc, 0x3000);
+        assert_eq!(cpu.extra_cycles, 0);
+
+        execute_opcode(&mut cpu, &mmu, 0xC0); // RET NZ
+        assert_eq!(cpu.registers.pc, 0x0200);
+        assert_eq!(cpu.registers.sp, 0xD000);
+        assert_eq!(cpu.extra_cycles, 3);
+    }
+
+    #[test]
+    fn test_push_and_pop() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+        cpu.registers.sp = 0xFFFE;
+        cpu.registers.b = 0x12;
+        cpu.registers.c = 0x34;
+
+        execute_opcode(&mut cpu, &mmu, 0xC5); // PUSH BC
+        assert_eq!(cpu.registers.sp, 0xFFFC);
+        assert_eq!(mmu.read_byte(0xFFFD), Some(0x12));
+        assert_eq!(mmu.read_byte(0xFFFC), Some(0x34));
+
+        execute_opcode(&mut cpu, &mmu, 0xD1); // POP DE
+        assert_eq!(cpu.registers.sp, 0xFFFE);
+        assert_eq!((cpu.registers.d, cpu.registers.e), (0x12, 0x34));
+    }
+
+    #[test]
+    fn test_load_word_high_byte_is_named_first() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+        mmu.write_byte(0xC123, 0x99);
+
+        execute(&mut cpu, &mmu, decode(0x21, 0x23, 0xC1)); // LD HL,C123
+        assert_eq!((cpu.registers.h, cpu.registers.l), (0xC1, 0x23));
+
+        execute(&mut cpu, &mmu, decode(0x01, 0x23, 0xC1)); // LD BC,C123
+        execute_opcode(&mut cpu, &mmu, 0x0A); // LD A,(BC)
+        assert_eq!(cpu.registers.a, 0x99);
+    }
+
+    #[test]
+    fn test_pop_af_masks_low_flag_bits() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+        cpu.registers.sp = 0xFFFC;
+        mmu.write_byte(0xFFFD, 0xAB);
+        mmu.write_byte(0xFFFC, 0xFF);
+
+        execute_opcode(&mut cpu, &mmu, 0xF1); // POP AF
+        assert_eq!(cpu.registers.a, 0xAB);
+        assert_eq!(cpu.registers.flags.bits(), 0xF0);
+
+        execute_opcode(&mut cpu, &mmu, 0xF5); // PUSH AF
+        assert_eq!(mmu.read_byte(0xFFFC), Some(0xF0));
+    }
+
+    #[test]
+    fn test_high_page_loads() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+
+        cpu.registers.a = 0x91;
+        execute(&mut cpu, &mmu, decode(0xE0, 0x40, 0x00)); // LDH (a8),A
+        assert_eq!(mmu.read_byte(0xFF40), Some(0x91));
+
+        cpu.registers.c = 0x80;
+        execute_opcode(&mut cpu, &mmu, 0xE2); // LD (C),A
+        assert_eq!(mmu.read_byte(0xFF80), Some(0x91));
+
+        mmu.write_byte(0xFF45, 0x90);
+        execute(&mut cpu, &mmu, decode(0xF0, 0x45, 0x00)); // LDH A,(a8)
+        assert_eq!(cpu.registers.a, 0x90);
+
+        mmu.write_byte(0xFFFE, 0x12);
+        cpu.registers.c = 0xFE;
+        execute_opcode(&mut cpu, &mmu, 0xF2); // LD A,(C)
+        assert_eq!(cpu.registers.a, 0x12);
+    }
+
+    #[test]
+    fn test_hl_increment_and_decrement_loads() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+        cpu.registers.set_hl(0xC000);
+        cpu.registers.a = 0x11;
+
+        execute_opcode(&mut cpu, &mmu, 0x22); // LD (HL+),A
+        assert_eq!(mmu.read_byte(0xC000), Some(0x11));
+        assert_eq!(cpu.registers.hl(), 0xC001);
+
+        execute_opcode(&mut cpu, &mmu, 0x32); // LD (HL-),A
+        assert_eq!(mmu.read_byte(0xC001), Some(0x11));
+        assert_eq!(cpu.registers.hl(), 0xC000);
+
+        mmu.write_byte(0xC000, 0x22);
+        execute_opcode(&mut cpu, &mmu, 0x2A); // LD A,(HL+)
+        assert_eq!(cpu.registers.a, 0x22);
+        assert_eq!(cpu.registers.hl(), 0xC001);
+
+        execute_opcode(&mut cpu, &mmu, 0x3A); // LD A,(HL-)
+        assert_eq!(cpu.registers.a, 0x11);
+        assert_eq!(cpu.registers.hl(), 0xC000);
+    }
+
+    #[test]
+    fn test_hl_increment_wraps() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+
+        cpu.registers.set_hl(0xFFFF);
+        execute_opcode(&mut cpu, &mmu, 0x22); // LD (HL+),A
+        assert_eq!(cpu.registers.hl(), 0x0000);
+
+        execute_opcode(&mut cpu, &mmu, 0x32); // LD (HL-),A
+        assert_eq!(cpu.registers.hl(), 0xFFFF);
+    }
+
+    #[test]
+    fn test_sp_loads() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+
+        execute(&mut cpu, &mmu, decode(0x31, 0xF8, 0xFF)); // LD SP,d16
+        assert_eq!(cpu.registers.sp, 0xFFF8);
+
+        execute(&mut cpu, &mmu, decode(0x08, 0x00, 0xC1)); // LD (a16),SP
+        assert_eq!(mmu.read_byte(0xC100), Some(0xF8));
+        assert_eq!(mmu.read_byte(0xC101), Some(0xFF));
+
+        cpu.registers.set_hl(0xD000);
+        execute_opcode(&mut cpu, &mmu, 0xF9); // LD SP,HL
+        assert_eq!(cpu.registers.sp, 0xD000);
+    }
+
+    #[test]
+    fn test_sp_offset_flags() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+
+        // carries are computed on the low byte, even for negative offsets
+        cpu.registers.sp = 0x00FF;
+        cpu.registers.flags.set(Flag::Zero);
+        execute(&mut cpu, &mmu, decode(0xE8, 0x01, 0x00)); // ADD SP,r8
+        assert_eq!(cpu.registers.sp, 0x0100);
+        assert_eq!(flags(&cpu), [false, false, true, true]);
+
+        cpu.registers.sp = 0x0100;
+        execute(&mut cpu, &mmu, decode(0xE8, 0xFF, 0x00)); // -1
+        assert_eq!(cpu.registers.sp, 0x00FF);
+        assert_eq!(flags(&cpu), [false, false, false, false]);
+
+        cpu.registers.sp = 0xFFFF;
+        execute(&mut cpu, &mmu, decode(0xE8, 0xFF, 0x00)); // -1
+        assert_eq!(cpu.registers.sp, 0xFFFE);
+        assert_eq!(flags(&cpu), [false, false, true, true]);
+
+        cpu.registers.sp = 0x0008;
+        execute(&mut cpu, &mmu, decode(0xF8, 0x08, 0x00)); // LD HL,SP+r8
+        assert_eq!(cpu.registers.hl(), 0x0010);
+        assert_eq!(cpu.registers.sp, 0x0008);
+        assert_eq!(flags(&cpu), [false, false, true, false]);
+
+        cpu.registers.sp = 0x0080;
+        execute(&mut cpu, &mmu, decode(0xF8, 0x80, 0x00)); // -128
+        assert_eq!(cpu.registers.hl(), 0x0000);
+        assert_eq!(flags(&cpu), [false, false, false, true]);
+    }
+
+    fn bcd(value: u8) -> u8 {
+        (value / 10) << 4 | (value % 10)
+    }
+
+    #[test]
+    fn test_daa_after_bcd_arithmetic() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+
+        for x in 0..100u8 {
+            for y in 0..100u8 {
+                for carry_in in [false, true] {
+                    let carry = carry_in as u8;
+
+                    // ADD/ADC
+                    cpu.registers.flags.clear();
+                    if carry_in {
+                        cpu.registers.flags.set(Flag::Carry);
+                    }
+                    cpu.registers.a = bcd(x);
+                    cpu.registers.b = bcd(y);
+                    execute_opcode(&mut cpu, &mmu, 0x88); // ADC A,B
+                    execute_opcode(&mut cpu, &mmu, 0x27); // DAA
+
+                    let sum = x as u16 + y as u16 + carry as u16;
+                    let result = (sum % 100) as u8;
+                    assert_eq!(cpu.registers.a, bcd(result), "{x} + {y} + {carry}");
+                    assert_eq!(flags(&cpu), [result == 0, false, false, sum >= 100]);
+
+                    // SUB/SBC
+                    cpu.registers.flags.clear();
+                    if carry_in {
+                        cpu.registers.flags.set(Flag::Carry);
+                    }
+                    cpu.registers.a = bcd(x);
+                    execute_opcode(&mut cpu, &mmu, 0x98); // SBC A,B
+                    execute_opcode(&mut cpu, &mmu, 0x27); // DAA
+
+                    let difference = (x as i16 - y as i16 - carry as i16).rem_euclid(100);
+                    let borrow = (x as i16) < y as i16 + carry as i16;
+                    assert_eq!(
+                        cpu.registers.a,
+                        bcd(difference as u8),
+                        "{x} - {y} - {carry}"
+                    );
+                    assert_eq!(flags(&cpu), [difference == 0, true, false, borrow]);
+                }
+            }
+        }
+    }
+
+    /// (N, C, H, upper nibble range, lower nibble range, adjustment, C after)
+    type DaaRow = (bool, bool, bool, (u8, u8), (u8, u8), u8, bool);
+
+    /// The DAA table from the Game Boy Programming Manual.
+    const DAA_TABLE: [DaaRow; 13] = [
+        (false, false, false, (0x0, 0x9), (0x0, 0x9), 0x00, false),
+        (false, false, false, (0x0, 0x8), (0xA, 0xF), 0x06, false),
+        (false, false, true, (0x0, 0x9), (0x0, 0x3), 0x06, false),
+        (false, false, false, (0xA, 0xF), (0x0, 0x9), 0x60, true),
+        (false, false, false, (0x9, 0xF), (0xA, 0xF), 0x66, true),
+        (false, false, true, (0xA, 0xF), (0x0, 0x3), 0x66, true),
+        (false, true, false, (0x0, 0x2), (0x0, 0x9), 0x60, true),
+        (false, true, false, (0x0, 0x2), (0xA, 0xF), 0x66, true),
+        (false, true, true, (0x0, 0x3), (0x0, 0x3), 0x66, true),
+        (true, false, false, (0x0, 0x9), (0x0, 0x9), 0x00, false),
+        (true, false, true, (0x0, 0x8), (0x6, 0xF), 0xFA, false),
+        (true, true, false, (0x7, 0xF), (0x0, 0x9), 0xA0, true),
+        (true, true, true, (0x6, 0xF), (0x6, 0xF), 0x9A, true),
+    ];
+
+    #[test]
+    fn test_daa_table() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+
+        for a in 0..=255u8 {
+            for (subtract, carry, half_carry, upper, lower, adjustment, carry_out) in DAA_TABLE {
+                if !(upper.0..=upper.1).contains(&(a >> 4))
+                    || !(lower.0..=lower.1).contains(&(a & 0x0F))
+                {
+                    continue;
+                }
+
+                cpu.registers.flags.clear();
+                for (flag, set) in [
+                    (Flag::Subtract, subtract),
+                    (Flag::Carry, carry),
+                    (Flag::HalfCarry, half_carry),
+                ] {
+                    if set {
+                        cpu.registers.flags.set(flag);
+                    }
+                }
+                cpu.registers.a = a;
+                execute_opcode(&mut cpu, &mmu, 0x27);
+
+                let expected = a.wrapping_add(adjustment);
+                assert_eq!(
+                    cpu.registers.a, expected,
+                    "A={a:02X} N={subtract} C={carry} H={half_carry}"
+                );
+                assert_eq!(flags(&cpu), [expected == 0, subtract, false, carry_out]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_accumulator_rotates() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+
+        cpu.registers.a = 0b1000_0001;
+        execute_opcode(&mut cpu, &mmu, 0x07); // RLCA
+        assert_eq!(cpu.registers.a, 0b0000_0011);
+        assert_eq!(flags(&cpu), [false, false, false, true]);
+
+        execute_opcode(&mut cpu, &mmu, 0x0F); // RRCA
+        assert_eq!(cpu.registers.a, 0b1000_0001);
+        assert_eq!(flags(&cpu), [false, false, false, true]);
+
+        // the carry flag is rotated in, and bit 7 rotated out
+        execute_opcode(&mut cpu, &mmu, 0x17); // RLA
+        assert_eq!(cpu.registers.a, 0b0000_0011);
+        assert_eq!(flags(&cpu), [false, false, false, true]);
+
+        cpu.registers.a = 0b0000_0010;
+        execute_opcode(&mut cpu, &mmu, 0x1F); // RRA
+        assert_eq!(cpu.registers.a, 0b1000_0001);
+        assert_eq!(flags(&cpu), [false, false, false, false]);
+    }
+
+    #[test]
+    fn test_accumulator_rotates_clear_zero() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+
+        for opcode in [0x07, 0x0F, 0x17, 0x1F] {
+            cpu.registers.a = 0x00;
+            cpu.registers.flags.clear();
+            cpu.registers.flags.set(Flag::Zero);
+            cpu.registers.flags.set(Flag::Subtract);
+            cpu.registers.flags.set(Flag::HalfCarry);
+
+            execute_opcode(&mut cpu, &mmu, opcode);
+            assert_eq!(cpu.registers.a, 0x00);
+            assert_eq!(flags(&cpu), [false, false, false, false]);
+        }
+    }
+
+    /// Execute the CB-prefixed instruction for `opcode`.
+    fn execute_prefixed(cpu: &mut SM83, bus: &impl Bus, opcode: u8) {
+        execute(cpu, bus, decode_prefixed(opcode));
+    }
+
+    #[test]
+    fn test_prefixed_shifts() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+
+        // opcode (on B), value, carry in, result, carry out
+        let cases = [
+            (0x00, 0b1000_0001, false, 0b0000_0011, true), // RLC
+            (0x08, 0b0000_0001, false, 0b1000_0000, true), // RRC
+            (0x10, 0b1000_0000, true, 0b0000_0001, true),  // RL
+            (0x18, 0b0000_0010, true, 0b1000_0001, false), // RR
+            (0x20, 0b1100_0000, true, 0b1000_0000, true),  // SLA
+            (0x28, 0b1000_0011, false, 0b1100_0001, true), // SRA
+            (0x30, 0b1010_0101, true, 0b0101_1010, false), // SWAP
+            (0x38, 0b1000_0011, false, 0b0100_0001, true), // SRL
+        ];
+        for (opcode, value, carry_in, result, carry_out) in cases {
+            cpu.registers.b = value;
+            cpu.registers.flags.set_bits(0x60 | (carry_in as u8) << 4);
+
+            execute_prefixed(&mut cpu, &mmu, opcode);
+            assert_eq!(cpu.registers.b, result, "CB {opcode:02X}");
+            assert_eq!(
+                flags(&cpu),
+                [false, false, false, carry_out],
+                "CB {opcode:02X}"
+            );
+        }
+
+        // unlike RLCA and friends, these set Z
+        cpu.registers.b = 0x80;
+        execute_prefixed(&mut cpu, &mmu, 0x20); // SLA B
+        assert_eq!(flags(&cpu), [true, false, false, true]);
+    }
+
+    #[test]
+    fn test_prefixed_bits() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+        cpu.registers.set_hl(0xC000);
+        mmu.write_byte(0xC000, 0b0100_0000);
+        cpu.registers.flags.set(Flag::Carry);
+
+        execute_prefixed(&mut cpu, &mmu, 0x76); // BIT 6,(HL)
+        assert_eq!(flags(&cpu), [false, false, true, true]);
+        execute_prefixed(&mut cpu, &mmu, 0x7E); // BIT 7,(HL)
+        assert_eq!(flags(&cpu), [true, false, true, true]);
+
+        // RES and SET leave the flags alone
+        execute_prefixed(&mut cpu, &mmu, 0xB6); // RES 6,(HL)
+        execute_prefixed(&mut cpu, &mmu, 0xC6); // SET 0,(HL)
+        execute_prefixed(&mut cpu, &mmu, 0xFF); // SET 7,A
+        assert_eq!(mmu.read_byte(0xC000), Some(0b0000_0001));
+        assert_eq!(cpu.registers.a, 0b1000_0000);
+        assert_eq!(flags(&cpu), [true, false, true, true]);
+    }
+
+    #[test]
+    fn test_decrement_flags() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+
+        cpu.registers.c = 0x01;
+        execute_opcode(&mut cpu, &mmu, 0x0D); // DEC C
+        assert_eq!(cpu.registers.c, 0x00);
+        assert_eq!(flags(&cpu), [true, true, false, false]);
+
+        // borrowing from bit 4 sets H, and C is never touched
+        cpu.registers.flags.set(Flag::Carry);
+        execute_opcode(&mut cpu, &mmu, 0x0D);
+        assert_eq!(cpu.registers.c, 0xFF);
+        assert_eq!(flags(&cpu), [false, true, true, true]);
+
+        cpu.registers.flags.clear();
+        execute_opcode(&mut cpu, &mmu, 0x0D);
+        assert_eq!(cpu.registers.c, 0xFE);
+        assert_eq!(flags(&cpu), [false, true, false, false]);
+    }
+
+    #[test]
+    fn test_decrement_hl_address() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+        cpu.registers.set_hl(0xC000);
+        mmu.write_byte(0xC000, 0x10);
+
+        execute_opcode(&mut cpu, &mmu, 0x35); // DEC (HL)
+        assert_eq!(mmu.read_byte(0xC000), Some(0x0F));
+        assert_eq!(flags(&cpu), [false, true, true, false]);
+    }
+
+    #[test]
+    fn test_sp_increment_and_decrement() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+        cpu.registers.sp = 0xFFFF;
+        cpu.registers.flags.set(Flag::Zero);
+
+        execute_opcode(&mut cpu, &mmu, 0x33); // INC SP
+        assert_eq!(cpu.registers.sp, 0x0000);
+        execute_opcode(&mut cpu, &mmu, 0x3B); // DEC SP
+        assert_eq!(cpu.registers.sp, 0xFFFF);
+        assert_eq!(flags(&cpu), [true, false, false, false]);
+    }
+
+    #[test]
+    fn test_16_bit_increment_wraps_without_flags() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+        cpu.registers.b = 0xFF;
+        cpu.registers.c = 0xFF;
+
+        execute_opcode(&mut cpu, &mmu, 0x03); // INC BC
+        assert_eq!((cpu.registers.b, cpu.registers.c), (0x00, 0x00));
+        assert_eq!(flags(&cpu), [false, false, false, false]);
+
+        execute_opcode(&mut cpu, &mmu, 0x0B); // DEC BC
+        assert_eq!((cpu.registers.b, cpu.registers.c), (0xFF, 0xFF));
+        assert_eq!(flags(&cpu), [false, false, false, false]);
+    }
+
+    #[test]
+    fn test_add_sp_to_hl() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+        cpu.registers.set_hl(0x8FFF);
+        cpu.registers.sp = 0x8001;
+
+        execute_opcode(&mut cpu, &mmu, 0x39); // ADD HL,SP
+        assert_eq!(cpu.registers.hl(), 0x1000);
+        assert_eq!(flags(&cpu), [false, false, true, true]);
+    }
+
+    #[test]
+    fn test_add_register_pair_to_hl() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+        cpu.registers.set_hl(0x0FFF);
+        cpu.registers.set_de(0x0001);
+        cpu.registers.flags.set(Flag::Zero);
+        cpu.registers.flags.set(Flag::Subtract);
+
+        execute_opcode(&mut cpu, &mmu, 0x19); // ADD HL,DE
+        assert_eq!(cpu.registers.hl(), 0x1000);
+        assert_eq!(flags(&cpu), [true, false, true, false]);
+
+        cpu.registers.set_bc(0xF000);
+        execute_opcode(&mut cpu, &mmu, 0x09); // ADD HL,BC
+        assert_eq!(cpu.registers.hl(), 0x0000);
+        assert_eq!(flags(&cpu), [true, false, false, true]);
+
+        cpu.registers.flags.clear();
+        cpu.registers.set_hl(0x8800);
+        execute_opcode(&mut cpu, &mmu, 0x29); // ADD HL,HL
+        assert_eq!(cpu.registers.hl(), 0x1000);
+        assert_eq!(flags(&cpu), [false, false, true, true]);
+    }
+
+    #[test]
+    fn test_increment_decrement_flag_table() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+        cpu.registers.set_hl(0xC000);
+
+        // (INC opcode, DEC opcode, location) for every 8-bit register
+        type Register = fn(&mut SM83) -> &mut u8;
+        let targets: [(u8, u8, Register); 7] = [
+            (0x04, 0x05, |cpu| &mut cpu.registers.b),
+            (0x0C, 0x0D, |cpu| &mut cpu.registers.c),
+            (0x14, 0x15, |cpu| &mut cpu.registers.d),
+            (0x1C, 0x1D, |cpu| &mut cpu.registers.e),
+            (0x24, 0x25, |cpu| &mut cpu.registers.h),
+            (0x2C, 0x2D, |cpu| &mut cpu.registers.l),
+            (0x3C, 0x3D, |cpu| &mut cpu.registers.a),
+        ];
+
+        for value in 0..=255u8 {
+            for carry in [false, true] {
+                for (increment, decrement, register) in targets {
+                    cpu.registers.flags.clear();
+                    if carry {
+                        cpu.registers.flags.set(Flag::Carry);
+                    }
+
+                    *register(&mut cpu) = value;
+                    execute_opcode(&mut cpu, &mmu, increment);
+                    let expected = value.wrapping_add(1);
+                    assert_eq!(*register(&mut cpu), expected);
+                    assert_eq!(
+                        flags(&cpu),
+                        [expected == 0, false, value & 0x0F == 0x0F, carry],
+                        "INC {value:02X}"
+                    );
+
+                    *register(&mut cpu) = value;
+                    execute_opcode(&mut cpu, &mmu, decrement);
+                    let expected = value.wrapping_sub(1);
+                    assert_eq!(*register(&mut cpu), expected);
+                    assert_eq!(
+                        flags(&cpu),
+                        [expected == 0, true, value & 0x0F == 0x00, carry],
+                        "DEC {value:02X}"
+                    );
+                }
+
+                // (HL)
+                cpu.registers.set_hl(0xC000);
+                mmu.write_byte(0xC000, value);
+                execute_opcode(&mut cpu, &mmu, 0x34);
+                assert_eq!(mmu.read_byte(0xC000), Some(value.wrapping_add(1)));
+                assert_eq!(flags(&cpu)[3], carry);
+
+                mmu.write_byte(0xC000, value);
+                execute_opcode(&mut cpu, &mmu, 0x35);
+                assert_eq!(mmu.read_byte(0xC000), Some(value.wrapping_sub(1)));
+                assert_eq!(flags(&cpu)[3], carry);
+            }
         }
     }
 }
@@ -0,0 +1,808 @@
+//! A deliberately naive SM83 interpreter, used to cross-check the decoder-driven
+//! one in [`super::opcodes`].
+//!
+//! Everything here is one big match over the opcode, written straight from
+//! the instruction set tables. The only things shared with the real
+//! implementation are the register bank and the MMU.
+
+use crate::{cpu::registers::SM83RegisterBank, mmu::MMU};
+
+const ZERO: u8 = 0b1000_0000;
+const SUBTRACT: u8 = 0b0100_0000;
+const HALF_CARRY: u8 = 0b0010_0000;
+const CARRY: u8 = 0b0001_0000;
+
+pub struct ReferenceCPU {
+    pub registers: SM83RegisterBank,
+}
+
+impl ReferenceCPU {
+    pub fn new(registers: SM83RegisterBank) -> Self {
+        ReferenceCPU { registers }
+    }
+
+    fn read(&self, mmu: &MMU, addr: u16) -> u8 {
+        mmu.read_byte(addr).expect("should be able to read byte")
+    }
+
+    fn fetch(&mut self, mmu: &MMU) -> u8 {
+        let value = self.read(mmu, self.registers.pc);
+        self.registers.pc = self.registers.pc.wrapping_add(1);
+
+        value
+    }
+
+    fn fetch_word(&mut self, mmu: &MMU) -> u16 {
+        let low = self.fetch(mmu);
+        let high = self.fetch(mmu);
+
+        u16::from_le_bytes([low, high])
+    }
+
+    fn flag(&self, mask: u8) -> bool {
+        self.registers.flags.bits() & mask != 0
+    }
+
+    fn set_flags(&mut self, zero: bool, subtract: bool, half_carry: bool, carry: bool) {
+        let mut value = 0;
+        if zero {
+            value |= ZERO;
+        }
+        if subtract {
+            value |= SUBTRACT;
+        }
+        if half_carry {
+            value |= HALF_CARRY;
+        }
+        if carry {
+            value |= CARRY;
+        }
+
+        self.registers.flags.set_bits(value);
+    }
+
+    /// B, C, D, E, H, L, (HL), A
+    fn reg(&self, mmu: &MMU, index: u8) -> u8 {
+        match index {
+            0 => self.registers.b,
+            1 => self.registers.c,
+            2 => self.registers.d,
+            3 => self.registers.e,
+            4 => self.registers.h,
+            5 => self.registers.l,
+            6 => self.read(mmu, self.registers.hl()),
+            7 => self.registers.a,
+            _ => unreachable!(),
+        }
+    }
+
+    fn set_reg(&mut self, mmu: &MMU, index: u8, value: u8) {
+        match index {
+            0 => self.registers.b = value,
+            1 => self.registers.c = value,
+            2 => self.registers.d = value,
+            3 => self.registers.e = value,
+            4 => self.registers.h = value,
+            5 => self.registers.l = value,
+            6 => mmu.write_byte(self.registers.hl(), value),
+            7 => self.registers.a = value,
+            _ => unreachable!(),
+        }
+    }
+
+    /// BC, DE, HL, SP
+    fn pair(&self, index: u8) -> u16 {
+        let registers = &self.registers;
+
+        match index {
+            0 => registers.bc(),
+            1 => registers.de(),
+            2 => registers.hl(),
+            3 => registers.sp,
+            _ => unreachable!(),
+        }
+    }
+
+    fn set_pair(&mut self, index: u8, value: u16) {
+        match index {
+            0 => self.registers.set_bc(value),
+            1 => self.registers.set_de(value),
+            2 => self.registers.set_hl(value),
+            3 => self.registers.sp = value,
+            _ => unreachable!(),
+        }
+    }
+
+    /// NZ, Z, NC, C
+    fn condition(&self, index: u8) -> bool {
+        match index {
+            0 => !self.flag(ZERO),
+            1 => self.flag(ZERO),
+            2 => !self.flag(CARRY),
+            3 => self.flag(CARRY),
+            _ => unreachable!(),
+        }
+    }
+
+    fn push(&mut self, mmu: &MMU, high: u8, low: u8) {
+        self.registers.sp = self.registers.sp.wrapping_sub(1);
+        mmu.write_byte(self.registers.sp, high);
+        self.registers.sp = self.registers.sp.wrapping_sub(1);
+        mmu.write_byte(self.registers.sp, low);
+    }
+
+    fn pop(&mut self, mmu: &MMU) -> (u8, u8) {
+        let low = self.read(mmu, self.registers.sp);
+        self.registers.sp = self.registers.sp.wrapping_add(1);
+        let high = self.read(mmu, self.registers.sp);
+        self.registers.sp = self.registers.sp.wrapping_add(1);
+
+        (high, low)
+    }
+
+    fn call(&mut self, mmu: &MMU, target: u16) {
+        let [low, high] = self.registers.pc.to_le_bytes();
+        self.push(mmu, high, low);
+        self.registers.pc = target;
+    }
+
+    /// ADD, ADC, SUB, SBC, AND, XOR, OR, CP
+    fn alu(&mut self, operation: u8, value: u8) {
+        let a = self.registers.a;
+        let carry = self.flag(CARRY) as u8;
+
+        match operation {
+            0 | 1 => {
+                let carry = if operation == 1 { carry } else { 0 };
+                let result = a as u16 + value as u16 + carry as u16;
+                let half = (a & 0xF) + (value & 0xF) + carry;
+
+                self.registers.a = result as u8;
+                self.set_flags(result as u8 == 0, false, half > 0xF, result > 0xFF);
+            }
+            2 | 3 | 7 => {
+                let carry = if operation == 3 { carry } else { 0 };
+                let result = a as i16 - value as i16 - carry as i16;
+                let half = (a & 0xF) as i16 - (value & 0xF) as i16 - carry as i16;
+
+                if operation != 7 {
+                    self.registers.a = result as u8;
+                }
+                self.set_flags(result as u8 == 0, true, half < 0, result < 0);
+            }
+            4 => {
+                self.registers.a = a & value;
+                self.set_flags(self.registers.a == 0, false, true, false);
+            }
+            5 => {
+                self.registers.a = a ^ value;
+                self.set_flags(self.registers.a == 0, false, false, false);
+            }
+            6 => {
+                self.registers.a = a | value;
+                self.set_flags(self.registers.a == 0, false, false, false);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// SP plus a signed offset, with the flags ADD SP,e and LD HL,SP+e set.
+    fn sp_plus(&mut self, offset: u8) -> u16 {
+        let sp = self.registers.sp;
+        let half = (sp & 0xF) + (offset as u16 & 0xF) > 0xF;
+        let carry = (sp & 0xFF) + offset as u16 > 0xFF;
+
+        self.set_flags(false, false, half, carry);
+        (sp as i32 + offset as i8 as i32) as u16
+    }
+
+    /// Execute the CB-prefixed instruction `opcode`, returning the number of
+    /// M-cycles taken (including the prefix).
+    fn prefixed(&mut self, mmu: &MMU, opcode: u8) -> u8 {
+        let y = (opcode >> 3) & 0b111;
+        let z = opcode & 0b111;
+        let value = self.reg(mmu, z);
+        let carry = self.flag(CARRY) as u8;
+
+        match opcode >> 6 {
+            // RLC / RRC / RL / RR / SLA / SRA / SWAP / SRL
+            0 => {
+                let (result, out) = match y {
+                    0 => (value.rotate_left(1), value >> 7),
+                    1 => (value.rotate_right(1), value & 1),
+                    2 => ((value << 1) | carry, value >> 7),
+                    3 => ((value >> 1) | (carry << 7), value & 1),
+                    4 => (value << 1, value >> 7),
+                    5 => ((value >> 1) | (value & 0x80), value & 1),
+                    6 => (value.rotate_left(4), 0),
+                    _ => (value >> 1, value & 1),
+                };
+                self.set_reg(mmu, z, result);
+                self.set_flags(result == 0, false, false, out != 0);
+                if z == 6 {
+                    4
+                } else {
+                    2
+                }
+            }
+            // BIT b,r
+            1 => {
+                self.set_flags((value >> y) & 1 == 0, false, true, carry != 0);
+                if z == 6 {
+                    3
+                } else {
+                    2
+                }
+            }
+            // RES b,r / SET b,r
+            operation => {
+                let result = match operation {
+                    2 => value & !(1 << y),
+                    _ => value | (1 << y),
+                };
+                self.set_reg(mmu, z, result);
+                if z == 6 {
+                    4
+                } else {
+                    2
+                }
+            }
+        }
+    }
+
+    /// Execute a single instruction, returning the number of M-cycles taken.
+    ///
+    /// Returns `None` (leaving PC where it was) for an opcode this doesn't
+    /// cover, so the caller can skip it.
+    pub fn step(&mut self, mmu: &MMU) -> Option<u8> {
+        let pc = self.registers.pc;
+        let opcode = self.fetch(mmu);
+        let y = (opcode >> 3) & 0b111;
+        let z = opcode & 0b111;
+        let p = y >> 1;
+
+        let cycles = match opcode {
+            // NOP
+            0x00 => 1,
+            // LD rr,d16
+            0x01 | 0x11 | 0x21 | 0x31 => {
+                let value = self.fetch_word(mmu);
+                self.set_pair(p, value);
+                3
+            }
+            // LD (BC),A / LD (DE),A / LD (HL+),A / LD (HL-),A
+            0x02 | 0x12 | 0x22 | 0x32 => {
+                let addr = self.pair(p.min(2));
+                mmu.write_byte(addr, self.registers.a);
+                match opcode {
+                    0x22 => self.registers.set_hl(addr.wrapping_add(1)),
+                    0x32 => self.registers.set_hl(addr.wrapping_sub(1)),
+                    _ => {}
+                }
+                2
+            }
+            // LD A,(BC) / LD A,(DE) / LD A,(HL+) / LD A,(HL-)
+            0x0A | 0x1A | 0x2A | 0x3A => {
+                let addr = self.pair(p.min(2));
+                self.registers.a = self.read(mmu, addr);
+                match opcode {
+                    0x2A => self.registers.set_hl(addr.wrapping_add(1)),
+                    0x3A => self.registers.set_hl(addr.wrapping_sub(1)),
+                    _ => {}
+                }
+                2
+            }
+            // INC rr / DEC rr
+            0x03 | 0x13 | 0x23 | 0x33 => {
+                self.set_pair(p, self.pair(p).wrapping_add(1));
+                2
+            }
+            0x0B | 0x1B | 0x2B | 0x3B => {
+                self.set_pair(p, self.pair(p).wrapping_sub(1));
+                2
+            }
+            // INC r / DEC r
+            0x04 | 0x0C | 0x14 | 0x1C | 0x24 | 0x2C | 0x34 | 0x3C => {
+                let value = self.reg(mmu, y);
+                let result = value.wrapping_add(1);
+                self.set_reg(mmu, y, result);
+                self.set_flags(result == 0, false, value & 0xF == 0xF, self.flag(CARRY));
+                if y == 6 {
+                    3
+                } else {
+                    1
+                }
+            }
+            0x05 | 0x0D | 0x15 | 0x1D | 0x25 | 0x2D | 0x35 | 0x3D => {
+                let value = self.reg(mmu, y);
+                let result = value.wrapping_sub(1);
+                self.set_reg(mmu, y, result);
+                self.set_flags(result == 0, true, value & 0xF == 0, self.flag(CARRY));
+                if y == 6 {
+                    3
+                } else {
+                    1
+                }
+            }
+            // LD r,d8
+            0x06 | 0x0E | 0x16 | 0x1E | 0x26 | 0x2E | 0x36 | 0x3E => {
+                let value = self.fetch(mmu);
+                self.set_reg(mmu, y, value);
+                if y == 6 {
+                    3
+                } else {
+                    2
+                }
+            }
+            // RLCA / RRCA / RLA / RRA
+            0x07 => {
+                let a = self.registers.a;
+                self.registers.a = a.rotate_left(1);
+                self.set_flags(false, false, false, a & 0x80 != 0);
+                1
+            }
+            0x0F => {
+                let a = self.registers.a;
+                self.registers.a = a.rotate_right(1);
+                self.set_flags(false, false, false, a & 0x01 != 0);
+                1
+            }
+            0x17 => {
+                let a = self.registers.a;
+                self.registers.a = (a << 1) | self.flag(CARRY) as u8;
+                self.set_flags(false, false, false, a & 0x80 != 0);
+                1
+            }
+            0x1F => {
+                let a = self.registers.a;
+                self.registers.a = (a >> 1) | ((self.flag(CARRY) as u8) << 7);
+                self.set_flags(false, false, false, a & 0x01 != 0);
+                1
+            }
+            // LD (a16),SP
+            0x08 => {
+                let addr = self.fetch_word(mmu);
+                let [low, high] = self.registers.sp.to_le_bytes();
+                mmu.write_byte(addr, low);
+                mmu.write_byte(addr.wrapping_add(1), high);
+                5
+            }
+            // ADD HL,rr
+            0x09 | 0x19 | 0x29 | 0x39 => {
+                let hl = self.registers.hl() as u32;
+                let value = self.pair(p) as u32;
+                let half = (hl & 0xFFF) + (value & 0xFFF) > 0xFFF;
+                self.registers.set_hl((hl + value) as u16);
+                self.set_flags(self.flag(ZERO), false, half, hl + value > 0xFFFF);
+                2
+            }
+            // JR e / JR cc,e
+            0x18 => {
+                let offset = self.fetch(mmu) as i8;
+                self.registers.pc = (self.registers.pc as i32 + offset as i32) as u16;
+                3
+            }
+            0x20 | 0x28 | 0x30 | 0x38 => {
+                let offset = self.fetch(mmu) as i8;
+                if self.condition(y - 4) {
+                    self.registers.pc = (self.registers.pc as i32 + offset as i32) as u16;
+                    3
+                } else {
+                    2
+                }
+            }
+            // DAA
+            0x27 => {
+                let mut a = self.registers.a;
+                let mut carry = self.flag(CARRY);
+                if !self.flag(SUBTRACT) {
+                    if carry || a > 0x99 {
+                        a = a.wrapping_add(0x60);
+                        carry = true;
+                    }
+                    if self.flag(HALF_CARRY) || a & 0xF > 0x9 {
+                        a = a.wrapping_add(0x6);
+                    }
+                } else {
+                    if carry {
+                        a = a.wrapping_sub(0x60);
+                    }
+                    if self.flag(HALF_CARRY) {
+                        a = a.wrapping_sub(0x6);
+                    }
+                }
+                self.registers.a = a;
+                self.set_flags(a == 0, self.flag(SUBTRACT), false, carry);
+                1
+            }
+            // CPL / SCF / CCF
+            0x2F => {
+                self.registers.a = !self.registers.a;
+                self.set_flags(self.flag(ZERO), true, true, self.flag(CARRY));
+                1
+            }
+            0x37 => {
+                self.set_flags(self.flag(ZERO), false, false, true);
+                1
+            }
+            0x3F => {
+                self.set_flags(self.flag(ZERO), false, false, !self.flag(CARRY));
+                1
+            }
+            // LD r,r'
+            0x40..=0x7F if opcode != 0x76 => {
+                let value = self.reg(mmu, z);
+                self.set_reg(mmu, y, value);
+                if y == 6 || z == 6 {
+                    2
+                } else {
+                    1
+                }
+            }
+            // ALU A,r
+            0x80..=0xBF => {
+                let value = self.reg(mmu, z);
+                self.alu(y, value);
+                if z == 6 {
+                    2
+                } else {
+                    1
+                }
+            }
+            // RET cc
+            0xC0 | 0xC8 | 0xD0 | 0xD8 => {
+                if self.condition(y) {
+                    let (high, low) = self.pop(mmu);
+                    self.registers.pc = u16::from_le_bytes([low, high]);
+                    5
+                } else {
+                    2
+                }
+            }
+            // POP rr
+            0xC1 | 0xD1 | 0xE1 => {
+                let (high, low) = self.pop(mmu);
+                match p {
+                    0 => (self.registers.b, self.registers.c) = (high, low),
+                    1 => (self.registers.d, self.registers.e) = (high, low),
+                    _ => (self.registers.h, self.registers.l) = (high, low),
+                }
+                3
+            }
+            0xF1 => {
+                let (high, low) = self.pop(mmu);
+                self.registers.a = high;
+                self.registers.flags.set_bits(low);
+                3
+            }
+            // JP cc,a16 / JP a16
+            0xC2 | 0xCA | 0xD2 | 0xDA => {
+                let target = self.fetch_word(mmu);
+                if self.condition(y) {
+                    self.registers.pc = target;
+                    4
+                } else {
+                    3
+                }
+            }
+            0xC3 => {
+                self.registers.pc = self.fetch_word(mmu);
+                4
+            }
+            // CALL cc,a16 / CALL a16
+            0xC4 | 0xCC | 0xD4 | 0xDC => {
+                let target = self.fetch_word(mmu);
+                if self.condition(y) {
+                    self.call(mmu, target);
+                    6
+                } else {
+                    3
+                }
+            }
+            0xCD => {
+                let target = self.fetch_word(mmu);
+                self.call(mmu, target);
+                6
+            }
+            // PUSH rr
+            0xC5 | 0xD5 | 0xE5 | 0xF5 => {
+                let (high, low) = match p {
+                    0 => (self.registers.b, self.registers.c),
+                    1 => (self.registers.d, self.registers.e),
+                    2 => (self.registers.h, self.registers.l),
+                    _ => (self.registers.a, self.registers.flags.bits()),
+                };
+                self.push(mmu, high, low);
+                4
+            }
+            // ALU A,d8
+            0xC6 | 0xCE | 0xD6 | 0xDE | 0xE6 | 0xEE | 0xF6 | 0xFE => {
+                let value = self.fetch(mmu);
+                self.alu(y, value);
+                2
+            }
+            // RST
+            0xC7 | 0xCF | 0xD7 | 0xDF | 0xE7 | 0xEF | 0xF7 | 0xFF => {
+                self.call(mmu, (y * 8) as u16);
+                4
+            }
+            // RET / RETI
+            0xC9 | 0xD9 => {
+                let (high, low) = self.pop(mmu);
+                self.registers.pc = u16::from_le_bytes([low, high]);
+                4
+            }
+            // LDH (a8),A / LDH A,(a8)
+            0xE0 => {
+                let addr = 0xFF00 + self.fetch(mmu) as u16;
+                mmu.write_byte(addr, self.registers.a);
+                3
+            }
+            0xF0 => {
+                let addr = 0xFF00 + self.fetch(mmu) as u16;
+                self.registers.a = self.read(mmu, addr);
+                3
+            }
+            // LD (C),A / LD A,(C)
+            0xE2 => {
+                mmu.write_byte(0xFF00 + self.registers.c as u16, self.registers.a);
+                2
+            }
+            0xF2 => {
+                self.registers.a = self.read(mmu, 0xFF00 + self.registers.c as u16);
+                2
+            }
+            // ADD SP,e / LD HL,SP+e / LD SP,HL
+            0xE8 => {
+                let offset = self.fetch(mmu);
+                self.registers.sp = self.sp_plus(offset);
+                4
+            }
+            0xF8 => {
+                let offset = self.fetch(mmu);
+                let value = self.sp_plus(offset);
+                self.registers.set_hl(value);
+                3
+            }
+            0xF9 => {
+                self.registers.sp = self.registers.hl();
+                2
+            }
+            // PREFIX CB
+            0xCB => {
+                let opcode = self.fetch(mmu);
+                self.prefixed(mmu, opcode)
+            }
+            // JP HL
+            0xE9 => {
+                self.registers.pc = self.registers.hl();
+                1
+            }
+            // LD (a16),A / LD A,(a16)
+            0xEA => {
+                let addr = self.fetch_word(mmu);
+                mmu.write_byte(addr, self.registers.a);
+                4
+            }
+            0xFA => {
+                let addr = self.fetch_word(mmu);
+                self.registers.a = self.read(mmu, addr);
+                4
+            }
+            _ => {
+                self.registers.pc = pc;
+                return None;
+            }
+        };
+
+        Some(cycles)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::{
+        instructions::{decode, length},
+        opcodes::execute,
+        sm83::SM83,
+    };
+
+    /// Opcodes the reference interpreter doesn't handle (yet), or that are
+    /// illegal. Streams are made without them.
+    const UNCOVERED: [u8; 15] = [
+        0x10, 0x76, 0xD3, 0xDB, 0xDD, 0xE3, 0xE4, 0xEB, 0xEC, 0xED, 0xF3, 0xF4, 0xFB, 0xFC, 0xFD,
+    ];
+
+    const STREAMS: usize = 200;
+    const STEPS: usize = 100;
+
+    /// A tiny xorshift generator, so the streams are reproducible.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u8 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 >> 32) as u8
+        }
+
+        fn word(&mut self) -> u16 {
+            u16::from_le_bytes([self.next(), self.next()])
+        }
+    }
+
+    /// Execute one instruction through the decoder, returning the M-cycles spent.
+    fn step_decoded(cpu: &mut SM83, mmu: &MMU) -> u8 {
+        let pc = cpu.registers.pc;
+        let operand = |offset| mmu.read_byte(pc.wrapping_add(offset)).unwrap();
+        let instruction = decode(operand(0), operand(1), operand(2));
+
+        cpu.registers.pc = pc.wrapping_add(instruction.length());
+        execute(cpu, mmu, instruction);
+
+        instruction.cycles() + std::mem::take(&mut cpu.extra_cycles)
+    }
+
+    /// Every address the next instruction could possibly touch.
+    fn touched_addresses(registers: &SM83RegisterBank, mmu: &MMU) -> Vec<u16> {
+        let pc = registers.pc;
+        let operand = |offset| mmu.read_byte(pc.wrapping_add(offset)).unwrap();
+        let a16 = u16::from_le_bytes([operand(1), operand(2)]);
+        let sp = registers.sp;
+
+        vec![
+            pc,
+            pc.wrapping_add(1),
+            pc.wrapping_add(2),
+            a16,
+            a16.wrapping_add(1),
+            registers.hl(),
+            registers.bc(),
+            registers.de(),
+            sp.wrapping_sub(2),
+            sp.wrapping_sub(1),
+            sp,
+            sp.wrapping_add(1),
+            0xFF00 | registers.c as u16,
+            0xFF00 | operand(1) as u16,
+        ]
+    }
+
+    fn random_registers(rng: &mut Rng) -> SM83RegisterBank {
+        let mut registers = SM83RegisterBank::new();
+        registers.a = rng.next();
+        registers.b = rng.next();
+        registers.c = rng.next();
+        registers.d = rng.next();
+        registers.e = rng.next();
+        registers.h = rng.next();
+        registers.l = rng.next();
+        registers.flags.set_bits(rng.next());
+        registers.sp = rng.word();
+        registers.pc = 0xC000 | (rng.word() & 0x0FFF);
+
+        registers
+    }
+
+    /// Lay out a stream of random (covered) instructions starting at `addr`.
+    fn write_stream(rng: &mut Rng, mmus: [&MMU; 2], mut addr: u16) {
+        for _ in 0..STEPS {
+            let opcode = loop {
+                let opcode = rng.next();
+                if !UNCOVERED.contains(&opcode) {
+                    break opcode;
+                }
+            };
+
+            let mut bytes = vec![opcode];
+            for _ in 1..length(opcode) {
+                bytes.push(rng.next());
+            }
+
+            for byte in bytes {
+                for mmu in mmus {
+                    mmu.write_byte(addr, byte);
+                }
+                addr += 1;
+            }
+        }
+    }
+
+    /// Random streams hit each CB-prefixed instruction less than once, so
+    /// every one of them gets a run of its own.
+    #[test]
+    fn test_differential_prefixed() {
+        let mut rng = Rng(0xCB0F_F1CE_D00D_5EED);
+
+        for opcode in 0..=255u8 {
+            for _ in 0..16 {
+                let decoded_mmu = MMU::new();
+                let reference_mmu = MMU::new();
+                let mut registers = random_registers(&mut rng);
+                registers.set_hl(0xD000 | (rng.word() & 0x0FFF));
+                let value = rng.next();
+                for mmu in [&decoded_mmu, &reference_mmu] {
+                    mmu.write_byte(registers.pc, 0xCB);
+                    mmu.write_byte(registers.pc + 1, opcode);
+                    mmu.write_byte(registers.hl(), value);
+                }
+
+                let mut cpu = SM83::new();
+                cpu.registers = registers.clone();
+                let mut reference = ReferenceCPU::new(registers.clone());
+
+                let context = format!("CB {opcode:02X}, (HL) {value:02X}, before {registers:?}");
+                let cycles = step_decoded(&mut cpu, &decoded_mmu);
+                assert_eq!(
+                    Some(cycles),
+                    reference.step(&reference_mmu),
+                    "cycles diverged: {context}"
+                );
+                assert_eq!(
+                    cpu.registers, reference.registers,
+                    "registers diverged: {context}"
+                );
+                assert_eq!(
+                    decoded_mmu.read_byte(registers.hl()),
+                    reference_mmu.read_byte(registers.hl()),
+                    "(HL) diverged: {context}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_differential_random_streams() {
+        let mut rng = Rng(0x5EED_CAFE_F00D_BEEF);
+
+        for stream in 0..STREAMS {
+            let decoded_mmu = MMU::new();
+            let reference_mmu = MMU::new();
+            let registers = random_registers(&mut rng);
+            write_stream(&mut rng, [&decoded_mmu, &reference_mmu], registers.pc);
+
+            let mut cpu = SM83::new();
+            cpu.registers = registers.clone();
+            let mut reference = ReferenceCPU::new(registers);
+
+            for step in 0..STEPS {
+                let before = reference.registers.clone();
+                let addresses = touched_addresses(&before, &reference_mmu);
+
+                let opcode = reference_mmu.read_byte(before.pc).unwrap();
+                let context =
+                    format!("stream {stream}, step {step}, opcode {opcode:02X}, before {before:?}");
+
+                // streams are written without these, so reaching one is a
+                // gap in coverage worth hearing about
+                let expected_cycles = reference
+                    .step(&reference_mmu)
+                    .unwrap_or_else(|| panic!("reached an uncovered opcode: {context}"));
+                let cycles = step_decoded(&mut cpu, &decoded_mmu);
+
+                assert_eq!(cycles, expected_cycles, "cycles diverged: {context}");
+                assert_eq!(
+                    cpu.registers, reference.registers,
+                    "registers diverged: {context}"
+                );
+                for addr in addresses {
+                    assert_eq!(
+                        decoded_mmu.read_byte(addr),
+                        reference_mmu.read_byte(addr),
+                        "memory at {addr:04X} diverged: {context}"
+                    );
+                }
+            }
+
+            for addr in 0x0000..=0xFFFF {
+                assert_eq!(
+                    decoded_mmu.read_byte(addr),
+                    reference_mmu.read_byte(addr),
+                    "memory at {addr:04X} diverged by the end of stream {stream}"
+                );
+            }
+        }
+    }
+}
@@ -1,6 +1,8 @@
 use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not};
 
-#[derive(Debug, Clone)]
+use crate::{cpu::instructions::Condition, model::Model};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct SM83RegisterBank {
     // 8 bit
     // general purpose
@@ -25,13 +27,51 @@ pub struct SM83RegisterBank {
 }
 
 impl SM83RegisterBank {
-    /// Helper function to get the value of a 16-bit register.
-    pub fn combined(&self, first: u8, second: u8) -> u16 {
-        u16::from_le_bytes([first, second])
+    /// Combine two 8-bit registers into the value of a 16-bit pair.
+    ///
+    /// The register named first is the high byte (H in HL, A in AF).
+    pub fn combined(&self, high: u8, low: u8) -> u16 {
+        u16::from_be_bytes([high, low])
+    }
+
+    /// Split the value of a 16-bit pair into its high and low bytes, the
+    /// inverse of [`combined`](Self::combined).
+    pub fn split(&self, value: u16) -> [u8; 2] {
+        value.to_be_bytes()
+    }
+
+    pub fn af(&self) -> u16 {
+        self.combined(self.a, self.flags.bits())
+    }
+
+    /// Set A and F. The low nibble of F is dropped, since it doesn't exist.
+    pub fn set_af(&mut self, value: u16) {
+        let [high, low] = self.split(value);
+
+        self.a = high;
+        self.flags.set_bits(low);
+    }
+
+    pub fn bc(&self) -> u16 {
+        self.combined(self.b, self.c)
+    }
+
+    pub fn set_bc(&mut self, value: u16) {
+        let [high, low] = self.split(value);
+
+        self.b = high;
+        self.c = low;
+    }
+
+    pub fn de(&self) -> u16 {
+        self.combined(self.d, self.e)
     }
 
-    pub fn split(&mut self, value: u16) -> [u8; 2] {
-        value.to_le_bytes()
+    pub fn set_de(&mut self, value: u16) {
+        let [high, low] = self.split(value);
+
+        self.d = high;
+        self.e = low;
     }
 
     pub fn hl(&self) -> u16 {
@@ -39,18 +79,19 @@ impl SM83RegisterBank {
     }
 
     pub fn set_hl(&mut self, value: u16) {
-        let [first, second] = self.split(value);
+        let [high, low] = self.split(value);
 
-        self.h = first;
-        self.l = second;
+        self.h = high;
+        self.l = low;
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct FlagRegister {
     value: u8,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Flag {
     Zero,
     Subtract,
@@ -149,16 +190,32 @@ impl PartialEq<Flag> for u8 {
     }
 }
 
+impl Default for FlagRegister {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl FlagRegister {
     pub fn new() -> Self {
         FlagRegister { value: 0b0000_0000 }
     }
 
     /// Check if a given bit is set.
-    pub fn check(&mut self, flag: Flag) -> bool {
+    pub fn check(&self, flag: Flag) -> bool {
         (flag.value() & self.value) == flag
     }
 
+    /// Whether a branch condition holds.
+    pub fn satisfies(&self, condition: Condition) -> bool {
+        match condition {
+            Condition::NotZero => !self.check(Flag::Zero),
+            Condition::Zero => self.check(Flag::Zero),
+            Condition::NotCarry => !self.check(Flag::Carry),
+            Condition::Carry => self.check(Flag::Carry),
+        }
+    }
+
     /// Set a given bit.
     pub fn set(&mut self, flag: Flag) {
         self.value |= flag;
@@ -169,10 +226,51 @@ impl FlagRegister {
         self.value &= !flag;
     }
 
+    /// Set a given bit if `condition` holds, and clear it otherwise.
+    pub fn set_if(&mut self, flag: Flag, condition: bool) {
+        if condition {
+            self.set(flag);
+        } else {
+            self.unset(flag);
+        }
+    }
+
+    /// Flip a given bit.
+    pub fn toggle(&mut self, flag: Flag) {
+        self.value ^= flag;
+    }
+
+    /// Overwrite all four flags at once, in Z/N/H/C order.
+    pub fn assign(&mut self, zero: bool, subtract: bool, half_carry: bool, carry: bool) {
+        self.value = (zero as u8) << 7
+            | (subtract as u8) << 6
+            | (half_carry as u8) << 5
+            | (carry as u8) << 4;
+    }
+
     /// Clear all flags.
     pub fn clear(&mut self) {
         self.value = 0b0000_0000;
     }
+
+    /// Get the raw value of the register.
+    pub fn bits(&self) -> u8 {
+        self.value
+    }
+
+    /// Overwrite the raw value of the register.
+    ///
+    /// The low nibble of F doesn't exist on the SM83, so it always reads
+    /// back as zero.
+    pub fn set_bits(&mut self, value: u8) {
+        self.value = value & 0xF0;
+    }
+}
+
+impl Default for SM83RegisterBank {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SM83RegisterBank {
@@ -192,4 +290,118 @@ impl SM83RegisterBank {
             t: 0,
         }
     }
+
+    /// The registers as the boot ROM leaves them, just before jumping to the
+    /// cartridge at 0x0100.
+    ///
+    /// On DMG and MGB, H and C depend on the header checksum; they are set
+    /// here, as they are for any cartridge with a nonzero checksum.
+    pub fn post_boot(model: Model) -> Self {
+        let (af, bc, de, hl) = match model {
+            Model::Dmg0 => (0x0100, 0xFF13, 0x00C1, 0x8403),
+            Model::Dmg => (0x01B0, 0x0013, 0x00D8, 0x014D),
+            Model::Mgb => (0xFFB0, 0x0013, 0x00D8, 0x014D),
+            Model::Sgb => (0x0100, 0x0014, 0x0000, 0xC060),
+            Model::Sgb2 => (0xFF00, 0x0014, 0x0000, 0xC060),
+            Model::Cgb => (0x1180, 0x0000, 0xFF56, 0x000D),
+            Model::Agb => (0x1100, 0x0100, 0xFF56, 0x000D),
+        };
+
+        let mut registers = Self::new();
+        registers.set_af(af);
+        registers.set_bc(bc);
+        registers.set_de(de);
+        registers.set_hl(hl);
+        registers.sp = 0xFFFE;
+        registers.pc = 0x0100;
+
+        registers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flag_helpers() {
+        let mut flags = FlagRegister::new();
+
+        flags.set_if(Flag::Zero, true);
+        flags.set_if(Flag::Carry, false);
+        assert_eq!(flags.bits(), 0b1000_0000);
+
+        flags.toggle(Flag::Carry);
+        flags.toggle(Flag::Zero);
+        assert_eq!(flags.bits(), 0b0001_0000);
+
+        flags.assign(true, false, true, false);
+        assert_eq!(flags.bits(), 0b1010_0000);
+    }
+
+    #[test]
+    fn test_pair_byte_order() {
+        let mut registers = SM83RegisterBank::new();
+        registers.set_bc(0x1234);
+        registers.set_de(0x5678);
+        registers.set_hl(0x9ABC);
+        registers.set_af(0xDEF0);
+
+        assert_eq!((registers.b, registers.c), (0x12, 0x34));
+        assert_eq!((registers.d, registers.e), (0x56, 0x78));
+        assert_eq!((registers.h, registers.l), (0x9A, 0xBC));
+        assert_eq!((registers.a, registers.flags.bits()), (0xDE, 0xF0));
+
+        assert_eq!(registers.bc(), 0x1234);
+        assert_eq!(registers.de(), 0x5678);
+        assert_eq!(registers.hl(), 0x9ABC);
+        assert_eq!(registers.af(), 0xDEF0);
+    }
+
+    #[test]
+    fn test_combined_and_split_round_trip() {
+        let registers = SM83RegisterBank::new();
+
+        assert_eq!(registers.combined(0xAB, 0xCD), 0xABCD);
+        assert_eq!(registers.split(0xABCD), [0xAB, 0xCD]);
+        for value in [0x0000, 0x00FF, 0xFF00, 0x1234, 0xFFFF] {
+            let [high, low] = registers.split(value);
+            assert_eq!(registers.combined(high, low), value);
+        }
+    }
+
+    #[test]
+    fn test_post_boot() {
+        let dmg = SM83RegisterBank::post_boot(Model::Dmg);
+        assert_eq!(
+            (dmg.af(), dmg.bc(), dmg.de(), dmg.hl()),
+            (0x01B0, 0x0013, 0x00D8, 0x014D)
+        );
+        assert_eq!((dmg.sp, dmg.pc), (0xFFFE, 0x0100));
+
+        // CGB games check A to detect the hardware
+        assert_eq!(SM83RegisterBank::post_boot(Model::Cgb).a, 0x11);
+        assert_eq!(SM83RegisterBank::post_boot(Model::Agb).b, 0x01);
+    }
+
+    #[test]
+    fn test_af_drops_low_flag_bits() {
+        let mut registers = SM83RegisterBank::new();
+        registers.set_af(0xFFFF);
+
+        assert_eq!(registers.a, 0xFF);
+        assert_eq!(registers.flags.bits(), 0xF0);
+        assert_eq!(registers.af(), 0xFFF0);
+    }
+
+    #[test]
+    fn test_satisfies() {
+        let mut flags = FlagRegister::new();
+        flags.set(Flag::Carry);
+
+        assert!(flags.satisfies(Condition::NotZero));
+        assert!(!flags.satisfies(Condition::Zero));
+        assert!(!flags.satisfies(Condition::NotCarry));
+        assert!(flags.satisfies(Condition::Carry));
+    }
 }
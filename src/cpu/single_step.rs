@@ -0,0 +1,252 @@
+//! A harness for the per-opcode JSON tests from the SingleStepTests project
+//! (<https://github.com/SingleStepTests/sm83>).
+//!
+//! Each test sets up the CPU and memory from an "initial" state, runs one
+//! instruction, and compares against a "final" state and the bus activity
+//! of every M-cycle. The suite isn't vendored, so the test is ignored by
+//! default. To run it, point `SM83_TESTS` at a checkout's `v1` directory:
+//!
+//! ```text
+//! SM83_TESTS=path/to/sm83/v1 cargo test single_step -- --ignored
+//! ```
+//!
+//! The tests assume flat memory, so they run against [`FlatMemory`] rather
+//! than the MMU, and no address range needs skipping. Every file is run,
+//! including the CB-prefixed `cb*` ones.
+//!
+//! This hasn't been run against the real suite yet, only against the
+//! hand-written case in [`test_harness_runs_a_case`]. Until it has, treat
+//! the harness (and anything that leans on it, like the claim that every
+//! instruction's bus accesses happen in hardware order) as unverified.
+
+use std::{cell::RefCell, fs, path::Path};
+
+use serde_json::Value;
+
+use crate::{
+    bus::{Bus, FlatMemory},
+    cpu::sm83::SM83,
+    mmu::Access,
+};
+
+/// Flat memory that records every access, like the MMU's access log.
+#[derive(Default)]
+struct LoggedMemory {
+    memory: FlatMemory,
+    log: RefCell<Vec<Access>>,
+}
+
+impl Bus for LoggedMemory {
+    fn read_byte(&self, addr: u16) -> Option<u8> {
+        self.log.borrow_mut().push(Access::Read(addr));
+        self.memory.read_byte(addr)
+    }
+
+    fn write_byte(&self, addr: u16, value: u8) {
+        self.log.borrow_mut().push(Access::Write(addr, value));
+        self.memory.write_byte(addr, value);
+    }
+
+    fn peek_byte(&self, addr: u16) -> Option<u8> {
+        self.memory.peek_byte(addr)
+    }
+
+    fn poke_byte(&self, addr: u16, value: u8) {
+        self.memory.poke_byte(addr, value);
+    }
+}
+
+fn byte(value: &Value) -> u8 {
+    value.as_u64().expect("should be a number") as u8
+}
+
+fn word(value: &Value) -> u16 {
+    value.as_u64().expect("should be a number") as u16
+}
+
+/// The (address, value) pairs of a state's "ram" list.
+fn ram(state: &Value) -> Vec<(u16, u8)> {
+    state["ram"]
+        .as_array()
+        .expect("should have a ram list")
+        .iter()
+        .map(|entry| (word(&entry[0]), byte(&entry[1])))
+        .collect()
+}
+
+/// The bus accesses listed in "cycles", skipping the idle ones.
+///
+/// Each cycle is `[address, data, pins]`, where the pins read `r-m` for a
+/// read and `-wm` for a write.
+fn accesses(case: &Value) -> Vec<Access> {
+    case["cycles"]
+        .as_array()
+        .expect("should have a cycles list")
+        .iter()
+        .filter_map(|cycle| {
+            let pins = cycle.get(2)?.as_str()?;
+            let addr = word(cycle.get(0).filter(|addr| !addr.is_null())?);
+
+            match pins.as_bytes() {
+                [b'r', ..] => Some(Access::Read(addr)),
+                [_, b'w', ..] => Some(Access::Write(addr, byte(&cycle[1]))),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+fn setup(state: &Value) -> (SM83, LoggedMemory) {
+    let mut cpu = SM83::new();
+    let memory = LoggedMemory::default();
+    let registers = &mut cpu.registers;
+
+    registers.a = byte(&state["a"]);
+    registers.b = byte(&state["b"]);
+    registers.c = byte(&state["c"]);
+    registers.d = byte(&state["d"]);
+    registers.e = byte(&state["e"]);
+    registers.h = byte(&state["h"]);
+    registers.l = byte(&state["l"]);
+    registers.flags.set_bits(byte(&state["f"]));
+    registers.pc = word(&state["pc"]);
+    registers.sp = word(&state["sp"]);
+    cpu.ime = state["ime"].as_u64() == Some(1);
+
+    if let Some(ie) = state.get("ie") {
+        memory.memory.write_byte(0xFFFF, byte(ie));
+    }
+    for (addr, value) in ram(state) {
+        memory.memory.write_byte(addr, value);
+    }
+
+    (cpu, memory)
+}
+
+/// Run a single test case, describing the first mismatch if there is one.
+fn run_case(case: &Value) -> Result<(), String> {
+    let (mut cpu, memory) = setup(&case["initial"]);
+
+    cpu.step(&memory).map_err(|error| error.to_string())?;
+    let log = memory.log.take();
+
+    let expected = &case["final"];
+    let registers = &cpu.registers;
+    let actual = [
+        ("a", registers.a as u16),
+        ("b", registers.b as u16),
+        ("c", registers.c as u16),
+        ("d", registers.d as u16),
+        ("e", registers.e as u16),
+        ("f", registers.flags.bits() as u16),
+        ("h", registers.h as u16),
+        ("l", registers.l as u16),
+        ("pc", registers.pc),
+        ("sp", registers.sp),
+        ("ime", cpu.ime() as u16),
+    ];
+    for (name, value) in actual {
+        let Some(wanted) = expected.get(name) else {
+            continue;
+        };
+        if value != word(wanted) {
+            return Err(format!("{name} is {value:X}, expected {:X}", word(wanted)));
+        }
+    }
+
+    for (addr, wanted) in ram(expected) {
+        let value = memory.memory.read_byte(addr).unwrap_or(0);
+        if value != wanted {
+            return Err(format!(
+                "memory at {addr:04X} is {value:02X}, expected {wanted:02X}"
+            ));
+        }
+    }
+
+    let cycles = case["cycles"].as_array().map_or(0, Vec::len);
+    if registers.m as usize != cycles {
+        return Err(format!("took {} M-cycles, expected {cycles}", registers.m));
+    }
+
+    let bus = accesses(case);
+    if log != bus {
+        return Err(format!("bus accesses were {log:?}, expected {bus:?}"));
+    }
+
+    Ok(())
+}
+
+/// Run every case in one of the suite's files, returning the failures.
+fn run_file(path: &Path) -> Vec<String> {
+    let text = fs::read_to_string(path).expect("should be able to read test file");
+    let cases: Vec<Value> = serde_json::from_str(&text).expect("should be valid JSON");
+
+    cases
+        .iter()
+        .filter_map(|case| {
+            run_case(case)
+                .err()
+                .map(|error| format!("{}: {error}", case["name"].as_str().unwrap_or("?")))
+        })
+        .collect()
+}
+
+#[test]
+#[ignore = "needs the SingleStepTests suite, via SM83_TESTS"]
+fn test_single_step_suite() {
+    let dir = std::env::var("SM83_TESTS").expect("SM83_TESTS should point at the sm83/v1 tests");
+    let mut paths: Vec<_> = fs::read_dir(dir)
+        .expect("should be able to read SM83_TESTS")
+        .map(|entry| entry.expect("should be a directory entry").path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    paths.sort();
+
+    let mut failing = Vec::new();
+    for path in &paths {
+        let failures = run_file(path);
+        if let Some(first) = failures.first() {
+            failing.push(format!(
+                "{}: {} failed, first was {first}",
+                path.display(),
+                failures.len()
+            ));
+        }
+    }
+
+    assert!(
+        failing.is_empty(),
+        "{} of {} files had failures:\n{}",
+        failing.len(),
+        paths.len(),
+        failing.join("\n")
+    );
+}
+
+#[test]
+fn test_harness_runs_a_case() {
+    // LD A,d8, in the suite's format
+    let case: Value = serde_json::from_str(
+        r#"{
+            "name": "3e 0000",
+            "initial": {
+                "a": 0, "b": 1, "c": 2, "d": 3, "e": 4, "f": 176, "h": 5, "l": 6,
+                "pc": 49152, "sp": 65534, "ime": 0, "ie": 0,
+                "ram": [[49152, 62], [49153, 66]]
+            },
+            "final": {
+                "a": 66, "b": 1, "c": 2, "d": 3, "e": 4, "f": 176, "h": 5, "l": 6,
+                "pc": 49154, "sp": 65534, "ime": 0, "ie": 0,
+                "ram": [[49152, 62], [49153, 66]]
+            },
+            "cycles": [[49152, 62, "r-m"], [49153, 66, "r-m"]]
+        }"#,
+    )
+    .unwrap();
+
+    assert_eq!(run_case(&case), Ok(()));
+
+    let mut wrong = case.clone();
+    wrong["final"]["a"] = 0.into();
+    assert_eq!(run_case(&wrong), Err("a is 42, expected 0".to_string()));
+}
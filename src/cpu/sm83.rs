@@ -1,79 +1,1112 @@
 use crate::{
+    bus::Bus,
     cpu::{
-        opcodes::{Opcode, OperationsMap, SM83_OPERATIONS},
+        error::ExecutionError,
+        instructions::{decode, length, Instruction},
+        interrupts::{Interrupt, Latency, LatencyTracker, INTERRUPT_FLAG},
+        opcodes::dispatch,
         registers::SM83RegisterBank,
     },
+    debug_message::{self, DebugMessage},
     mmu::MMU,
+    watchdog::Watchdog,
 };
 
+/// The opcode of `LD B,B`, which BGB treats as a breakpoint.
+const SOURCE_BREAKPOINT: u8 = 0x40;
+
+/// How many of the last instructions [`SM83::history`] keeps.
+pub const HISTORY_LEN: usize = 32;
+
+/// An instruction the CPU executed: where it was, and its bytes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HistoryEntry {
+    pub pc: u16,
+    pub opcode: u8,
+    /// The bytes after the opcode, whether or not the instruction used them.
+    pub operands: [u8; 2],
+}
+
+impl HistoryEntry {
+    pub fn instruction(&self) -> Instruction {
+        decode(self.opcode, self.operands[0], self.operands[1])
+    }
+}
+
+/// The last [`HISTORY_LEN`] instructions, overwriting the oldest.
+#[derive(Debug, Clone)]
+struct History {
+    entries: [HistoryEntry; HISTORY_LEN],
+    /// Where the next entry goes.
+    next: usize,
+    len: usize,
+}
+
+impl History {
+    fn new() -> Self {
+        History {
+            entries: [HistoryEntry::default(); HISTORY_LEN],
+            next: 0,
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, entry: HistoryEntry) {
+        self.entries[self.next] = entry;
+        self.next = (self.next + 1) % HISTORY_LEN;
+        self.len = (self.len + 1).min(HISTORY_LEN);
+    }
+
+    /// The entries, oldest first.
+    fn to_vec(&self) -> Vec<HistoryEntry> {
+        let start = (self.next + HISTORY_LEN - self.len) % HISTORY_LEN;
+        (0..self.len)
+            .map(|i| self.entries[(start + i) % HISTORY_LEN])
+            .collect()
+    }
+}
+
 /// The CPU of the GameBoy, a Sharp SM83.
 #[derive(Debug, Clone)]
 pub struct SM83 {
-    // CPU clock
-    m: u8,
-    t: u8,
-
     pub registers: SM83RegisterBank,
-    operations: OperationsMap<Self>,
+    /// Additional cycles spent by the current instruction, on top of the
+    /// base cost of the instruction (i.e. for taken branches).
+    pub(crate) extra_cycles: u8,
+    /// Set by HALT; instruction fetch stops until an interrupt is pending.
+    pub(crate) halted: bool,
+    /// Set when HALT is executed with an interrupt already pending.
+    pub(crate) halt_bug: bool,
+    /// Set by STOP; the CPU sleeps until a joypad interrupt is requested.
+    pub(crate) stopped: bool,
+    /// Whether the CGB is running in double-speed mode.
+    pub(crate) double_speed: bool,
+    /// Set by the unused opcodes, which hang the CPU for good.
+    pub(crate) locked: bool,
+    /// The address of a failed memory access, reported once the current
+    /// instruction finishes.
+    pub(crate) fault: Option<u16>,
+    /// The interrupt master enable flag.
+    pub(crate) ime: bool,
+    /// Set by EI; IME is enabled once the next instruction has run.
+    pub(crate) ime_scheduled: bool,
+    /// Gameboy Doctor trace lines, only recorded while tracing is enabled.
+    trace: Option<Vec<String>>,
+    /// Messages printed with `LD D,D`, only recorded while enabled.
+    debug_messages: Option<Vec<DebugMessage>>,
+    /// Whether `LD B,B` stops execution.
+    source_breakpoints: bool,
+    /// Interrupt latency, only measured while enabled.
+    latency: Option<LatencyTracker>,
+    /// The last few instructions, always recorded, for crash reports.
+    history: History,
+    /// M-cycles elapsed since the CPU was created (or the count was reset).
+    cycles: u64,
+}
+
+impl Default for SM83 {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SM83 {
     pub fn new() -> Self {
         SM83 {
-            m: 0,
-            t: 0,
             registers: SM83RegisterBank::new(),
-            operations: SM83_OPERATIONS.clone(),
+            extra_cycles: 0,
+            halted: false,
+            halt_bug: false,
+            stopped: false,
+            double_speed: false,
+            locked: false,
+            fault: None,
+            ime: false,
+            ime_scheduled: false,
+            trace: None,
+            debug_messages: None,
+            source_breakpoints: false,
+            latency: None,
+            history: History::new(),
+            cycles: 0,
         }
     }
 
-    pub fn step(&mut self) {}
+    /// Push a 16-bit value onto the stack, high byte first.
+    pub fn push_word(&mut self, bus: &impl Bus, value: u16) {
+        let [low, high] = value.to_le_bytes();
+
+        self.registers.sp = self.registers.sp.wrapping_sub(1);
+        bus.write_byte(self.registers.sp, high);
+        self.registers.sp = self.registers.sp.wrapping_sub(1);
+        bus.write_byte(self.registers.sp, low);
+    }
+
+    /// Pop a 16-bit value off of the stack, low byte first.
+    pub fn pop_word(&mut self, bus: &impl Bus) -> u16 {
+        let low = self.read_or_fault(bus, self.registers.sp);
+        self.registers.sp = self.registers.sp.wrapping_add(1);
+        let high = self.read_or_fault(bus, self.registers.sp);
+        self.registers.sp = self.registers.sp.wrapping_add(1);
+
+        u16::from_le_bytes([low, high])
+    }
 
     pub fn reset(&mut self) {}
 
-    pub fn run(&mut self, mmu: &MMU) {
+    /// Read a byte, or record a fault and read open bus (0xFF) if the
+    /// address can't be read.
+    pub(crate) fn read_or_fault(&mut self, bus: &impl Bus, addr: u16) -> u8 {
+        bus.read_byte(addr).unwrap_or_else(|| {
+            self.fault.get_or_insert(addr);
+            0xFF
+        })
+    }
+
+    /// Run until execution can't continue, the program uses hardware that
+    /// the MMU's [`Compatibility`](crate::compatibility::Compatibility)
+    /// policy says to stop at, or the watchdog reports that the CPU has hung.
+    pub fn run(&mut self, mmu: &MMU, watchdog: &mut Watchdog) -> ExecutionError {
         loop {
-            let Some(code) = mmu.read_byte(self.registers.pc) else {
-                println!("Failed to read byte at address: {:04X}", self.registers.pc);
-                continue;
-            };
+            let pc = self.registers.pc;
+            if let Err(error) = self.step(mmu) {
+                return error;
+            }
+            if let Some(hardware) = mmu.take_unimplemented_stop() {
+                return ExecutionError::Unimplemented { pc, hardware };
+            }
+            if let Some(hang) = watchdog.observe(self, mmu) {
+                return ExecutionError::Hang(hang);
+            }
+        }
+    }
+
+    /// Run for at least `cycles` M-cycles, stopping early on an error.
+    ///
+    /// Instructions aren't split, so the last one may overshoot; the
+    /// overshoot is left in [`SM83::cycles`].
+    pub fn run_for(&mut self, bus: &impl Bus, cycles: u64) -> Result<(), ExecutionError> {
+        let end = self.cycles + cycles;
+        while self.cycles < end {
+            self.step(bus)?;
+        }
 
-            let Some(opcode) = self.operations.get(&code) else {
-                panic!("Unknown opcode: {:02X}", code);
+        Ok(())
+    }
+
+    /// Execute a single instruction (or idle for one cycle while halted or
+    /// stopped).
+    ///
+    /// An unused opcode locks up the CPU, as on hardware, and is reported
+    /// here once; later steps just idle.
+    pub fn step(&mut self, bus: &impl Bus) -> Result<(), ExecutionError> {
+        let result = self.advance(bus);
+        self.cycles += self.registers.m as u64;
+        bus.tick(self.registers.m);
+
+        result
+    }
+
+    fn advance(&mut self, bus: &impl Bus) -> Result<(), ExecutionError> {
+        if let Some(latency) = &mut self.latency {
+            latency.observe(bus, self.cycles);
+        }
+
+        if self.locked {
+            self.registers.m = 1;
+            self.registers.t = 4;
+            return Ok(());
+        }
+
+        if self.stopped {
+            // only a button press brings the CPU out of stop mode
+            let requested = bus.peek_byte(INTERRUPT_FLAG).unwrap_or(0);
+            if requested & Interrupt::Joypad.mask() == 0 {
+                self.registers.m = 1;
+                self.registers.t = 4;
+                return Ok(());
+            }
+
+            self.stopped = false;
+        }
+
+        if self.halted {
+            if !Self::interrupt_pending(bus) {
+                self.registers.m = 1;
+                self.registers.t = 4;
+                return Ok(());
+            }
+
+            self.halted = false;
+        }
+
+        if self.ime {
+            if let Some(interrupt) = Interrupt::pending(bus) {
+                self.service_interrupt(bus, interrupt);
+                return Ok(());
+            }
+        }
+
+        if let Some(trace) = &mut self.trace {
+            trace.push(trace_line(&self.registers, bus));
+        }
+        if let Some(latency) = &mut self.latency {
+            latency.fetch(self.cycles);
+        }
+
+        // EI only takes effect after the instruction following it
+        let enable_ime = self.ime_scheduled;
+
+        let pc = self.registers.pc;
+        let Some(code) = bus.read_byte(pc) else {
+            return Err(ExecutionError::MemoryFault { pc, addr: pc });
+        };
+
+        // PC is moved past the instruction before it executes, so the
+        // instruction sees the address of the one after it. The halt bug
+        // stops PC from incrementing past the opcode, so it is read again as
+        // an operand.
+        let operands = if std::mem::take(&mut self.halt_bug) {
+            self.registers.pc
+        } else {
+            self.registers.pc.wrapping_add(1)
+        };
+        let length = length(code);
+
+        let mut immediates = [0; 2];
+        for offset in 0..length - 1 {
+            let addr = operands.wrapping_add(offset);
+            let Some(byte) = bus.read_byte(addr) else {
+                return Err(ExecutionError::MemoryFault { pc, addr });
             };
-            let cycles = opcode.cycle_count();
+            immediates[offset as usize] = byte;
+        }
 
-            match opcode {
-                Opcode::Unary(operation, _) => {
-                    operation(self, mmu);
-                }
-                Opcode::Binary(operation, _) => {
-                    let addr = self.registers.pc + 1;
-                    let Some(immediate) = mmu.read_byte(addr) else {
-                        println!("Failed to read byte at address: {:04X}", addr);
-                        continue;
-                    };
-
-                    operation(self, mmu, immediate);
-                }
-                Opcode::Ternary(operation, _) => {
-                    let addr = self.registers.pc + 1;
-                    let Some(immediate_a) = mmu.read_byte(addr) else {
-                        println!("Failed to read byte at address: {:04X}", addr);
-                        continue;
-                    };
-                    let Some(immediate_b) = mmu.read_byte(addr + 1) else {
-                        println!("Failed to read byte at address: {:04X}", addr);
-                        continue;
-                    };
-
-                    operation(self, mmu, immediate_a, immediate_b);
+        self.history.push(HistoryEntry {
+            pc,
+            opcode: code,
+            operands: immediates,
+        });
+
+        self.registers.pc = operands.wrapping_add(length - 1);
+        let base_cycles = dispatch(self, bus, code, immediates[0], immediates[1]);
+        if code == debug_message::MARKER {
+            self.read_debug_message(bus, pc);
+        }
+
+        // a DI in between cancels the scheduled enable
+        if enable_ime && self.ime_scheduled {
+            self.ime = true;
+            self.ime_scheduled = false;
+        }
+
+        // increment our clock registers
+        let cycles = base_cycles + std::mem::take(&mut self.extra_cycles);
+        self.registers.m = cycles;
+        self.registers.t = cycles * 4;
+
+        if let Some(addr) = self.fault.take() {
+            return Err(ExecutionError::MemoryFault { pc, addr });
+        }
+        if self.source_breakpoints && code == SOURCE_BREAKPOINT {
+            return Err(ExecutionError::Breakpoint { pc });
+        }
+        // only the unused opcodes lock the CPU up
+        match self.locked {
+            true => Err(ExecutionError::UnknownOpcode { pc, byte: code }),
+            false => Ok(()),
+        }
+    }
+
+    /// Whether any enabled interrupt has been requested (IE & IF).
+    pub(crate) fn interrupt_pending(bus: &impl Bus) -> bool {
+        Interrupt::pending(bus).is_some()
+    }
+
+    /// Jump to an interrupt's vector, taking 5 M-cycles.
+    ///
+    /// IME is cleared and the interrupt acknowledged, so the handler won't be
+    /// interrupted again until it re-enables interrupts (usually via RETI).
+    fn service_interrupt(&mut self, bus: &impl Bus, interrupt: Interrupt) {
+        self.ime = false;
+        self.ime_scheduled = false;
+        interrupt.acknowledge(bus);
+        if let Some(latency) = &mut self.latency {
+            latency.dispatch(interrupt, self.cycles);
+        }
+
+        self.push_word(bus, self.registers.pc);
+        self.registers.pc = interrupt.vector();
+
+        self.registers.m = 5;
+        self.registers.t = 20;
+    }
+
+    /// Start or stop recording a trace line before every instruction.
+    ///
+    /// See [`SM83::trace_line`] for the format.
+    pub fn set_trace_logging(&mut self, enabled: bool) {
+        self.trace = enabled.then(Vec::new);
+    }
+
+    /// The trace lines recorded so far, oldest first, without draining them.
+    pub fn trace_log(&self) -> &[String] {
+        self.trace.as_deref().unwrap_or_default()
+    }
+
+    /// Drain the trace lines recorded so far, leaving tracing enabled.
+    pub fn take_trace_log(&mut self) -> Vec<String> {
+        self.trace.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// Start or stop recording the messages printed with BGB's `LD D,D`
+    /// protocol: an `LD D,D` followed by a `JR` over a `$6464` signature and
+    /// the message.
+    pub fn set_debug_messages(&mut self, enabled: bool) {
+        self.debug_messages = enabled.then(Vec::new);
+    }
+
+    /// Drain the debug messages printed so far, leaving recording enabled.
+    pub fn take_debug_messages(&mut self) -> Vec<DebugMessage> {
+        self.debug_messages
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    /// Start or stop measuring how long each interrupt waits to be serviced,
+    /// starting over from nothing. See [`Latency`].
+    pub fn set_latency_tracking(&mut self, enabled: bool) {
+        self.latency = enabled.then(LatencyTracker::default);
+    }
+
+    /// How long `interrupt` has waited to be serviced, since latency
+    /// tracking was turned on.
+    pub fn interrupt_latency(&self, interrupt: Interrupt) -> Latency {
+        self.latency
+            .as_ref()
+            .map(|latency| latency.latency(interrupt))
+            .unwrap_or_default()
+    }
+
+    /// Have `LD B,B` stop execution with [`ExecutionError::Breakpoint`], as
+    /// BGB does, so homebrew can break into a debugger from its source.
+    pub fn set_source_breakpoints(&mut self, enabled: bool) {
+        self.source_breakpoints = enabled;
+    }
+
+    /// Record the message following the `LD D,D` at `pc`, if there is one.
+    fn read_debug_message(&mut self, bus: &impl Bus, pc: u16) {
+        let Some(messages) = &mut self.debug_messages else {
+            return;
+        };
+
+        if let Some(text) = debug_message::read(bus, self.registers.pc, &self.registers) {
+            messages.push(DebugMessage { pc, text });
+        }
+    }
+
+    /// The CPU state, in the log format expected by Gameboy Doctor.
+    ///
+    /// PCMEM holds the four bytes starting at PC. These are peeked, so they
+    /// don't show up in the MMU's access log. Gameboy Doctor also expects LY
+    /// to read 0x90, which is up to whatever sets up the MMU.
+    pub fn trace_line(&self, bus: &impl Bus) -> String {
+        trace_line(&self.registers, bus)
+    }
+
+    /// The last [`HISTORY_LEN`] instructions executed, oldest first.
+    ///
+    /// Unlike the trace log, this is always recorded, and never grows.
+    pub fn history(&self) -> Vec<HistoryEntry> {
+        self.history.to_vec()
+    }
+
+    /// M-cycles elapsed since the CPU was created, or since
+    /// [`SM83::reset_cycles`].
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn reset_cycles(&mut self) {
+        self.cycles = 0;
+    }
+
+    /// Whether the CPU is halted, waiting for an interrupt.
+    pub fn halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Whether the interrupt master enable flag (IME) is set.
+    pub fn ime(&self) -> bool {
+        self.ime
+    }
+
+    /// Whether the CPU has hung after executing an unused opcode.
+    pub fn locked(&self) -> bool {
+        self.locked
+    }
+
+    /// Whether the CPU is in stop mode, waiting for a button press.
+    pub fn stopped(&self) -> bool {
+        self.stopped
+    }
+
+    /// Whether the CPU is running at double speed (CGB only).
+    pub fn double_speed(&self) -> bool {
+        self.double_speed
+    }
+}
+
+fn trace_line(registers: &SM83RegisterBank, bus: &impl Bus) -> String {
+    let pc = registers.pc;
+    let memory: Vec<String> = (0..4)
+        .map(|offset| {
+            let byte = bus.peek_byte(pc.wrapping_add(offset)).unwrap_or(0);
+            format!("{:02X}", byte)
+        })
+        .collect();
+
+    format!(
+        "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} PCMEM:{}",
+        registers.a,
+        registers.flags.bits(),
+        registers.b,
+        registers.c,
+        registers.d,
+        registers.e,
+        registers.h,
+        registers.l,
+        registers.sp,
+        pc,
+        memory.join(",")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bus::FlatMemory,
+        mmu::Access::{self, Read, Write},
+        model::Model,
+    };
+
+    /// Write a program to WRAM at 0xC000, and point PC at it.
+    fn load(cpu: &mut SM83, mmu: &MMU, program: &[u8]) {
+        cpu.registers.pc = 0xC000;
+        for (i, byte) in program.iter().enumerate() {
+            mmu.write_byte(0xC000 + i as u16, *byte);
+        }
+    }
+
+    /// Run the instruction at 0xC000 and return the bus accesses it made.
+    fn accesses(cpu: &mut SM83, mmu: &MMU, bytes: &[u8]) -> Vec<Access> {
+        load(cpu, mmu, bytes);
+
+        mmu.set_access_logging(true);
+        cpu.step(mmu).unwrap();
+        let log = mmu.take_access_log();
+        mmu.set_access_logging(false);
+
+        log
+    }
+
+    #[test]
+    fn test_halt_waits_for_interrupt() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+        load(&mut cpu, &mmu, &[0x76, 0x3C]); // HALT; INC A
+        mmu.write_byte(0xFFFF, 0x01);
+
+        cpu.step(&mmu).unwrap();
+        assert!(cpu.halted());
+
+        // nothing requested yet, so the CPU idles without fetching
+        cpu.step(&mmu).unwrap();
+        assert!(cpu.halted());
+        assert_eq!(cpu.registers.a, 0x00);
+
+        mmu.write_byte(0xFF0F, 0x01);
+        cpu.step(&mmu).unwrap();
+        assert!(!cpu.halted());
+        assert_eq!(cpu.registers.a, 0x01);
+    }
+
+    #[test]
+    fn test_halt_bug() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+        load(&mut cpu, &mmu, &[0x76, 0x3E, 0x14]); // HALT; LD A,d8
+        mmu.write_byte(0xFFFF, 0x04);
+        mmu.write_byte(0xFF0F, 0x04);
+
+        cpu.step(&mmu).unwrap();
+        assert!(!cpu.halted());
+        assert_eq!(cpu.registers.pc, 0xC001);
+
+        // PC doesn't move past the LD, so it reads its own opcode as the
+        // immediate, and the old immediate runs next as INC D
+        cpu.step(&mmu).unwrap();
+        assert_eq!(cpu.registers.a, 0x3E);
+        assert_eq!(cpu.registers.pc, 0xC002);
+
+        cpu.step(&mmu).unwrap();
+        assert_eq!(cpu.registers.d, 0x01);
+    }
+
+    #[test]
+    fn test_stop_waits_for_joypad() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+        load(&mut cpu, &mmu, &[0x10, 0x00, 0x3C]); // STOP; INC A
+
+        cpu.step(&mmu).unwrap();
+        assert!(cpu.stopped());
+
+        // other interrupts don't wake the CPU
+        mmu.write_byte(0xFF0F, 0x01);
+        cpu.step(&mmu).unwrap();
+        assert!(cpu.stopped());
+        assert_eq!(cpu.registers.a, 0x00);
+
+        mmu.write_byte(0xFF0F, 0x10);
+        cpu.step(&mmu).unwrap();
+        assert!(!cpu.stopped());
+        assert_eq!(cpu.registers.a, 0x01);
+    }
+
+    #[test]
+    fn test_stop_speed_switch() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+        mmu.set_model(Model::Cgb);
+        load(&mut cpu, &mmu, &[0x10, 0x00, 0x10, 0x00]); // STOP; STOP
+        mmu.write_byte(0xFF4D, 0x01);
+
+        cpu.step(&mmu).unwrap();
+        assert!(!cpu.stopped());
+        assert!(cpu.double_speed());
+        assert_eq!(mmu.read_byte(0xFF4D), Some(0xFE));
+
+        mmu.write_byte(0xFF4D, 0x81);
+        cpu.step(&mmu).unwrap();
+        assert!(!cpu.double_speed());
+        assert_eq!(mmu.read_byte(0xFF4D), Some(0x7E));
+    }
+
+    #[test]
+    fn test_access_order() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+        cpu.registers.set_hl(0xC100);
+        cpu.registers.sp = 0xD000;
+        mmu.write_byte(0xC100, 0x41);
+
+        // read-modify-write: the operand is read before it is written back
+        assert_eq!(
+            accesses(&mut cpu, &mmu, &[0x34]), // INC (HL)
+            [Read(0xC000), Read(0xC100), Write(0xC100, 0x42)]
+        );
+        assert_eq!(
+            accesses(&mut cpu, &mmu, &[0x35]), // DEC (HL)
+            [Read(0xC000), Read(0xC100), Write(0xC100, 0x41)]
+        );
+        assert_eq!(
+            accesses(&mut cpu, &mmu, &[0x36, 0x99]), // LD (HL),d8
+            [Read(0xC000), Read(0xC001), Write(0xC100, 0x99)]
+        );
+
+        // the stack is written high byte first, and read low byte first
+        cpu.registers.b = 0x12;
+        cpu.registers.c = 0x34;
+        assert_eq!(
+            accesses(&mut cpu, &mmu, &[0xC5]), // PUSH BC
+            [Read(0xC000), Write(0xCFFF, 0x12), Write(0xCFFE, 0x34)]
+        );
+        assert_eq!(
+            accesses(&mut cpu, &mmu, &[0xD1]), // POP DE
+            [Read(0xC000), Read(0xCFFE), Read(0xCFFF)]
+        );
+        assert_eq!(
+            accesses(&mut cpu, &mmu, &[0xCD, 0x00, 0xC2]), // CALL a16
+            [
+                Read(0xC000),
+                Read(0xC001),
+                Read(0xC002),
+                Write(0xCFFF, 0xC0),
+                Write(0xCFFE, 0x03),
+            ]
+        );
+
+        // LD (a16),SP stores the low byte first
+        assert_eq!(
+            accesses(&mut cpu, &mmu, &[0x08, 0x00, 0xC3]),
+            [
+                Read(0xC000),
+                Read(0xC001),
+                Read(0xC002),
+                Write(0xC300, 0xFE),
+                Write(0xC301, 0xCF),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ei_delay() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+        load(&mut cpu, &mmu, &[0xFB, 0x00, 0xF3]); // EI; NOP; DI
+
+        cpu.step(&mmu).unwrap();
+        assert!(!cpu.ime());
+
+        cpu.step(&mmu).unwrap();
+        assert!(cpu.ime());
+
+        cpu.step(&mmu).unwrap();
+        assert!(!cpu.ime());
+    }
+
+    #[test]
+    fn test_di_cancels_ei() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+        load(&mut cpu, &mmu, &[0xFB, 0xF3, 0x00]); // EI; DI; NOP
+
+        cpu.step(&mmu).unwrap();
+        cpu.step(&mmu).unwrap();
+        assert!(!cpu.ime());
+
+        cpu.step(&mmu).unwrap();
+        assert!(!cpu.ime());
+    }
+
+    #[test]
+    fn test_reti_enables_immediately() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+        cpu.registers.pc = 0xC000;
+        cpu.registers.sp = 0xD000;
+        cpu.push_word(&mmu, 0xC123);
+
+        mmu.write_byte(0xC000, 0xD9); // RETI
+        cpu.step(&mmu).unwrap();
+        assert!(cpu.ime());
+        assert_eq!(cpu.registers.pc, 0xC123);
+    }
+
+    #[test]
+    fn test_halt_with_ime_has_no_bug() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+        cpu.registers.pc = 0xC000;
+        cpu.ime = true;
+        mmu.write_byte(0xC000, 0x76); // HALT
+        mmu.write_byte(0xFFFF, 0x01);
+        mmu.write_byte(0xFF0F, 0x01);
+
+        cpu.step(&mmu).unwrap();
+        assert!(!cpu.halted());
+        assert!(!cpu.halt_bug);
+    }
+
+    #[test]
+    fn test_interrupt_dispatch() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+        cpu.registers.pc = 0xC000;
+        cpu.registers.sp = 0xD000;
+        cpu.ime = true;
+        mmu.write_byte(0xFFFF, 0x1F);
+        Interrupt::Serial.request(&mmu);
+        Interrupt::Stat.request(&mmu);
+
+        cpu.step(&mmu).unwrap();
+        assert_eq!(cpu.registers.pc, 0x0048);
+        assert_eq!(cpu.registers.m, 5);
+        assert!(!cpu.ime());
+        assert_eq!(cpu.pop_word(&mmu), 0xC000);
+
+        // only the serviced interrupt is acknowledged
+        assert_eq!(Interrupt::pending(&mmu), Some(Interrupt::Serial));
+    }
+
+    #[test]
+    fn test_interrupt_polling_isnt_an_access() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+        cpu.registers.sp = 0xD000;
+        cpu.ime = true;
+        mmu.write_byte(0xFFFF, 0x04);
+
+        // checking IE and IF between instructions doesn't touch the bus
+        assert_eq!(accesses(&mut cpu, &mmu, &[0x00]), [Read(0xC000)]);
+
+        // and neither does acknowledging one, only pushing PC does
+        Interrupt::Timer.request(&mmu);
+        let io_writes = mmu.io_writes();
+        assert_eq!(
+            accesses(&mut cpu, &mmu, &[0x00]),
+            [Write(0xCFFF, 0xC0), Write(0xCFFE, 0x00)]
+        );
+        assert_eq!(mmu.io_writes(), io_writes);
+        assert_eq!(Interrupt::pending(&mmu), None);
+    }
+
+    #[test]
+    fn test_interrupt_wakes_halt() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+        cpu.registers.pc = 0xC000;
+        cpu.registers.sp = 0xD000;
+        cpu.ime = true;
+        mmu.write_byte(0xFFFF, 0x04);
+        mmu.write_byte(0xC000, 0x76); // HALT
+
+        cpu.step(&mmu).unwrap();
+        assert!(cpu.halted());
+
+        Interrupt::Timer.request(&mmu);
+        cpu.step(&mmu).unwrap();
+        assert!(!cpu.halted());
+        assert_eq!(cpu.registers.pc, 0x0050);
+    }
+
+    #[test]
+    fn test_cycle_counter() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+        load(&mut cpu, &mmu, &[0x00, 0xC3, 0x00, 0xC0]); // NOP; JP C000
+
+        cpu.step(&mmu).unwrap();
+        cpu.step(&mmu).unwrap();
+        assert_eq!(cpu.cycles(), 5);
+
+        // the JP that crosses the limit runs to completion
+        cpu.run_for(&mmu, 7).unwrap();
+        assert_eq!(cpu.cycles(), 15);
+
+        cpu.reset_cycles();
+        assert_eq!(cpu.cycles(), 0);
+    }
+
+    #[test]
+    fn test_unused_opcode_locks_up() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+        cpu.registers.pc = 0xC000;
+        cpu.ime = true;
+        mmu.write_byte(0xC000, 0xD3);
+
+        assert!(matches!(
+            cpu.step(&mmu),
+            Err(ExecutionError::UnknownOpcode {
+                pc: 0xC000,
+                byte: 0xD3
+            })
+        ));
+        assert!(cpu.locked());
+
+        // not even an interrupt gets it going again
+        mmu.write_byte(0xFFFF, 0x01);
+        mmu.write_byte(0xFF0F, 0x01);
+        cpu.step(&mmu).unwrap();
+        assert!(cpu.locked());
+        assert_eq!(cpu.registers.pc, 0xC001);
+    }
+
+    #[test]
+    fn test_pc_advances_by_instruction_length() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+        // LD A,0x05; LD BC,0x1234; JP 0xC010; ...; INC A
+        load(
+            &mut cpu,
+            &mmu,
+            &[0x3E, 0x05, 0x01, 0x34, 0x12, 0xC3, 0x10, 0xC0],
+        );
+        mmu.write_byte(0xC010, 0x3C);
+
+        cpu.step(&mmu).unwrap();
+        assert_eq!(cpu.registers.pc, 0xC002);
+        cpu.step(&mmu).unwrap();
+        assert_eq!(cpu.registers.pc, 0xC005);
+
+        // jumps still get the final say over PC
+        cpu.step(&mmu).unwrap();
+        assert_eq!(cpu.registers.pc, 0xC010);
+        cpu.step(&mmu).unwrap();
+        assert_eq!(cpu.registers.pc, 0xC011);
+        assert_eq!(cpu.registers.a, 0x06);
+    }
+
+    /// M-cycles per opcode, with branches not taken, from the tables in
+    /// Blargg's instr_timing test ROM. 0 marks the ones it doesn't time:
+    /// STOP, HALT, the prefix and the unused opcodes.
+    #[rustfmt::skip]
+    const TIMING: [u8; 256] = [
+        1,3,2,2,1,1,2,1,5,2,2,2,1,1,2,1,
+        0,3,2,2,1,1,2,1,3,2,2,2,1,1,2,1,
+        2,3,2,2,1,1,2,1,2,2,2,2,1,1,2,1,
+        2,3,2,2,3,3,3,1,2,2,2,2,1,1,2,1,
+        1,1,1,1,1,1,2,1,1,1,1,1,1,1,2,1,
+        1,1,1,1,1,1,2,1,1,1,1,1,1,1,2,1,
+        1,1,1,1,1,1,2,1,1,1,1,1,1,1,2,1,
+        2,2,2,2,2,2,0,2,1,1,1,1,1,1,2,1,
+        1,1,1,1,1,1,2,1,1,1,1,1,1,1,2,1,
+        1,1,1,1,1,1,2,1,1,1,1,1,1,1,2,1,
+        1,1,1,1,1,1,2,1,1,1,1,1,1,1,2,1,
+        1,1,1,1,1,1,2,1,1,1,1,1,1,1,2,1,
+        2,3,3,4,3,4,2,4,2,4,3,0,3,6,2,4,
+        2,3,3,0,3,4,2,4,2,4,3,0,3,0,2,4,
+        3,3,2,0,0,4,2,4,4,1,4,0,0,0,2,4,
+        3,3,2,1,0,4,2,4,3,2,4,1,0,0,2,4,
+    ];
+
+    /// The same for the CB-prefixed instructions, including the prefix.
+    #[rustfmt::skip]
+    const PREFIXED_TIMING: [u8; 256] = [
+        2,2,2,2,2,2,4,2,2,2,2,2,2,2,4,2,
+        2,2,2,2,2,2,4,2,2,2,2,2,2,2,4,2,
+        2,2,2,2,2,2,4,2,2,2,2,2,2,2,4,2,
+        2,2,2,2,2,2,4,2,2,2,2,2,2,2,4,2,
+        2,2,2,2,2,2,3,2,2,2,2,2,2,2,3,2,
+        2,2,2,2,2,2,3,2,2,2,2,2,2,2,3,2,
+        2,2,2,2,2,2,3,2,2,2,2,2,2,2,3,2,
+        2,2,2,2,2,2,3,2,2,2,2,2,2,2,3,2,
+        2,2,2,2,2,2,4,2,2,2,2,2,2,2,4,2,
+        2,2,2,2,2,2,4,2,2,2,2,2,2,2,4,2,
+        2,2,2,2,2,2,4,2,2,2,2,2,2,2,4,2,
+        2,2,2,2,2,2,4,2,2,2,2,2,2,2,4,2,
+        2,2,2,2,2,2,4,2,2,2,2,2,2,2,4,2,
+        2,2,2,2,2,2,4,2,2,2,2,2,2,2,4,2,
+        2,2,2,2,2,2,4,2,2,2,2,2,2,2,4,2,
+        2,2,2,2,2,2,4,2,2,2,2,2,2,2,4,2,
+    ];
+
+    /// How many M-cycles a conditional branch adds when it's taken.
+    fn taken_extra(opcode: u8) -> u8 {
+        match opcode {
+            0x20 | 0x28 | 0x30 | 0x38 | 0xC2 | 0xCA | 0xD2 | 0xDA => 1,
+            0xC0 | 0xC8 | 0xD0 | 0xD8 | 0xC4 | 0xCC | 0xD4 | 0xDC => 3,
+            _ => 0,
+        }
+    }
+
+    /// The ROM measures every instruction with the timer, which doesn't
+    /// exist yet, so this runs its tables against `step` instead. Each
+    /// opcode runs with all flags clear and all set, so every conditional
+    /// branch is both taken and not.
+    #[test]
+    fn test_instruction_timing() {
+        let run = |bytes: &[u8], flags: u8| {
+            let mut cpu = SM83::new();
+            let memory = FlatMemory::new();
+            cpu.registers.pc = 0xC000;
+            cpu.registers.sp = 0xD000;
+            cpu.registers.set_hl(0xC800);
+            cpu.registers.flags.set_bits(flags);
+            memory.load(0xC000, bytes);
+
+            cpu.step(&memory).unwrap();
+            cpu.registers.m
+        };
+
+        for flags in [0x00, 0xF0] {
+            for opcode in 0..=255u8 {
+                if TIMING[opcode as usize] == 0 {
+                    continue;
                 }
-            };
 
-            // increment our clock registers
-            self.registers.m = cycles;
-            self.registers.t = cycles * 4;
+                // NZ and NC (bit 3 clear) hold with the flags clear
+                let taken = (opcode & 0x08 == 0) == (flags == 0x00);
+                let expected = TIMING[opcode as usize] + taken as u8 * taken_extra(opcode);
+                assert_eq!(
+                    run(&[opcode, 0x00, 0x00], flags),
+                    expected,
+                    "{opcode:02X} with F={flags:02X}"
+                );
+            }
+        }
+
+        for opcode in 0..=255u8 {
+            assert_eq!(
+                run(&[0xCB, opcode], 0x00),
+                PREFIXED_TIMING[opcode as usize],
+                "CB {opcode:02X}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_prefixed_instructions() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+        load(&mut cpu, &mmu, &[0xCB, 0x37, 0x3C]); // SWAP A; INC A
+        cpu.registers.a = 0x12;
+
+        // the prefix and the byte after it are a single instruction
+        cpu.step(&mmu).unwrap();
+        assert_eq!(cpu.registers.a, 0x21);
+        assert_eq!(cpu.registers.pc, 0xC002);
+        assert_eq!(cpu.registers.m, 2);
+        cpu.step(&mmu).unwrap();
+        assert_eq!(cpu.registers.a, 0x22);
+
+        // read-modify-write on (HL)
+        cpu.registers.set_hl(0xC100);
+        mmu.write_byte(0xC100, 0x0F);
+        assert_eq!(
+            accesses(&mut cpu, &mmu, &[0xCB, 0xFE]), // SET 7,(HL)
+            [
+                Read(0xC000),
+                Read(0xC001),
+                Read(0xC100),
+                Write(0xC100, 0x8F)
+            ]
+        );
+        assert_eq!(cpu.registers.m, 4);
+    }
+
+    #[test]
+    fn test_history() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+        load(&mut cpu, &mmu, &[0x3E, 0x42, 0xCB, 0x37]); // LD A,d8; SWAP A
+        assert_eq!(cpu.history(), []);
+
+        cpu.step(&mmu).unwrap();
+        cpu.step(&mmu).unwrap();
+        let history = cpu.history();
+        assert_eq!(history[0].pc, 0xC000);
+        assert_eq!(history[0].instruction().to_string(), "LD A,42");
+        assert_eq!(history[1].instruction().to_string(), "SWAP A");
+
+        // only the most recent are kept, oldest first
+        for _ in 0..HISTORY_LEN {
+            cpu.step(&mmu).unwrap();
         }
+        let history = cpu.history();
+        assert_eq!(history.len(), HISTORY_LEN);
+        assert_eq!(history[0].pc, 0xC004);
+        assert_eq!(history[HISTORY_LEN - 1].pc, 0xC003 + HISTORY_LEN as u16);
+    }
+
+    #[test]
+    fn test_trace_log() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+        load(&mut cpu, &mmu, &[0x3E, 0x42, 0x06, 0x10, 0x00]); // LD A,d8; LD B,d8; NOP
+        cpu.registers.sp = 0xFFFE;
+        cpu.registers.flags.set_bits(0xB0);
+
+        cpu.set_trace_logging(true);
+        mmu.set_access_logging(true);
+        cpu.step(&mmu).unwrap();
+        cpu.step(&mmu).unwrap();
+
+        assert_eq!(
+            cpu.take_trace_log(),
+            [
+                "A:00 F:B0 B:00 C:00 D:00 E:00 H:00 L:00 SP:FFFE PC:C000 PCMEM:3E,42,06,10",
+                "A:42 F:B0 B:00 C:00 D:00 E:00 H:00 L:00 SP:FFFE PC:C002 PCMEM:06,10,00,00",
+            ]
+        );
+
+        // the trace peeks memory, rather than accessing it over the bus
+        assert_eq!(
+            mmu.take_access_log(),
+            [Read(0xC000), Read(0xC001), Read(0xC002), Read(0xC003)]
+        );
+
+        cpu.set_trace_logging(false);
+        cpu.step(&mmu).unwrap();
+        assert!(cpu.take_trace_log().is_empty());
+    }
+
+    #[test]
+    fn test_interrupt_latency() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+        load(&mut cpu, &mmu, &[0x00, 0x00, 0xFB, 0x00]); // NOP; NOP; EI; NOP
+        cpu.registers.sp = 0xD000;
+        mmu.write_byte(0xFFFF, 0x05);
+        cpu.set_latency_tracking(true);
+
+        cpu.step(&mmu).unwrap();
+        Interrupt::Timer.request(&mmu);
+        // the NOPs, EI and the NOP after it run first, then the interrupt is
+        // dispatched
+        for _ in 0..5 {
+            cpu.step(&mmu).unwrap();
+        }
+        assert_eq!(cpu.registers.pc, 0x0051);
+
+        let timer = cpu.interrupt_latency(Interrupt::Timer);
+        assert_eq!((timer.count, timer.min, timer.max), (1, 8, 8));
+        assert_eq!(cpu.interrupt_latency(Interrupt::VBlank).count, 0);
+
+        // straight away, with IME already set
+        cpu.ime = true;
+        Interrupt::VBlank.request(&mmu);
+        cpu.step(&mmu).unwrap();
+        cpu.step(&mmu).unwrap();
+        assert_eq!(cpu.interrupt_latency(Interrupt::VBlank).mean(), Some(5.0));
+    }
+
+    #[test]
+    fn test_source_breakpoints() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+        load(&mut cpu, &mmu, &[0x40, 0x40, 0x3C]); // LD B,B; LD B,B; INC A
+
+        cpu.step(&mmu).unwrap();
+        cpu.set_source_breakpoints(true);
+        assert!(matches!(
+            cpu.step(&mmu),
+            Err(ExecutionError::Breakpoint { pc: 0xC001 })
+        ));
+
+        // the breakpoint has run, so carrying on doesn't stop there again
+        cpu.step(&mmu).unwrap();
+        assert_eq!(cpu.registers.a, 0x01);
+    }
+
+    #[test]
+    fn test_debug_messages() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+        // LD D,D; JR over the message; INC A
+        let mut program = vec![0x52, 0x18, 0x09, 0x64, 0x64, 0x00, 0x00];
+        program.extend_from_slice(b"A=%A%");
+        program.push(0x3C);
+        load(&mut cpu, &mmu, &program);
+        cpu.registers.a = 0x07;
+
+        // nothing is recorded until it's asked for
+        cpu.step(&mmu).unwrap();
+        assert!(cpu.take_debug_messages().is_empty());
+
+        cpu.registers.pc = 0xC000;
+        cpu.set_debug_messages(true);
+        mmu.set_access_logging(true);
+        for _ in 0..3 {
+            cpu.step(&mmu).unwrap();
+        }
+        assert_eq!(
+            cpu.take_debug_messages(),
+            [DebugMessage {
+                pc: 0xC000,
+                text: "A=07".to_string()
+            }]
+        );
+        assert_eq!(cpu.registers.a, 0x08);
+
+        // the message is peeked, so only the instructions are accessed
+        assert_eq!(
+            mmu.take_access_log(),
+            [Read(0xC000), Read(0xC001), Read(0xC002), Read(0xC00C)]
+        );
     }
 }
@@ -0,0 +1,228 @@
+use std::{fmt, fs, io, ops::RangeInclusive, path::Path};
+
+use crate::{
+    cartridge::header::CartridgeHeader,
+    compatibility::Compatibility,
+    cpu::{
+        registers::SM83RegisterBank,
+        sm83::{HistoryEntry, SM83},
+    },
+    mmu::MMU,
+    model::Model,
+    watchdog::Watchdog,
+};
+
+/// How many bytes on either side of PC/SP to include in a report.
+const WINDOW: u16 = 0x20;
+
+/// A diagnostic snapshot of the emulator, taken when execution can't continue.
+///
+/// The report is plain text so it can be attached to an issue as-is.
+#[derive(Debug, Clone)]
+pub struct CrashReport {
+    pub reason: String,
+    pub registers: SM83RegisterBank,
+    /// The header of the loaded cartridge, if there is one.
+    pub cartridge: Option<CartridgeHeader>,
+    pub config: Config,
+    /// The last instructions the CPU executed, oldest first. See
+    /// [`SM83::history`].
+    pub history: Vec<HistoryEntry>,
+    pub regions: Vec<MemoryRegion>,
+}
+
+/// How the emulator was set up, since the same program can behave
+/// differently under other settings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    pub model: Model,
+    pub boot_rom_mapped: bool,
+    /// See [`MMU::set_ppu_access_locks`].
+    pub ppu_access_locks: bool,
+    pub double_speed: bool,
+    pub compatibility: Compatibility,
+    /// The watchdog's limit and span, if one was watching.
+    pub watchdog: Option<(u64, u16)>,
+}
+
+/// A labelled dump of a contiguous block of memory.
+#[derive(Debug, Clone)]
+pub struct MemoryRegion {
+    pub label: &'static str,
+    pub start: u16,
+    /// `None` for addresses that couldn't be read.
+    pub bytes: Vec<Option<u8>>,
+}
+
+impl MemoryRegion {
+    fn capture(mmu: &MMU, label: &'static str, range: RangeInclusive<u16>) -> Self {
+        MemoryRegion {
+            label,
+            start: *range.start(),
+            // peeked, so taking a report doesn't show up in the access log or
+            // to observers, and isn't locked out of VRAM or OAM by the PPU
+            bytes: range.map(|addr| mmu.peek_byte(addr)).collect(),
+        }
+    }
+}
+
+impl CrashReport {
+    pub fn capture(cpu: &SM83, mmu: &MMU, reason: impl Into<String>) -> Self {
+        let pc = cpu.registers.pc;
+        let sp = cpu.registers.sp;
+
+        CrashReport {
+            reason: reason.into(),
+            registers: cpu.registers.clone(),
+            cartridge: mmu.cartridge_header(),
+            config: Config {
+                model: mmu.model(),
+                boot_rom_mapped: mmu.boot_rom_mapped(),
+                ppu_access_locks: mmu.ppu_access_locks(),
+                double_speed: cpu.double_speed(),
+                compatibility: mmu.compatibility(),
+                watchdog: None,
+            },
+            history: cpu.history(),
+            regions: vec![
+                MemoryRegion::capture(
+                    mmu,
+                    "around PC",
+                    pc.saturating_sub(WINDOW)..=pc.saturating_add(WINDOW),
+                ),
+                MemoryRegion::capture(mmu, "stack", sp..=sp.saturating_add(WINDOW)),
+                MemoryRegion::capture(mmu, "I/O registers", 0xFF00..=0xFF7F),
+                MemoryRegion::capture(mmu, "HRAM", 0xFF80..=0xFFFE),
+                MemoryRegion::capture(mmu, "IE", 0xFFFF..=0xFFFF),
+            ],
+        }
+    }
+
+    /// Note the settings of the watchdog that was watching the CPU.
+    pub fn with_watchdog(mut self, watchdog: &Watchdog) -> Self {
+        self.config.watchdog = Some((watchdog.limit, watchdog.span));
+        self
+    }
+
+    /// Write the report to a file, overwriting it if it exists.
+    pub fn write_to(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+}
+
+impl fmt::Display for CrashReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "magi crash report")?;
+        writeln!(f, "reason: {}", self.reason)?;
+        writeln!(f)?;
+        writeln!(f, "registers:")?;
+        writeln!(f, "{:#?}", self.registers)?;
+
+        writeln!(f)?;
+        match &self.cartridge {
+            Some(header) => writeln!(f, "cartridge:\n{:#?}", header)?,
+            None => writeln!(f, "cartridge: none loaded")?,
+        }
+
+        writeln!(f)?;
+        writeln!(f, "config:\n{:#?}", self.config)?;
+
+        writeln!(f)?;
+        match self.history.is_empty() {
+            true => writeln!(f, "recent instructions: none")?,
+            false => {
+                writeln!(f, "recent instructions:")?;
+                for entry in &self.history {
+                    let instruction = entry.instruction();
+                    let bytes = [entry.opcode, entry.operands[0], entry.operands[1]];
+                    let bytes: Vec<String> = bytes[..instruction.length() as usize]
+                        .iter()
+                        .map(|byte| format!("{:02X}", byte))
+                        .collect();
+                    writeln!(
+                        f,
+                        "  {:04X}: {:<8} {}",
+                        entry.pc,
+                        bytes.join(" "),
+                        instruction
+                    )?;
+                }
+            }
+        }
+
+        for region in &self.regions {
+            writeln!(f)?;
+            writeln!(f, "{} ({:04X}):", region.label, region.start)?;
+
+            for (row, chunk) in region.bytes.chunks(16).enumerate() {
+                write!(f, "  {:04X}:", region.start as usize + row * 16)?;
+                for byte in chunk {
+                    match byte {
+                        Some(byte) => write!(f, " {:02X}", byte)?,
+                        None => write!(f, " ??")?,
+                    }
+                }
+                writeln!(f)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cartridge::Validation, cpu::sm83::HISTORY_LEN};
+
+    #[test]
+    fn test_report_includes_unusable_area() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+        cpu.registers.pc = 0xFEA0;
+        mmu.write_byte(0xFE9F, 0xAB);
+
+        let report = CrashReport::capture(&cpu, &mmu, "test");
+        let around_pc = &report.regions[0];
+
+        assert_eq!(around_pc.start, 0xFE80);
+        assert_eq!(around_pc.bytes[0x1F], Some(0xAB));
+        assert_eq!(around_pc.bytes[0x20], Some(0x00));
+        assert!(report.to_string().contains("FE90: 00"));
+    }
+
+    #[test]
+    fn test_report_has_history_and_header() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+        mmu.set_access_logging(true);
+
+        let report = CrashReport::capture(&cpu, &mmu, "test");
+        assert_eq!(report.cartridge, None);
+        assert!(report.history.is_empty());
+        assert_eq!(report.config.model, Model::Dmg);
+        assert_eq!(report.config.watchdog, None);
+        // taking a report doesn't touch the bus
+        assert_eq!(mmu.take_access_log(), []);
+
+        let mut rom = vec![0; 0x8000];
+        rom[0x0134..0x0138].copy_from_slice(b"TEST");
+        rom[0x0100..0x0103].copy_from_slice(&[0xC3, 0x00, 0x01]); // JP 0100
+        mmu.load_rom_bytes(&rom, Validation::Permissive).unwrap();
+        cpu.registers.pc = 0x0100;
+        // without trace logging
+        for _ in 0..HISTORY_LEN + 8 {
+            cpu.step(&mmu).unwrap();
+        }
+
+        let report = CrashReport::capture(&cpu, &mmu, "test").with_watchdog(&Watchdog::new(100, 8));
+        assert_eq!(report.cartridge.as_ref().unwrap().title, "TEST");
+        assert_eq!(report.config.watchdog, Some((100, 8)));
+        assert_eq!(report.history.len(), HISTORY_LEN);
+        assert_eq!(report.history[0].pc, 0x0100);
+
+        let text = report.to_string();
+        assert!(text.contains("\n  0100: C3 00 01 JP 0100\n"));
+        assert!(text.contains("watchdog: Some("));
+    }
+}
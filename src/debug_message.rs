@@ -0,0 +1,172 @@
+//! BGB's debug message protocol, which homebrew uses for printf-style
+//! debugging.
+//!
+//! `LD D,D` does nothing on hardware, so it's used as a marker. When it's
+//! followed by a `JR` over a `$6464` signature, the bytes being jumped over
+//! describe a message:
+//!
+//! ```text
+//! ld d, d
+//! jr .end
+//! dw $6464
+//! dw $0000        ; the message follows inline
+//! db "A is %A%"
+//! .end:
+//! ```
+//!
+//! With `dw $0001` instead, the message is somewhere else: `dw address,
+//! bank` follows, pointing at text that ends with a zero byte. The address
+//! is read as currently mapped; the bank isn't switched in.
+//!
+//! Register names between `%`s (like `%A%` or `%HL%`) are replaced with their
+//! values in hex.
+
+use std::fmt;
+
+use crate::{bus::Bus, cpu::registers::SM83RegisterBank};
+
+/// The opcode of `LD D,D`.
+pub(crate) const MARKER: u8 = 0x52;
+
+/// What comes after the `JR` in a message.
+const SIGNATURE: u16 = 0x6464;
+
+/// The longest message read through a pointer, in case the terminator is
+/// missing.
+const MAX_LEN: u16 = 0x100;
+
+/// A message the program printed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DebugMessage {
+    /// Where the `LD D,D` was.
+    pub pc: u16,
+    pub text: String,
+}
+
+impl fmt::Display for DebugMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04X}: {}", self.pc, self.text)
+    }
+}
+
+/// Read the message described at `addr`, just after an `LD D,D`, or `None`
+/// if there isn't one there (in which case the `LD D,D` was just an
+/// `LD D,D`).
+///
+/// Memory is peeked, so reading the message isn't a bus access.
+pub(crate) fn read(bus: &impl Bus, addr: u16, registers: &SM83RegisterBank) -> Option<String> {
+    let byte = |offset: u16| bus.peek_byte(addr.wrapping_add(offset)).unwrap_or(0xFF);
+    let word = |offset: u16| u16::from_le_bytes([byte(offset), byte(offset + 1)]);
+
+    // JR e8, whose target is the end of the message
+    if byte(0) != 0x18 || word(2) != SIGNATURE {
+        return None;
+    }
+    let end = byte(1) as i8 as i16 + 2;
+
+    let text: Vec<u8> = match word(4) {
+        0x0000 => (6..end.max(6) as u16).map(byte).collect(),
+        0x0001 => {
+            let start = word(6);
+            (0..MAX_LEN)
+                .map(|offset| bus.peek_byte(start.wrapping_add(offset)).unwrap_or(0))
+                .take_while(|&byte| byte != 0)
+                .collect()
+        }
+        _ => return None,
+    };
+
+    let text: String = text.iter().map(|&byte| byte as char).collect();
+    Some(substitute(&text, registers))
+}
+
+/// Replace each `%REGISTER%` with the register's value.
+fn substitute(text: &str, registers: &SM83RegisterBank) -> String {
+    let mut parts = text.split('%');
+    let mut result = parts.next().unwrap_or_default().to_string();
+
+    // odd parts are between a pair of %s
+    let mut parts = parts.peekable();
+    while let Some(name) = parts.next() {
+        let Some(rest) = parts.next() else {
+            // an unmatched % stays as it is
+            result.push('%');
+            result.push_str(name);
+            break;
+        };
+        match register(name, registers) {
+            Some(value) => result.push_str(&value),
+            None => {
+                result.push('%');
+                result.push_str(name);
+                result.push('%');
+            }
+        }
+        result.push_str(rest);
+    }
+
+    result
+}
+
+fn register(name: &str, registers: &SM83RegisterBank) -> Option<String> {
+    let byte = |value: u8| Some(format!("{:02X}", value));
+    let word = |value: u16| Some(format!("{:04X}", value));
+
+    match name.to_ascii_uppercase().as_str() {
+        "A" => byte(registers.a),
+        "F" => byte(registers.flags.bits()),
+        "B" => byte(registers.b),
+        "C" => byte(registers.c),
+        "D" => byte(registers.d),
+        "E" => byte(registers.e),
+        "H" => byte(registers.h),
+        "L" => byte(registers.l),
+        "AF" => word(registers.af()),
+        "BC" => word(registers.bc()),
+        "DE" => word(registers.de()),
+        "HL" => word(registers.hl()),
+        "SP" => word(registers.sp),
+        "PC" => word(registers.pc),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::FlatMemory;
+
+    #[test]
+    fn test_inline_message() {
+        let memory = FlatMemory::new();
+        let mut registers = SM83RegisterBank::new();
+        registers.a = 0x2A;
+        registers.set_hl(0xC0DE);
+
+        let text = b"A=%A% HL=%hl% %Q% 100%";
+        let mut message = vec![0x18, 4 + text.len() as u8, 0x64, 0x64, 0x00, 0x00];
+        message.extend_from_slice(text);
+        memory.load(0xC000, &message);
+        assert_eq!(
+            read(&memory, 0xC000, &registers).as_deref(),
+            Some("A=2A HL=C0DE %Q% 100%")
+        );
+
+        // no signature, so it's just an LD D,D
+        memory.load(0xC002, &[0x00]);
+        assert_eq!(read(&memory, 0xC000, &registers), None);
+    }
+
+    #[test]
+    fn test_pointer_message() {
+        let memory = FlatMemory::new();
+        let registers = SM83RegisterBank::new();
+        memory.load(0xC000, &[0x18, 0x08, 0x64, 0x64, 0x01, 0x00, 0x00, 0xD0]);
+        memory.load(0xD000, b"elsewhere\0ignored");
+
+        assert_eq!(
+            read(&memory, 0xC000, &registers).as_deref(),
+            Some("elsewhere")
+        );
+    }
+}
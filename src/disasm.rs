@@ -0,0 +1,209 @@
+//! Turning SM83 machine code back into readable mnemonics.
+//!
+//! ```
+//! use magi::disasm::disassemble;
+//!
+//! let lines = disassemble(&[0xF0, 0x44, 0xFE, 0x90], 0x0150);
+//! assert_eq!(lines[0].to_string(), "0150: F0 44     LD A,(FF44)");
+//! assert_eq!(lines[1].instruction.to_string(), "CP 90");
+//! ```
+
+use std::{fmt, ops::RangeInclusive};
+
+use crate::{
+    cpu::instructions::{decode, length, Instruction},
+    mmu::MMU,
+};
+
+/// A single disassembled instruction, and where it came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    pub address: u16,
+    /// The encoded instruction, opcode first.
+    pub bytes: Vec<u8>,
+    pub instruction: Instruction,
+}
+
+impl Line {
+    /// The address a relative jump lands on, if this is one.
+    pub fn relative_target(&self) -> Option<u16> {
+        match self.instruction {
+            Instruction::JumpRelative(_, offset) => Some(
+                self.address
+                    .wrapping_add(self.bytes.len() as u16)
+                    .wrapping_add_signed(offset as i16),
+            ),
+            _ => None,
+        }
+    }
+}
+
+/// Lines are written as `address: bytes  mnemonic`, with the target of a
+/// relative jump added as a comment.
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes: Vec<String> = self
+            .bytes
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect();
+
+        write!(
+            f,
+            "{:04X}: {:<10}{}",
+            self.address,
+            bytes.join(" "),
+            self.instruction
+        )?;
+        if let Some(target) = self.relative_target() {
+            write!(f, " ; {:04X}", target)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Disassemble a block of machine code, as if it were loaded at `origin`.
+///
+/// Data is decoded as code, since there's no way to tell the two apart. An
+/// instruction cut off by the end of `bytes` is left out.
+pub fn disassemble(bytes: &[u8], origin: u16) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+
+    while let Some(&opcode) = bytes.get(offset) {
+        let end = offset + length(opcode) as usize;
+        let Some(encoded) = bytes.get(offset..end) else {
+            break;
+        };
+
+        let operand = |index| encoded.get(index).copied().unwrap_or(0);
+        lines.push(Line {
+            address: origin.wrapping_add(offset as u16),
+            bytes: encoded.to_vec(),
+            instruction: decode(opcode, operand(1), operand(2)),
+        });
+        offset = end;
+    }
+
+    lines
+}
+
+/// Disassemble every instruction starting within `range` of memory.
+///
+/// The last instruction may read past the end of the range for its
+/// operands.
+///
+/// Memory is peeked, so this can run during emulation without showing up in
+/// the access log or to observers, or being locked out of VRAM or OAM.
+pub fn disassemble_range(mmu: &MMU, range: RangeInclusive<u16>) -> Vec<Line> {
+    let start = *range.start();
+    // leave room for the operands of an instruction starting on the last byte
+    let end = range.end().saturating_add(2);
+    let bytes: Vec<u8> = (start..=end)
+        .map_while(|addr| mmu.peek_byte(addr))
+        .collect();
+
+    disassemble(&bytes, start)
+        .into_iter()
+        .take_while(|line| range.contains(&line.address))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mnemonics(bytes: &[u8]) -> Vec<String> {
+        disassemble(bytes, 0x0000)
+            .iter()
+            .map(|line| line.instruction.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_operand_formatting() {
+        assert_eq!(
+            mnemonics(&[
+                0x3E, 0x05, // LD A,d8
+                0x22, // LD (HL+),A
+                0xE2, // LD (C),A
+                0xFA, 0x00, 0xC1, // LD A,(a16)
+                0x01, 0x34, 0x12, // LD BC,d16
+                0x08, 0x00, 0xD0, // LD (a16),SP
+                0xF8, 0xFE, // LD HL,SP+r8
+                0xE8, 0x02, // ADD SP,r8
+                0x8E, // ADC A,(HL)
+                0xB9, // CP C
+            ]),
+            [
+                "LD A,05",
+                "LD (HL+),A",
+                "LD (FF00+C),A",
+                "LD A,(C100)",
+                "LD BC,1234",
+                "LD (D000),SP",
+                "LD HL,SP-2",
+                "ADD SP,2",
+                "ADC A,(HL)",
+                "CP C",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_branch_formatting() {
+        assert_eq!(
+            mnemonics(&[
+                0xC3, 0x50, 0x01, // JP a16
+                0xDA, 0x00, 0x40, // JP C,a16
+                0xE9, // JP HL
+                0xC4, 0x00, 0x20, // CALL NZ,a16
+                0xC8, // RET Z
+                0xC9, // RET
+                0xD9, // RETI
+                0xFF, // RST 38h
+            ]),
+            [
+                "JP 0150",
+                "JP C,4000",
+                "JP HL",
+                "CALL NZ,2000",
+                "RET Z",
+                "RET",
+                "RETI",
+                "RST 38",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_relative_jump_targets() {
+        let lines = disassemble(&[0x00, 0x20, 0xFD], 0xC000); // NOP; JR NZ,-3
+
+        assert_eq!(lines[1].relative_target(), Some(0xC000));
+        assert_eq!(lines[1].to_string(), "C001: 20 FD     JR NZ,-3 ; C000");
+    }
+
+    #[test]
+    fn test_truncated_instruction_is_left_out() {
+        assert_eq!(mnemonics(&[0x00, 0xC3, 0x50]), ["NOP"]);
+    }
+
+    #[test]
+    fn test_disassemble_range() {
+        let mmu = MMU::new();
+        for (i, byte) in [0x3C, 0xC3, 0x00, 0xC0].iter().enumerate() {
+            mmu.write_byte(0xC000 + i as u16, *byte);
+        }
+
+        // the JP starts inside the range, so its operands are read past it
+        let lines = disassemble_range(&mmu, 0xC000..=0xC001);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1].instruction, Instruction::Jump(None, 0xC000));
+
+        // carries on through the unusable area, which reads 0x00 on the DMG
+        let lines = disassemble_range(&mmu, 0xFE9E..=0xFEA1);
+        assert_eq!(lines.len(), 4);
+    }
+}
@@ -1,19 +1,619 @@
-use crate::{cpu::sm83::SM83, mmu::MMU};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{
+    cartridge::{
+        dat::{Dat, DumpStatus},
+        header::{CartridgeHeader, HeaderProblem},
+        RomError, Validation,
+    },
+    cpu::{
+        error::ExecutionError,
+        interrupts::{Interrupt, Latency},
+        registers::{Flag, SM83RegisterBank},
+        sm83::SM83,
+    },
+    crash::CrashReport,
+    debug_message::DebugMessage,
+    mmu::MMU,
+    model::Model,
+    storage::{FileStorage, StorageBackend},
+    symbols::Symbols,
+    watchdog::Watchdog,
+};
+
+/// What [`GB::reload_if_changed`] does with the state of the old build.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Reload {
+    /// Power on again, as if the cartridge had just been put in.
+    #[default]
+    Reset,
+    /// Keep the CPU, memory and cartridge RAM as they are, and carry on in
+    /// the new code from wherever the old code was. The save file stays the
+    /// same, and the mapper starts over (see [`MMU::reload_rom_bytes`]).
+    KeepState,
+}
+
+/// Settings for working on a homebrew ROM. See [`GB::set_dev_mode`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DevMode {
+    pub reload: Reload,
+}
+
+/// The ROM file that was loaded, to notice it being rebuilt.
+struct RomFile {
+    path: PathBuf,
+    validation: Validation,
+    modified: Option<SystemTime>,
+}
+
+/// Where the cartridge's battery-backed data is kept.
+struct SaveFile {
+    storage: Box<dyn StorageBackend>,
+    key: String,
+}
 
 pub struct GB {
     cpu: SM83,
     pub mmu: MMU,
+    pub watchdog: Watchdog,
+    save_file: Option<SaveFile>,
+    crash_report: Option<CrashReport>,
+    rom_file: Option<RomFile>,
+    dev_mode: Option<DevMode>,
+    symbols: Option<Symbols>,
+    dat: Option<Dat>,
+    dump_status: Option<DumpStatus>,
 }
 
 impl GB {
-    fn new() -> Self {
+    pub fn new() -> Self {
         GB {
             cpu: SM83::new(),
             mmu: MMU::new(),
+            watchdog: Watchdog::default(),
+            save_file: None,
+            crash_report: None,
+            rom_file: None,
+            dev_mode: None,
+            symbols: None,
+            dat: None,
+            dump_status: None,
+        }
+    }
+
+    /// Load a cartridge ROM from a file, along with its save file (the ROM's
+    /// path with a `.sav` extension) if it has a battery.
+    ///
+    /// The previous cartridge is saved first. If that fails, or the new ROM
+    /// or its save file can't be read, or the ROM can't be loaded, the
+    /// previous cartridge stays in.
+    pub fn load_rom(
+        &mut self,
+        path: impl AsRef<Path>,
+        validation: Validation,
+    ) -> Result<Vec<HeaderProblem>, RomError> {
+        let path = path.as_ref();
+        let modified = modified(path);
+        let rom = fs::read(path)?;
+        self.save().map_err(RomError::SaveFile)?;
+
+        // read everything before touching the MMU, so nothing can fail halfway
+        let root = path.parent().unwrap_or(Path::new(""));
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let storage = FileStorage::new(root);
+        let key = format!("{stem}.sav");
+        let battery = CartridgeHeader::parse(&rom)
+            .is_ok_and(|header| self.mmu.save_type_for(&header).battery);
+        let save = match battery {
+            true => storage.load(&key).map_err(RomError::SaveFile)?,
+            false => None,
+        };
+
+        let problems = self.mmu.load_rom_bytes(&rom, validation)?;
+        if let Some(data) = save {
+            self.mmu.load_save_data(&data);
+        }
+        self.save_file = battery.then(|| SaveFile {
+            storage: Box::new(storage),
+            key,
+        });
+        self.dump_status = self.dat.as_ref().map(|dat| dat.check(&rom));
+        self.rom_file = Some(RomFile {
+            path: path.to_path_buf(),
+            validation,
+            modified,
+        });
+        self.load_symbols();
+
+        Ok(problems)
+    }
+
+    /// Check the ROMs loaded from now on against a No-Intro DAT file, or stop
+    /// checking them with `None`. See [`GB::dump_status`].
+    pub fn set_dat(&mut self, dat: Option<Dat>) {
+        self.dat = dat;
+    }
+
+    /// What the DAT file said about the loaded ROM, if there was one set
+    /// when it was loaded.
+    pub fn dump_status(&self) -> Option<&DumpStatus> {
+        self.dump_status.as_ref()
+    }
+
+    /// Make working on a homebrew ROM easier, or go back to normal with
+    /// `None`. In dev mode:
+    ///
+    /// - [`GB::reload_if_changed`] picks up new builds of the ROM file.
+    /// - The ROM's symbol file (its path with a `.sym` extension) is loaded
+    ///   alongside it, if there is one. See [`GB::symbols`].
+    /// - `LD D,D` debug messages are recorded, and `LD B,B` stops
+    ///   execution, as in BGB. See [`SM83::set_debug_messages`] and
+    ///   [`SM83::set_source_breakpoints`].
+    pub fn set_dev_mode(&mut self, dev_mode: Option<DevMode>) {
+        self.dev_mode = dev_mode;
+        self.configure_cpu();
+        self.load_symbols();
+    }
+
+    /// Set up the CPU's debugging hooks for dev mode.
+    fn configure_cpu(&mut self) {
+        let enabled = self.dev_mode.is_some();
+        self.cpu.set_debug_messages(enabled);
+        self.cpu.set_source_breakpoints(enabled);
+    }
+
+    /// Load the symbol file next to the ROM, in dev mode. A missing or
+    /// unreadable file just means there are no symbols.
+    fn load_symbols(&mut self) {
+        self.symbols = match (&self.dev_mode, &self.rom_file) {
+            (Some(_), Some(rom_file)) => Symbols::load(rom_file.path.with_extension("sym")).ok(),
+            _ => None,
+        };
+    }
+
+    /// The loaded ROM's symbols, in dev mode.
+    pub fn symbols(&self) -> Option<&Symbols> {
+        self.symbols.as_ref()
+    }
+
+    /// In dev mode, load the ROM file again if it has changed since it was
+    /// loaded, handling the old state as the [`DevMode`] says. Returns
+    /// whether it was reloaded.
+    ///
+    /// This is meant to be called regularly, e.g. once a frame. If the new
+    /// build can't be loaded (say, because it's only half written), the old
+    /// one stays in and loading is tried again next time.
+    pub fn reload_if_changed(&mut self) -> Result<bool, RomError> {
+        let (Some(dev_mode), Some(rom_file)) = (self.dev_mode, &self.rom_file) else {
+            return Ok(false);
+        };
+        let modified = modified(&rom_file.path);
+        if modified == rom_file.modified {
+            return Ok(false);
         }
+
+        let path = rom_file.path.clone();
+        let validation = rom_file.validation;
+        match dev_mode.reload {
+            Reload::Reset => {
+                self.load_rom(&path, validation)?;
+                self.power_on(self.mmu.model());
+            }
+            Reload::KeepState => {
+                self.mmu.reload_rom_bytes(&fs::read(&path)?, validation)?;
+                if let Some(rom_file) = &mut self.rom_file {
+                    rom_file.modified = modified;
+                }
+                self.load_symbols();
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Get ready to run the loaded cartridge, as `model`.
+    ///
+    /// With a boot ROM loaded, the CPU starts at 0x0000 and runs it.
+    /// Otherwise, the CPU and memory are set up the way the boot ROM would
+    /// have left them, and the cartridge starts at 0x0100.
+    pub fn power_on(&mut self, model: Model) {
+        self.cpu = SM83::new();
+        self.configure_cpu();
+        self.mmu.set_model(model);
+        if self.mmu.boot_rom_mapped() {
+            return;
+        }
+
+        self.mmu.skip_boot_rom(model);
+        self.cpu.registers = SM83RegisterBank::post_boot(model);
+
+        // the DMG boot ROM's last check leaves H and C clear for a header
+        // checksum of 0
+        let checksum = self
+            .mmu
+            .cartridge_header()
+            .map(|header| header.header_checksum);
+        if matches!(model, Model::Dmg | Model::Mgb) && checksum == Some(0) {
+            self.cpu.registers.flags.unset(Flag::HalfCarry);
+            self.cpu.registers.flags.unset(Flag::Carry);
+        }
+    }
+
+    /// Keep the loaded cartridge's save data in `storage` under `key`,
+    /// loading what's already there.
+    ///
+    /// Cartridges without a battery have nothing to save, so this does
+    /// nothing for them.
+    pub fn attach_storage(
+        &mut self,
+        storage: Box<dyn StorageBackend>,
+        key: impl Into<String>,
+    ) -> io::Result<()> {
+        if !self.mmu.has_battery() {
+            self.save_file = None;
+            return Ok(());
+        }
+
+        let key = key.into();
+        if let Some(data) = storage.load(&key)? {
+            self.mmu.load_save_data(&data);
+        }
+        self.save_file = Some(SaveFile { storage, key });
+
+        Ok(())
     }
 
-    fn run(&mut self) {
-        self.cpu.run(&self.mmu)
+    /// Write the cartridge's save data out. This also happens when the GB
+    /// is dropped, but errors are lost then.
+    pub fn save(&mut self) -> io::Result<()> {
+        match (&mut self.save_file, self.mmu.save_data()) {
+            (Some(file), Some(data)) => file.storage.save(&file.key, &data),
+            _ => Ok(()),
+        }
+    }
+
+    /// See [`MMU::set_tilt`].
+    pub fn set_tilt(&self, x: f32, y: f32) {
+        self.mmu.set_tilt(x, y);
+    }
+
+    /// See [`SM83::set_debug_messages`].
+    pub fn set_debug_messages(&mut self, enabled: bool) {
+        self.cpu.set_debug_messages(enabled);
+    }
+
+    /// See [`SM83::take_debug_messages`].
+    pub fn take_debug_messages(&mut self) -> Vec<DebugMessage> {
+        self.cpu.take_debug_messages()
+    }
+
+    /// See [`SM83::set_latency_tracking`].
+    pub fn set_latency_tracking(&mut self, enabled: bool) {
+        self.cpu.set_latency_tracking(enabled);
+    }
+
+    /// See [`SM83::interrupt_latency`].
+    pub fn interrupt_latency(&self, interrupt: Interrupt) -> Latency {
+        self.cpu.interrupt_latency(interrupt)
+    }
+
+    /// Run until execution can't continue. A [`CrashReport`] of the state
+    /// it stopped in is kept until the next run.
+    pub fn run(&mut self) -> ExecutionError {
+        let error = self.cpu.run(&self.mmu, &mut self.watchdog);
+        self.crash_report = Some(
+            CrashReport::capture(&self.cpu, &self.mmu, error.to_string())
+                .with_watchdog(&self.watchdog),
+        );
+
+        error
+    }
+
+    /// The report from the last time [`GB::run`] stopped.
+    pub fn crash_report(&self) -> Option<&CrashReport> {
+        self.crash_report.as_ref()
+    }
+}
+
+/// When a file was last modified, if the platform can tell.
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+impl Default for GB {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for GB {
+    fn drop(&mut self) {
+        let _ = self.save();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::{
+        cartridge::{dat::sha1, ROM_BANK_SIZE},
+        compatibility::{Compatibility, OnUnimplemented, Unimplemented},
+    };
+
+    /// Write an MBC1 ROM with 8KB of RAM to `dir`.
+    fn write_rom(dir: &Path, name: &str, cartridge_type: u8) -> std::path::PathBuf {
+        let mut rom = vec![0; 2 * ROM_BANK_SIZE];
+        rom[0x0147] = cartridge_type;
+        rom[0x0149] = 0x02;
+
+        let path = dir.join(name);
+        fs::write(&path, rom).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_power_on() {
+        let mut gb = GB::new();
+        gb.mmu.load_boot_rom_bytes(&[0; 0x100]).unwrap();
+        gb.power_on(Model::Dmg);
+        assert_eq!(gb.cpu.registers.pc, 0x0000);
+
+        let mut gb = GB::new();
+        gb.power_on(Model::Dmg);
+        assert_eq!(gb.cpu.registers.pc, 0x0100);
+        assert_eq!(gb.cpu.registers.af(), 0x01B0);
+        assert_eq!(gb.mmu.read_byte(0xFF40), Some(0x91));
+
+        let mut rom = vec![0; 2 * ROM_BANK_SIZE];
+        rom[0x0147] = 0x00;
+        gb.mmu.load_rom_bytes(&rom, Validation::Permissive).unwrap();
+        gb.power_on(Model::Dmg);
+        assert_eq!(gb.cpu.registers.af(), 0x0180);
+    }
+
+    #[test]
+    fn test_stop_without_boot_rom() {
+        let mut rom = vec![0; 2 * ROM_BANK_SIZE];
+        rom[0x0100..0x0102].copy_from_slice(&[0x10, 0x00]); // STOP
+
+        // the DMG has no KEY1, so STOP always stops
+        let mut gb = GB::new();
+        gb.mmu.load_rom_bytes(&rom, Validation::Permissive).unwrap();
+        gb.power_on(Model::Dmg);
+        gb.cpu.step(&gb.mmu).unwrap();
+        assert!(gb.cpu.stopped());
+        assert!(!gb.cpu.double_speed());
+
+        let mut gb = GB::new();
+        gb.mmu.load_rom_bytes(&rom, Validation::Permissive).unwrap();
+        gb.power_on(Model::Cgb);
+        gb.mmu.write_byte(0xFF4D, 0x01);
+        gb.cpu.step(&gb.mmu).unwrap();
+        assert!(!gb.cpu.stopped());
+        assert!(gb.cpu.double_speed());
+    }
+
+    #[test]
+    fn test_run_keeps_crash_report() {
+        let mut rom = vec![0; 2 * ROM_BANK_SIZE];
+        rom[0x0100] = 0xD3;
+
+        let mut gb = GB::new();
+        gb.mmu.load_rom_bytes(&rom, Validation::Permissive).unwrap();
+        gb.power_on(Model::Dmg);
+        assert!(gb.crash_report().is_none());
+
+        assert!(matches!(
+            gb.run(),
+            ExecutionError::UnknownOpcode {
+                pc: 0x0100,
+                byte: 0xD3
+            }
+        ));
+        let report = gb.crash_report().unwrap();
+        assert_eq!(report.reason, "unknown opcode D3 at 0100");
+        let around_pc = &report.regions[0];
+        let offset = 0x0100 - around_pc.start as usize;
+        assert_eq!(around_pc.bytes[offset], Some(0xD3));
+    }
+
+    #[test]
+    fn test_run_stops_at_unimplemented_hardware() {
+        let mut rom = vec![0; 2 * ROM_BANK_SIZE];
+        rom[0x0100..0x0104].copy_from_slice(&[0xF0, 0x03, 0xF0, 0x4C]); // LDH A,(03); LDH A,(4C)
+
+        let mut gb = GB::new();
+        gb.mmu.load_rom_bytes(&rom, Validation::Permissive).unwrap();
+        gb.power_on(Model::Dmg);
+        gb.mmu.set_compatibility(Compatibility {
+            io_registers: OnUnimplemented::Stop,
+            ..Compatibility::default()
+        });
+
+        assert!(matches!(
+            gb.run(),
+            ExecutionError::Unimplemented {
+                pc: 0x0100,
+                hardware: Unimplemented::IoRegister(0xFF03)
+            }
+        ));
+        // carrying on stops at the next one
+        assert!(matches!(
+            gb.run(),
+            ExecutionError::Unimplemented { pc: 0x0102, .. }
+        ));
+        let report = gb.crash_report().unwrap();
+        assert_eq!(report.reason, "unmapped I/O register FF4C, at 0102");
+    }
+
+    #[test]
+    fn test_dump_status() {
+        let dir = std::env::temp_dir().join(format!("magi-dat-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = write_rom(&dir, "game.gb", 0x00);
+        let hash: String = sha1(&fs::read(&path).unwrap())
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect();
+        let dat = Dat::parse(&format!(
+            r#"<game name="Game (World)"><rom name="Game (World).gb" sha1="{hash}"/></game>"#
+        ));
+
+        let mut gb = GB::new();
+        gb.load_rom(&path, Validation::Permissive).unwrap();
+        assert_eq!(gb.dump_status(), None);
+
+        gb.set_dat(Some(dat));
+        gb.load_rom(&path, Validation::Permissive).unwrap();
+        assert_eq!(
+            gb.dump_status(),
+            Some(&DumpStatus::Good {
+                name: "Game (World)".to_string()
+            })
+        );
+
+        let other = write_rom(&dir, "other.gb", 0x01);
+        gb.load_rom(&other, Validation::Permissive).unwrap();
+        assert_eq!(gb.dump_status(), Some(&DumpStatus::Unknown));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_dev_mode() {
+        let dir = std::env::temp_dir().join(format!("magi-dev-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("dev.gb");
+        let mut rom = vec![0; 2 * ROM_BANK_SIZE];
+        rom[0x0100] = 0x40; // LD B,B
+        fs::write(&path, &rom).unwrap();
+        fs::write(dir.join("dev.sym"), "00:0150 Main\n").unwrap();
+
+        // rebuilding the ROM, with a timestamp that's sure to be different
+        let rebuild = |rom: &[u8], seconds: u64| {
+            fs::write(&path, rom).unwrap();
+            let time = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(seconds);
+            fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(time)
+                .unwrap();
+        };
+
+        let mut gb = GB::new();
+        gb.load_rom(&path, Validation::Permissive).unwrap();
+        assert!(gb.symbols().is_none());
+        gb.set_dev_mode(Some(DevMode {
+            reload: Reload::KeepState,
+        }));
+        assert_eq!(gb.symbols().unwrap().address("Main"), Some((0, 0x0150)));
+
+        gb.power_on(Model::Dmg);
+        assert!(matches!(
+            gb.run(),
+            ExecutionError::Breakpoint { pc: 0x0100 }
+        ));
+        assert!(!gb.reload_if_changed().unwrap());
+
+        gb.mmu.write_byte(0xC000, 0x42);
+        rom[0x0150] = 0x3C;
+        rebuild(&rom, 1000);
+        assert!(gb.reload_if_changed().unwrap());
+        assert!(!gb.reload_if_changed().unwrap());
+        assert_eq!(gb.cpu.registers.pc, 0x0101);
+        assert_eq!(gb.mmu.read_byte(0xC000), Some(0x42));
+        assert_eq!(gb.mmu.read_byte(0x0150), Some(0x3C));
+
+        // a half written build is left for next time
+        gb.set_dev_mode(Some(DevMode {
+            reload: Reload::Reset,
+        }));
+        rebuild(&rom[..0x100], 2000);
+        assert!(gb.reload_if_changed().is_err());
+        rebuild(&rom, 3000);
+        assert!(gb.reload_if_changed().unwrap());
+        assert_eq!(gb.cpu.registers.pc, 0x0100);
+
+        // the CPU's hooks survive powering on again
+        assert!(matches!(
+            gb.run(),
+            ExecutionError::Breakpoint { pc: 0x0100 }
+        ));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_battery_ram_is_saved() {
+        let dir = std::env::temp_dir().join(format!("magi-gb-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let battery = write_rom(&dir, "battery.gb", 0x03);
+        let no_battery = write_rom(&dir, "plain.gb", 0x02);
+
+        for path in [&battery, &no_battery] {
+            let mut gb = GB::new();
+            gb.load_rom(path, Validation::Permissive).unwrap();
+            gb.mmu.write_byte(0x0000, 0x0A);
+            gb.mmu.write_byte(0xA001, 0x42);
+        }
+
+        let save = fs::read(dir.join("battery.sav")).unwrap();
+        assert_eq!((save.len(), save[1]), (0x2000, 0x42));
+        assert!(!dir.join("plain.sav").exists());
+
+        let mut gb = GB::new();
+        gb.load_rom(&battery, Validation::Permissive).unwrap();
+        gb.mmu.write_byte(0x0000, 0x0A);
+        assert_eq!(gb.mmu.read_byte(0xA001), Some(0x42));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_loading_another_rom_saves_the_first() {
+        let dir = std::env::temp_dir().join(format!("magi-swap-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let first = write_rom(&dir, "first.gb", 0x03);
+        let second = write_rom(&dir, "second.gb", 0x03);
+
+        let mut gb = GB::new();
+        gb.load_rom(&first, Validation::Permissive).unwrap();
+        gb.mmu.write_byte(0x0000, 0x0A);
+        gb.mmu.write_byte(0xA000, 0x11);
+        gb.load_rom(&second, Validation::Permissive).unwrap();
+        assert_eq!(fs::read(dir.join("first.sav")).unwrap()[0], 0x11);
+
+        gb.mmu.write_byte(0x0000, 0x0A);
+        gb.mmu.write_byte(0xA000, 0x22);
+        drop(gb);
+        assert_eq!(fs::read(dir.join("first.sav")).unwrap()[0], 0x11);
+        assert_eq!(fs::read(dir.join("second.sav")).unwrap()[0], 0x22);
+
+        // a save file that can't be read leaves the first game in, still
+        // saving to its own file
+        fs::remove_file(dir.join("second.sav")).unwrap();
+        fs::create_dir(dir.join("second.sav")).unwrap();
+        let mut gb = GB::new();
+        gb.load_rom(&first, Validation::Permissive).unwrap();
+        gb.mmu.write_byte(0x0000, 0x0A);
+        assert!(matches!(
+            gb.load_rom(&second, Validation::Permissive),
+            Err(RomError::SaveFile(_))
+        ));
+        assert_eq!(gb.mmu.read_byte(0xA000), Some(0x11));
+        gb.mmu.write_byte(0xA000, 0x33);
+        drop(gb);
+        assert_eq!(fs::read(dir.join("first.sav")).unwrap()[0], 0x33);
+
+        fs::remove_dir_all(&dir).unwrap();
     }
 }
@@ -0,0 +1,168 @@
+//! The CGB's VRAM DMA (FF51-FF55), which copies to VRAM either all at once
+//! (general-purpose DMA) or 16 bytes per H-blank (H-blank DMA).
+//!
+//! This only tracks the registers; the MMU does the copying, and whatever
+//! runs the PPU has to call [`MMU::hblank`](crate::mmu::MMU::hblank).
+
+/// A copy for the MMU to do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Transfer {
+    pub source: u16,
+    /// Always in VRAM.
+    pub dest: u16,
+    pub len: u16,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Hdma {
+    source: u16,
+    /// An offset into VRAM.
+    dest: u16,
+    /// Whether an H-blank DMA is running.
+    active: bool,
+    /// The number of 16 byte blocks left, minus 1.
+    blocks: u8,
+}
+
+impl Hdma {
+    pub fn new() -> Self {
+        Hdma {
+            blocks: 0x7F,
+            ..Default::default()
+        }
+    }
+
+    /// Read FF51-FF55. Only FF55 can be read back.
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr {
+            0xFF55 => (!self.active as u8) << 7 | self.blocks,
+            _ => 0xFF,
+        }
+    }
+
+    /// Write FF51-FF55, returning the copy a general-purpose DMA asks for.
+    pub fn write(&mut self, addr: u16, value: u8) -> Option<Transfer> {
+        match addr {
+            0xFF51 => self.source = self.source & 0x00FF | (value as u16) << 8,
+            0xFF52 => self.source = self.source & 0xFF00 | (value & 0xF0) as u16,
+            0xFF53 => self.dest = self.dest & 0x00FF | ((value & 0x1F) as u16) << 8,
+            0xFF54 => self.dest = self.dest & 0xFF00 | (value & 0xF0) as u16,
+            0xFF55 => {
+                return match (value & 0x80 != 0, self.active) {
+                    (true, _) => {
+                        self.blocks = value & 0x7F;
+                        self.active = true;
+                        None
+                    }
+                    // writing with bit 7 clear during an H-blank DMA stops it
+                    (false, true) => {
+                        self.active = false;
+                        None
+                    }
+                    (false, false) => {
+                        let transfer = self.next((value & 0x7F) as u16 + 1);
+                        self.blocks = 0x7F;
+                        Some(transfer)
+                    }
+                };
+            }
+            _ => {}
+        }
+
+        None
+    }
+
+    /// Run the next block of an H-blank DMA, if one is running.
+    pub fn hblank(&mut self) -> Option<Transfer> {
+        if !self.active {
+            return None;
+        }
+
+        let transfer = self.next(1);
+        match self.blocks {
+            0 => {
+                self.active = false;
+                self.blocks = 0x7F;
+            }
+            _ => self.blocks -= 1,
+        }
+
+        Some(transfer)
+    }
+
+    /// Take the next `blocks` blocks, moving the addresses along.
+    fn next(&mut self, blocks: u16) -> Transfer {
+        let len = blocks * 0x10;
+        let transfer = Transfer {
+            source: self.source,
+            dest: 0x8000 | self.dest,
+            len,
+        };
+        self.source = self.source.wrapping_add(len);
+        self.dest = (self.dest + len) & 0x1FF0;
+
+        transfer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_general_purpose_dma() {
+        let mut hdma = Hdma::new();
+        hdma.write(0xFF51, 0xC1);
+        hdma.write(0xFF52, 0x2F);
+        hdma.write(0xFF53, 0xE8);
+        hdma.write(0xFF54, 0x0F);
+
+        let transfer = hdma.write(0xFF55, 0x02);
+        assert_eq!(
+            transfer,
+            Some(Transfer {
+                source: 0xC120,
+                dest: 0x8800,
+                len: 0x30
+            })
+        );
+        assert_eq!(hdma.read(0xFF55), 0xFF);
+    }
+
+    #[test]
+    fn test_hblank_dma() {
+        let mut hdma = Hdma::new();
+        hdma.write(0xFF51, 0xC0);
+        hdma.write(0xFF52, 0x00);
+        hdma.write(0xFF53, 0x00);
+        hdma.write(0xFF54, 0x00);
+
+        assert_eq!(hdma.write(0xFF55, 0x81), None);
+        assert_eq!(hdma.read(0xFF55), 0x01);
+
+        let first = hdma.hblank().unwrap();
+        assert_eq!(
+            (first.source, first.dest, first.len),
+            (0xC000, 0x8000, 0x10)
+        );
+        assert_eq!(hdma.read(0xFF55), 0x00);
+
+        let second = hdma.hblank().unwrap();
+        assert_eq!((second.source, second.dest), (0xC010, 0x8010));
+        assert_eq!(hdma.read(0xFF55), 0xFF);
+        assert_eq!(hdma.hblank(), None);
+    }
+
+    #[test]
+    fn test_cancel_hblank_dma() {
+        let mut hdma = Hdma::new();
+        hdma.write(0xFF55, 0x85);
+        hdma.hblank();
+
+        assert_eq!(hdma.write(0xFF55, 0x00), None);
+        // bit 7 reads as set again, and the remaining length is still there
+        // to read
+        assert_eq!(hdma.read(0xFF55), 0x84);
+        assert_eq!(hdma.hblank(), None);
+    }
+}
@@ -0,0 +1,165 @@
+//! The I/O registers at 0xFF00-0xFF7F.
+//!
+//! These aren't plain RAM: some bits are unused or write-only (and read as
+//! 1), some can't be written by the CPU, and some writes set off something
+//! else. [`register`] describes each one, and the MMU routes accesses
+//! through it.
+
+/// What a write to a register does, besides storing the value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteEffect {
+    None,
+    /// DIV: any write resets the divider to 0.
+    ResetDivider,
+    /// DMA: copy 160 bytes from `value << 8` into OAM.
+    OamDma,
+    /// LCDC: turning the LCD off resets LY and the STAT mode.
+    Lcdc,
+    /// NR52: turning the APU off clears all of its registers.
+    ApuPower,
+    /// BOOT: any nonzero write unmaps the boot ROM.
+    UnmapBootRom,
+    /// HDMA1-HDMA5: handled by [`Hdma`](crate::hdma::Hdma).
+    Hdma,
+}
+
+/// How one I/O register behaves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoRegister {
+    pub name: &'static str,
+    /// Bits that always read as 1, because they're unused or write-only.
+    pub read_mask: u8,
+    /// Bits the CPU can't change.
+    pub read_only: u8,
+    pub effect: WriteEffect,
+    /// Only there on the CGB; reads 0xFF and ignores writes otherwise.
+    pub cgb_only: bool,
+}
+
+impl IoRegister {
+    const fn new(name: &'static str, read_mask: u8) -> Self {
+        IoRegister {
+            name,
+            read_mask,
+            read_only: 0x00,
+            effect: WriteEffect::None,
+            cgb_only: false,
+        }
+    }
+
+    const fn read_only(self, read_only: u8) -> Self {
+        IoRegister { read_only, ..self }
+    }
+
+    const fn effect(self, effect: WriteEffect) -> Self {
+        IoRegister { effect, ..self }
+    }
+
+    const fn cgb(self) -> Self {
+        IoRegister {
+            cgb_only: true,
+            ..self
+        }
+    }
+}
+
+/// The register at `0xFF00 + offset`, or `None` if nothing is there (which
+/// reads 0xFF and ignores writes).
+pub fn register(offset: u8) -> Option<IoRegister> {
+    let register = match offset {
+        0x00 => IoRegister::new("P1", 0xC0).read_only(0x0F),
+        0x01 => IoRegister::new("SB", 0x00),
+        0x02 => IoRegister::new("SC", 0x7E),
+        0x04 => IoRegister::new("DIV", 0x00).effect(WriteEffect::ResetDivider),
+        0x05 => IoRegister::new("TIMA", 0x00),
+        0x06 => IoRegister::new("TMA", 0x00),
+        0x07 => IoRegister::new("TAC", 0xF8),
+        0x0F => IoRegister::new("IF", 0xE0),
+        0x10 => IoRegister::new("NR10", 0x80),
+        0x11 => IoRegister::new("NR11", 0x3F),
+        0x12 => IoRegister::new("NR12", 0x00),
+        0x13 => IoRegister::new("NR13", 0xFF),
+        0x14 => IoRegister::new("NR14", 0xBF),
+        0x16 => IoRegister::new("NR21", 0x3F),
+        0x17 => IoRegister::new("NR22", 0x00),
+        0x18 => IoRegister::new("NR23", 0xFF),
+        0x19 => IoRegister::new("NR24", 0xBF),
+        0x1A => IoRegister::new("NR30", 0x7F),
+        0x1B => IoRegister::new("NR31", 0xFF),
+        0x1C => IoRegister::new("NR32", 0x9F),
+        0x1D => IoRegister::new("NR33", 0xFF),
+        0x1E => IoRegister::new("NR34", 0xBF),
+        0x20 => IoRegister::new("NR41", 0xFF),
+        0x21 => IoRegister::new("NR42", 0x00),
+        0x22 => IoRegister::new("NR43", 0x00),
+        0x23 => IoRegister::new("NR44", 0xBF),
+        0x24 => IoRegister::new("NR50", 0x00),
+        0x25 => IoRegister::new("NR51", 0x00),
+        // the low bits report which channels are on
+        0x26 => IoRegister::new("NR52", 0x70)
+            .read_only(0x0F)
+            .effect(WriteEffect::ApuPower),
+        0x30..=0x3F => IoRegister::new("WAVE", 0x00),
+        0x40 => IoRegister::new("LCDC", 0x00).effect(WriteEffect::Lcdc),
+        0x41 => IoRegister::new("STAT", 0x80).read_only(0x07),
+        0x42 => IoRegister::new("SCY", 0x00),
+        0x43 => IoRegister::new("SCX", 0x00),
+        0x44 => IoRegister::new("LY", 0x00).read_only(0xFF),
+        0x45 => IoRegister::new("LYC", 0x00),
+        0x46 => IoRegister::new("DMA", 0x00).effect(WriteEffect::OamDma),
+        0x47 => IoRegister::new("BGP", 0x00),
+        0x48 => IoRegister::new("OBP0", 0x00),
+        0x49 => IoRegister::new("OBP1", 0x00),
+        0x4A => IoRegister::new("WY", 0x00),
+        0x4B => IoRegister::new("WX", 0x00),
+        // bit 7 is the current speed, which only STOP changes
+        0x4D => IoRegister::new("KEY1", 0x7E).read_only(0x80).cgb(),
+        0x4F => IoRegister::new("VBK", 0xFE).cgb(),
+        0x50 => IoRegister::new("BOOT", 0xFF).effect(WriteEffect::UnmapBootRom),
+        0x51 => IoRegister::new("HDMA1", 0xFF)
+            .effect(WriteEffect::Hdma)
+            .cgb(),
+        0x52 => IoRegister::new("HDMA2", 0xFF)
+            .effect(WriteEffect::Hdma)
+            .cgb(),
+        0x53 => IoRegister::new("HDMA3", 0xFF)
+            .effect(WriteEffect::Hdma)
+            .cgb(),
+        0x54 => IoRegister::new("HDMA4", 0xFF)
+            .effect(WriteEffect::Hdma)
+            .cgb(),
+        0x55 => IoRegister::new("HDMA5", 0x00)
+            .effect(WriteEffect::Hdma)
+            .cgb(),
+        0x56 => IoRegister::new("RP", 0x3C).cgb(),
+        0x68 => IoRegister::new("BCPS", 0x40).cgb(),
+        0x69 => IoRegister::new("BCPD", 0x00).cgb(),
+        0x6A => IoRegister::new("OCPS", 0x40).cgb(),
+        0x6B => IoRegister::new("OCPD", 0x00).cgb(),
+        0x6C => IoRegister::new("OPRI", 0xFE).cgb(),
+        0x70 => IoRegister::new("SVBK", 0xF8).cgb(),
+        0x72..=0x74 => IoRegister::new("UNDOC", 0x00).cgb(),
+        0x75 => IoRegister::new("UNDOC", 0x8F).cgb(),
+        0x76 => IoRegister::new("PCM12", 0x00).read_only(0xFF).cgb(),
+        0x77 => IoRegister::new("PCM34", 0x00).read_only(0xFF).cgb(),
+        _ => return None,
+    };
+
+    Some(register)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_register_table() {
+        assert_eq!(register(0x03), None);
+        assert_eq!(register(0x7F), None);
+        assert_eq!(register(0x0F).unwrap().read_mask, 0xE0);
+        assert_eq!(register(0x44).unwrap().read_only, 0xFF);
+        assert!(register(0x70).unwrap().cgb_only);
+        assert!(register(0x4D).unwrap().cgb_only);
+        assert!(!register(0x40).unwrap().cgb_only);
+    }
+}
@@ -1,11 +1,22 @@
+pub mod bus;
+pub mod cartridge;
+pub(crate) mod compatibility;
 pub mod cpu;
+pub(crate) mod crash;
+pub(crate) mod debug_message;
+pub mod disasm;
 pub mod gb;
+pub(crate) mod hdma;
+pub(crate) mod io;
 pub mod mmu;
+pub mod model;
+pub mod prelude;
+pub(crate) mod storage;
+pub(crate) mod symbols;
+pub(crate) mod watchdog;
 
 #[cfg(test)]
 mod tests {
-    use super::*;
-
     #[test]
     fn it_works() {
         assert_eq!(true, true);
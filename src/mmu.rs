@@ -1,36 +1,188 @@
-use std::cell::RefCell;
+use std::{
+    cell::{Cell, RefCell},
+    fs,
+    path::Path,
+};
+
+use crate::{
+    bus::Bus,
+    cartridge::{
+        header::{CartridgeHeader, HeaderProblem},
+        mbc::{self, Banks, Mbc, RAM_BANK_SIZE},
+        mbc5::Mbc5,
+        rom_only::RomOnly,
+        save_type::{SaveOverrides, SaveType},
+        validate_size, RomError, Validation, CGB_BOOT_ROM_SIZE, DMG_BOOT_ROM_SIZE,
+    },
+    compatibility::{Compatibility, OnUnimplemented, Unimplemented},
+    hdma::{Hdma, Transfer},
+    io::{self, WriteEffect},
+    model::Model,
+};
 
 use MemoryLocation::*;
 
+/// The I/O registers the DMG boot ROM leaves behind, as offsets from 0xFF00.
+/// Anything not listed reads 0xFF.
+const POST_BOOT_IO: &[(usize, u8)] = &[
+    (0x00, 0xCF), // P1
+    (0x01, 0x00), // SB
+    (0x02, 0x7E), // SC
+    (0x04, 0xAB), // DIV
+    (0x05, 0x00), // TIMA
+    (0x06, 0x00), // TMA
+    (0x07, 0xF8), // TAC
+    (0x0F, 0xE1), // IF
+    (0x10, 0x80), // NR10
+    (0x11, 0xBF), // NR11
+    (0x12, 0xF3), // NR12
+    (0x14, 0xBF), // NR14
+    (0x16, 0x3F), // NR21
+    (0x17, 0x00), // NR22
+    (0x19, 0xBF), // NR24
+    (0x1A, 0x7F), // NR30
+    (0x1C, 0x9F), // NR32
+    (0x1E, 0xBF), // NR34
+    (0x21, 0x00), // NR42
+    (0x22, 0x00), // NR43
+    (0x23, 0xBF), // NR44
+    (0x24, 0x77), // NR50
+    (0x25, 0xF3), // NR51
+    (0x26, 0xF1), // NR52
+    (0x40, 0x91), // LCDC
+    (0x41, 0x85), // STAT
+    (0x42, 0x00), // SCY
+    (0x43, 0x00), // SCX
+    (0x44, 0x00), // LY
+    (0x45, 0x00), // LYC
+    (0x47, 0xFC), // BGP
+    (0x4A, 0x00), // WY
+    (0x4B, 0x00), // WX
+];
+
+const WRAM_BANK_SIZE: usize = 0x1000;
+const VRAM_BANK_SIZE: usize = 0x2000;
+
+/// The boot ROM's ® tile, one bit plane.
+const REGISTERED_MARK: [u8; 8] = [0x3C, 0x42, 0xB9, 0xA5, 0xB9, 0xA5, 0x42, 0x3C];
+
+/// The Game Boy's memory map.
+///
+/// Every access goes through `&self`: each region lives in its own
+/// [`RefCell`] (or [`Cell`]), so the CPU, the cartridge's hardware and
+/// whatever drives the PPU can all hold the same `&MMU`. No borrow is held
+/// past the access that takes it, so they never overlap.
 pub struct MMU {
     // general RAM
-    wram: RefCell<Vec<u8>>, // 8KB
+    wram: RefCell<Vec<u8>>, // 8 banks of 4KB, only 2 of which the DMG has
     hram: RefCell<Vec<u8>>, // 128B
     // graphics RAM
-    vram: RefCell<Vec<u8>>, // 8KB
+    vram: RefCell<Vec<u8>>, // 2 banks of 8KB, only 1 of which the DMG has
     // I/O registers
-    io: RefCell<Vec<u8>>,            // 128B
-    cartridge: RefCell<Vec<u8>>,     // 16KB
-    cartridge_mbc: RefCell<Vec<u8>>, // 16KB
-    cartridge_ram: RefCell<Vec<u8>>, // 16KB
-    oam: RefCell<Vec<u8>>,           // 160B
+    io: RefCell<Vec<u8>>,  // 128B
+    oam: RefCell<Vec<u8>>, // 160B
     ie: RefCell<Vec<u8>>,
+    // the cartridge's ROM and RAM, behind its mapper
+    mbc: RefCell<Box<dyn Mbc>>,
+    // the header of the loaded cartridge, if there is one
+    header: RefCell<Option<CartridgeHeader>>,
+    // what the loaded cartridge saves, and the games that get told
+    save_type: Cell<Option<SaveType>>,
+    save_overrides: RefCell<SaveOverrides>,
+    // writes to cartridge RAM that wasn't there
+    lost_ram_writes: Cell<u64>,
+    // how the cartridge's banks are used, only tracked while enabled
+    bank_usage: RefCell<Option<BankUsage>>,
+    // what to do about hardware that isn't emulated, and what's been hit
+    compatibility: Cell<Compatibility>,
+    unimplemented: RefCell<Vec<Unimplemented>>,
+    unimplemented_stop: Cell<Option<Unimplemented>>,
+    // mapped over the start of the cartridge until FF50 is written
+    boot_rom: RefCell<Option<Vec<u8>>>,
+    // decides which of the CGB's registers exist
+    model: Cell<Model>,
+    // CGB VRAM DMA
+    hdma: RefCell<Hdma>,
+    // ordered bus accesses, only recorded while logging is enabled
+    access_log: RefCell<Option<Vec<Access>>>,
+    // running count of writes to the I/O registers and IE
+    io_writes: Cell<u64>,
+    // told about every access, for debuggers
+    observers: RefCell<Vec<Box<dyn MemObserver>>>,
+    // whether the CPU is shut out of VRAM and OAM while the PPU uses them
+    ppu_locks: Cell<bool>,
 }
 
-// pub struct Cartridge {
-//     pub rom: [u8; 16384], // 16KB
-//     pub mbc: [u8; 16384], // 16KB
-//     pub ram: [u8; 16384], // 16KB
-// }
+/// A single bus access, as recorded by the access log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read(u16),
+    Write(u16, u8),
+}
 
-pub enum MemoryLocation {
+/// How the cartridge's banks have been used, since tracking was turned on.
+/// See [`MMU::set_bank_tracking`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BankUsage {
+    /// M-cycles spent with each ROM bank mapped at 0x4000-0x7FFF, by bank.
+    pub rom: Vec<u64>,
+    /// M-cycles spent with each RAM bank mapped and enabled, by bank.
+    pub ram: Vec<u64>,
+    /// How many writes to the mapper switched a bank. Enabling and
+    /// disabling RAM doesn't count.
+    pub switches: u64,
+}
+
+impl BankUsage {
+    fn add(&mut self, banks: Banks, cycles: u8) {
+        let add = |histogram: &mut Vec<u64>, bank: usize| {
+            if histogram.len() <= bank {
+                histogram.resize(bank + 1, 0);
+            }
+            histogram[bank] += cycles as u64;
+        };
+
+        add(&mut self.rom, banks.romx);
+        if let (Some(bank), true) = (banks.ram, banks.ram_enabled) {
+            add(&mut self.ram, bank);
+        }
+    }
+}
+
+/// What made a bus access.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessSource {
+    Cpu,
+    /// OAM DMA, or the CGB's VRAM DMA.
+    Dma,
+}
+
+/// Something that watches memory accesses as they happen, like a debugger's
+/// watchpoints. See [`MMU::add_observer`].
+///
+/// Observers can't touch the MMU themselves, so anything they want to report
+/// has to go through state they share with whoever added them.
+pub trait MemObserver {
+    /// Called after a read, with the value that was read.
+    fn on_read(&mut self, _addr: u16, _value: u8, _source: AccessSource) {}
+
+    /// Called after a write, with the value that was written (which isn't
+    /// necessarily what reads back). Writes the PPU blocks aren't reported.
+    fn on_write(&mut self, _addr: u16, _value: u8, _source: AccessSource) {}
+}
+
+#[allow(clippy::upper_case_acronyms)]
+pub(crate) enum MemoryLocation {
     Cartridge(u16),
     CartridgeMBC(u16),
     CartridgeRAM(u16),
     VRAM(u16),
     WRAM(u16),
+    /// 0xE000-0xFDFF, the same memory as 0xC000-0xDDFF.
     EchoRAM(u16),
     OAM(u16),
+    /// 0xFEA0-0xFEFF, which nothing is wired to.
+    Unusable(u16),
     IO(u16),
     HRAM(u16),
     IE(u16),
@@ -46,6 +198,7 @@ impl MemoryLocation {
             self::WRAM(addr) => *addr,
             self::EchoRAM(addr) => *addr,
             self::OAM(addr) => *addr,
+            self::Unusable(addr) => *addr,
             self::HRAM(addr) => *addr,
             self::IO(addr) => *addr,
             self::IE(addr) => *addr,
@@ -53,29 +206,507 @@ impl MemoryLocation {
     }
 }
 
+impl Default for MMU {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl MMU {
     pub fn new() -> Self {
         MMU {
-            wram: vec![0; 8192].into(),
+            wram: vec![0; 8 * WRAM_BANK_SIZE].into(),
             hram: vec![0; 128].into(),
-            vram: vec![0; 8192].into(),
+            vram: vec![0; 2 * VRAM_BANK_SIZE].into(),
             io: vec![0; 128].into(),
-            cartridge: vec![0; 16384].into(),
-            cartridge_mbc: vec![0; 16384].into(),
-            cartridge_ram: vec![0; 16384].into(),
             oam: vec![0; 160].into(),
             ie: vec![0].into(),
+            // blank ROM and RAM until a cartridge is loaded
+            mbc: RefCell::new(Box::new(RomOnly::new(vec![0; 0x8000], RAM_BANK_SIZE))),
+            header: None.into(),
+            save_type: None.into(),
+            save_overrides: SaveOverrides::new().into(),
+            lost_ram_writes: 0.into(),
+            bank_usage: None.into(),
+            compatibility: Compatibility::default().into(),
+            unimplemented: Vec::new().into(),
+            unimplemented_stop: None.into(),
+            boot_rom: None.into(),
+            model: Model::default().into(),
+            hdma: Hdma::new().into(),
+            access_log: None.into(),
+            io_writes: 0.into(),
+            observers: Vec::new().into(),
+            ppu_locks: true.into(),
+        }
+    }
+
+    /// Load a cartridge ROM from a file. See [`MMU::load_rom_bytes`].
+    pub fn load_rom(
+        &self,
+        path: impl AsRef<Path>,
+        validation: Validation,
+    ) -> Result<Vec<HeaderProblem>, RomError> {
+        self.load_rom_bytes(&fs::read(path)?, validation)
+    }
+
+    /// Load a cartridge ROM image.
+    ///
+    /// The mapper is picked from the cartridge type in the header. A header
+    /// that fails the boot ROM's checks is an error when loading strictly,
+    /// and otherwise the problems are returned as warnings.
+    pub fn load_rom_bytes(
+        &self,
+        rom: &[u8],
+        validation: Validation,
+    ) -> Result<Vec<HeaderProblem>, RomError> {
+        validate_size(rom)?;
+        let header = CartridgeHeader::parse(rom)?;
+        let problems = CartridgeHeader::verify(rom);
+        if validation == Validation::Strict && !problems.is_empty() {
+            return Err(RomError::InvalidHeader(problems));
+        }
+
+        let save_type = self.save_type_for(&header);
+        let (mbc, missing) = match mbc::for_header(&header, save_type.ram_size, rom) {
+            Err(RomError::UnsupportedMapper(mapper))
+                if self.compatibility().mappers == OnUnimplemented::Continue =>
+            {
+                let mbc: Box<dyn Mbc> =
+                    Box::new(Mbc5::new(rom.to_vec(), save_type.ram_size, false));
+                (mbc, Some(Unimplemented::Mapper(mapper)))
+            }
+            result => (result?, None),
+        };
+
+        *self.mbc.borrow_mut() = mbc;
+        *self.header.borrow_mut() = Some(header);
+        self.save_type.set(Some(save_type));
+        self.lost_ram_writes.set(0);
+        self.unimplemented.borrow_mut().clear();
+        self.unimplemented_stop.set(None);
+        if let Some(mapper) = missing {
+            self.note_unimplemented(mapper);
+        }
+
+        Ok(problems)
+    }
+
+    /// Swap in another build of the loaded ROM, keeping what's in the
+    /// cartridge's RAM. The new mapper starts over, so any banks the old one
+    /// had switched in are switched back out.
+    pub fn reload_rom_bytes(
+        &self,
+        rom: &[u8],
+        validation: Validation,
+    ) -> Result<Vec<HeaderProblem>, RomError> {
+        let ram = self.mbc.borrow().save_data();
+        let problems = self.load_rom_bytes(rom, validation)?;
+        self.mbc.borrow_mut().load_save_data(&ram);
+
+        Ok(problems)
+    }
+
+    /// Load a boot ROM from a file. See [`MMU::load_boot_rom_bytes`].
+    pub fn load_boot_rom(&self, path: impl AsRef<Path>) -> Result<(), RomError> {
+        self.load_boot_rom_bytes(&fs::read(path)?)
+    }
+
+    /// Map a boot ROM over the start of the cartridge, where it stays until
+    /// it unmaps itself by writing to 0xFF50.
+    ///
+    /// A CGB boot ROM also covers 0x0200-0x08FF, leaving the cartridge
+    /// header visible in between.
+    pub fn load_boot_rom_bytes(&self, boot_rom: &[u8]) -> Result<(), RomError> {
+        if boot_rom.len() != DMG_BOOT_ROM_SIZE && boot_rom.len() != CGB_BOOT_ROM_SIZE {
+            return Err(RomError::InvalidBootRomSize(boot_rom.len()));
+        }
+
+        *self.boot_rom.borrow_mut() = Some(boot_rom.to_vec());
+        Ok(())
+    }
+
+    /// Whether a boot ROM is mapped over the cartridge.
+    pub fn boot_rom_mapped(&self) -> bool {
+        self.boot_rom.borrow().is_some()
+    }
+
+    fn read_boot_rom(&self, addr: u16) -> Option<u8> {
+        let boot_rom = self.boot_rom.borrow();
+        let boot_rom = boot_rom.as_ref()?;
+
+        match addr as usize {
+            addr @ 0x0000..0x0100 => Some(boot_rom[addr]),
+            addr @ 0x0200..0x0900 if boot_rom.len() == CGB_BOOT_ROM_SIZE => Some(boot_rom[addr]),
+            _ => None,
+        }
+    }
+
+    pub fn model(&self) -> Model {
+        self.model.get()
+    }
+
+    /// Pick the hardware to emulate, which decides whether the CGB's
+    /// registers are there.
+    pub fn set_model(&self, model: Model) {
+        self.model.set(model);
+    }
+
+    /// Called by the PPU at the start of each H-blank, to run the next block
+    /// of an H-blank DMA.
+    pub fn hblank(&self) {
+        let transfer = self.hdma.borrow_mut().hblank();
+        if let Some(transfer) = transfer {
+            self.copy_to_vram(transfer);
+        }
+    }
+
+    fn copy_to_vram(&self, transfer: Transfer) {
+        for i in 0..transfer.len {
+            let value = self.read_dma(transfer.source.wrapping_add(i));
+            let offset = (transfer.dest + i) & 0x1FFF;
+            self.vram.borrow_mut()[self.vram_index(offset)] = value;
+            self.notify_write(0x8000 | offset, value, AccessSource::Dma);
+        }
+    }
+
+    /// Read a byte for one of the DMAs.
+    fn read_dma(&self, addr: u16) -> u8 {
+        let value = self.read_location(addr).unwrap_or(0xFF);
+        self.notify_read(addr, value, AccessSource::Dma);
+
+        value
+    }
+
+    /// Read VRAM from either bank, regardless of which one VBK selects. This
+    /// is for the PPU, which reads tile attributes from bank 1 and tiles from
+    /// both.
+    ///
+    /// `offset` is from 0x8000. Bank 1 is only there on the CGB, and reads
+    /// 0xFF otherwise.
+    pub fn read_vram(&self, bank: u8, offset: u16) -> u8 {
+        match bank {
+            0 => self.vram.borrow()[offset as usize & 0x1FFF],
+            1 if self.model().is_cgb() => {
+                self.vram.borrow()[VRAM_BANK_SIZE + (offset as usize & 0x1FFF)]
+            }
+            _ => 0xFF,
+        }
+    }
+
+    /// Set up memory the way the boot ROM leaves it, for starting a
+    /// cartridge without one: the I/O registers, and on models without the
+    /// CGB hardware, the logo in VRAM.
+    ///
+    /// This also unmaps any boot ROM that was loaded.
+    pub fn skip_boot_rom(&self, model: Model) {
+        *self.boot_rom.borrow_mut() = None;
+
+        let mut io = self.io.borrow_mut();
+        io.fill(0xFF);
+        for &(offset, value) in POST_BOOT_IO {
+            io[offset] = value;
+        }
+        match model {
+            Model::Sgb | Model::Sgb2 => io[0x26] = 0xF0,
+            Model::Cgb | Model::Agb => {
+                io[0x4D] = 0x7E; // KEY1
+                io[0x4F] = 0xFE; // VBK
+                io[0x70] = 0xF8; // SVBK
+            }
+            _ => {}
+        }
+        self.ie.borrow_mut()[0] = 0x00;
+
+        if !model.is_cgb() {
+            self.draw_boot_logo();
+        }
+    }
+
+    /// Copy the logo from the cartridge header into VRAM, doubled in size,
+    /// as the DMG boot ROM does. It's left there for the game to clear.
+    fn draw_boot_logo(&self) {
+        let mut vram = self.vram.borrow_mut();
+        vram.fill(0);
+
+        let logo: Vec<u8> = (0x0104..0x0134)
+            .map(|addr| self.mbc.borrow().read_rom(addr))
+            .collect();
+        // each nibble becomes 4 rows of a tile: 2 pixels per bit, 2 rows
+        // per nibble, with the upper bit plane left clear
+        let nibbles = logo.iter().flat_map(|byte| [byte >> 4, byte & 0x0F]);
+        for (i, nibble) in nibbles.enumerate() {
+            let doubled = (0..4).fold(0u8, |row, bit| {
+                row | (((nibble >> bit) & 1) * 0b11) << (bit * 2)
+            });
+            vram[0x0010 + i * 4] = doubled;
+            vram[0x0010 + i * 4 + 2] = doubled;
+        }
+        for (i, row) in REGISTERED_MARK.iter().enumerate() {
+            vram[0x0190 + i * 2] = *row;
+        }
+
+        // the logo's two rows of tiles, then the mark
+        for tile in 0..12 {
+            vram[0x1904 + tile] = tile as u8 + 1;
+            vram[0x1924 + tile] = tile as u8 + 13;
+        }
+        vram[0x1910] = 0x19;
+    }
+
+    /// The header of the loaded cartridge, if one has been loaded.
+    pub fn cartridge_header(&self) -> Option<CartridgeHeader> {
+        self.header.borrow().clone()
+    }
+
+    /// Use these save types for the games they cover, from the next ROM
+    /// loaded on.
+    pub fn set_save_overrides(&self, overrides: SaveOverrides) {
+        *self.save_overrides.borrow_mut() = overrides;
+    }
+
+    /// The save type a cartridge with this header would be given.
+    pub fn save_type_for(&self, header: &CartridgeHeader) -> SaveType {
+        self.save_overrides.borrow().save_type(header)
+    }
+
+    /// What the loaded cartridge saves, if one has been loaded.
+    pub fn save_type(&self) -> Option<SaveType> {
+        self.save_type.get()
+    }
+
+    /// Whether the loaded cartridge keeps its save data with a battery.
+    pub fn has_battery(&self) -> bool {
+        self.save_type().is_some_and(|save_type| save_type.battery)
+    }
+
+    /// Decide what happens when a program uses hardware that isn't emulated.
+    /// The mapper policy applies from the next ROM loaded on.
+    pub fn set_compatibility(&self, compatibility: Compatibility) {
+        self.compatibility.set(compatibility);
+    }
+
+    pub fn compatibility(&self) -> Compatibility {
+        self.compatibility.get()
+    }
+
+    /// The hardware that isn't emulated that the loaded cartridge has used
+    /// so far, each only once, in the order it was first used.
+    pub fn unimplemented(&self) -> Vec<Unimplemented> {
+        self.unimplemented.borrow().clone()
+    }
+
+    /// The hardware the [`Compatibility`] policy says to stop at, if the
+    /// program has used any since the last call.
+    pub(crate) fn take_unimplemented_stop(&self) -> Option<Unimplemented> {
+        self.unimplemented_stop.take()
+    }
+
+    fn note_unimplemented(&self, hardware: Unimplemented) {
+        let mut seen = self.unimplemented.borrow_mut();
+        if !seen.contains(&hardware) {
+            seen.push(hardware);
+        }
+        if self.compatibility().on(hardware) == OnUnimplemented::Stop {
+            self.unimplemented_stop.set(Some(hardware));
+        }
+    }
+
+    /// Note a CPU access to an I/O address with no register behind it.
+    fn check_io_register(&self, addr: u16) {
+        if (0xFF00..0xFF80).contains(&addr) && io::register(addr as u8).is_none() {
+            self.note_unimplemented(Unimplemented::IoRegister(addr));
+        }
+    }
+
+    /// The banks the cartridge has mapped in right now.
+    pub fn banks(&self) -> Banks {
+        self.mbc.borrow().banks()
+    }
+
+    /// Start or stop keeping a [`BankUsage`] histogram, starting over from
+    /// nothing.
+    pub fn set_bank_tracking(&self, enabled: bool) {
+        *self.bank_usage.borrow_mut() = enabled.then(BankUsage::default);
+    }
+
+    /// The bank usage tracked so far, if tracking is on.
+    pub fn bank_usage(&self) -> Option<BankUsage> {
+        self.bank_usage.borrow().clone()
+    }
+
+    /// Write to the mapper's registers, counting any bank switch.
+    fn write_mapper(&self, addr: u16, value: u8) {
+        let mut mbc = self.mbc.borrow_mut();
+        let mut usage = self.bank_usage.borrow_mut();
+        let Some(usage) = usage.as_mut() else {
+            mbc.write_rom(addr, value);
+            return;
+        };
+
+        let switched = |banks: Banks| (banks.rom0, banks.romx, banks.ram);
+        let before = switched(mbc.banks());
+        mbc.write_rom(addr, value);
+        if switched(mbc.banks()) != before {
+            usage.switches += 1;
+        }
+    }
+
+    /// How many writes to 0xA000-0xBFFF the loaded cartridge has had, with
+    /// no RAM (or clock) there to take them.
+    ///
+    /// A game that makes lots of these probably has RAM its header doesn't
+    /// mention, and needs a [`SaveOverrides`] entry to keep it.
+    pub fn lost_ram_writes(&self) -> u64 {
+        self.lost_ram_writes.get()
+    }
+
+    fn count_lost_ram_write(&self, addr: u16) {
+        if !matches!(self.get_location(addr), CartridgeRAM(_)) {
+            return;
+        }
+
+        let no_ram = self
+            .save_type()
+            .is_some_and(|save_type| save_type.ram_size == 0);
+        let no_clock = self
+            .header
+            .borrow()
+            .as_ref()
+            .is_some_and(|header| !header.cartridge_type.timer);
+        if no_ram && no_clock {
+            self.lost_ram_writes.set(self.lost_ram_writes.get() + 1);
+        }
+    }
+
+    /// The cartridge's battery-backed data, for writing to a save file, or
+    /// `None` if it doesn't have a battery.
+    pub fn save_data(&self) -> Option<Vec<u8>> {
+        self.has_battery().then(|| self.mbc.borrow().save_data())
+    }
+
+    /// Restore data from [`MMU::save_data`].
+    pub fn load_save_data(&self, data: &[u8]) {
+        self.mbc.borrow_mut().load_save_data(data);
+    }
+
+    /// Have the loaded cartridge report its rumble motor turning on and off,
+    /// if it has one. Loading another ROM drops the handler.
+    pub fn set_rumble_handler(&self, handler: impl FnMut(bool) + 'static) {
+        self.mbc.borrow_mut().set_rumble_handler(Box::new(handler));
+    }
+
+    /// Set how far the console is tilted, in g, for cartridges with an
+    /// accelerometer. Positive `x` is tilted right, and positive `y` is
+    /// tilted towards the player.
+    pub fn set_tilt(&self, x: f32, y: f32) {
+        self.mbc.borrow_mut().set_tilt(x, y);
+    }
+
+    /// Start or stop recording every bus access, in order.
+    ///
+    /// This is meant for debugging and accuracy testing, since it slows
+    /// down every read and write.
+    pub fn set_access_logging(&self, enabled: bool) {
+        *self.access_log.borrow_mut() = enabled.then(Vec::new);
+    }
+
+    /// Drain the accesses recorded so far, leaving logging enabled.
+    pub fn take_access_log(&self) -> Vec<Access> {
+        self.access_log
+            .borrow_mut()
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    fn log_access(&self, access: Access) {
+        if let Some(log) = self.access_log.borrow_mut().as_mut() {
+            log.push(access);
+        }
+    }
+
+    /// Called by the PPU when it changes mode, which goes in the low bits of
+    /// STAT (FF41).
+    pub fn set_ppu_mode(&self, mode: u8) {
+        let mut io = self.io.borrow_mut();
+        io[0x41] = io[0x41] & !0x03 | mode & 0x03;
+    }
+
+    /// The PPU's current mode, or `None` while the LCD is off.
+    fn ppu_mode(&self) -> Option<u8> {
+        let io = self.io.borrow();
+        (io[0x40] & 0x80 != 0).then_some(io[0x41] & 0x03)
+    }
+
+    /// Turn off (or back on) locking the CPU out of VRAM during mode 3 and
+    /// OAM during modes 2 and 3. With it off, a debugger can poke at them
+    /// whenever it likes, at the cost of accuracy.
+    pub fn set_ppu_access_locks(&self, enabled: bool) {
+        self.ppu_locks.set(enabled);
+    }
+
+    pub fn ppu_access_locks(&self) -> bool {
+        self.ppu_locks.get()
+    }
+
+    /// Whether the PPU is using `addr`, so the CPU can't; reads see 0xFF and
+    /// writes are dropped.
+    fn locked_by_ppu(&self, addr: u16) -> bool {
+        if !self.ppu_locks.get() {
+            return false;
+        }
+
+        matches!(
+            (self.get_location(addr), self.ppu_mode()),
+            (VRAM(_), Some(3)) | (OAM(_), Some(2 | 3))
+        )
+    }
+
+    /// Have `observer` told about every read and write from now on, except
+    /// for [`MMU::peek_byte`]s.
+    pub fn add_observer(&self, observer: impl MemObserver + 'static) {
+        self.observers.borrow_mut().push(Box::new(observer));
+    }
+
+    pub fn clear_observers(&self) {
+        self.observers.borrow_mut().clear();
+    }
+
+    fn notify_read(&self, addr: u16, value: u8, source: AccessSource) {
+        for observer in self.observers.borrow_mut().iter_mut() {
+            observer.on_read(addr, value, source);
+        }
+    }
+
+    fn notify_write(&self, addr: u16, value: u8, source: AccessSource) {
+        for observer in self.observers.borrow_mut().iter_mut() {
+            observer.on_write(addr, value, source);
+        }
+    }
+
+    /// How many writes have gone to the I/O registers (including IE).
+    ///
+    /// Only the change between two calls is meaningful; it's a cheap way to
+    /// tell whether a program is still talking to the hardware.
+    pub fn io_writes(&self) -> u64 {
+        self.io_writes.get()
+    }
+
+    fn count_io_write(&self, addr: u16) {
+        if matches!(self.get_location(addr), IO(_) | IE(_)) {
+            self.io_writes.set(self.io_writes.get() + 1);
         }
     }
 
     fn map_register(&self, location: MemoryLocation) -> (&RefCell<Vec<u8>>, usize) {
         let register = match location {
-            Cartridge(_) => &self.cartridge,
-            CartridgeMBC(_) => &self.cartridge_mbc,
-            VRAM(_) => &self.vram,
-            CartridgeRAM(_) => &self.cartridge_ram,
-            WRAM(_) => &self.wram,
-            EchoRAM(_) => &self.wram,
+            Cartridge(_) | CartridgeMBC(_) | CartridgeRAM(_) => {
+                unreachable!("the cartridge is accessed through its mapper")
+            }
+            Unusable(_) => unreachable!("the unusable area has no memory behind it"),
+            VRAM(offset) => return (&self.vram, self.vram_index(offset)),
+            WRAM(offset) | EchoRAM(offset) => return (&self.wram, self.wram_index(offset)),
             OAM(_) => &self.oam,
             HRAM(_) => &self.hram,
             IO(_) => &self.io,
@@ -85,49 +716,253 @@ impl MMU {
         (register, location.unwrap_value().into())
     }
 
+    /// Where an offset into 0x8000-0x9FFF is in VRAM, going by the bank the
+    /// CGB has selected with VBK (FF4F).
+    fn vram_index(&self, offset: u16) -> usize {
+        let bank = match self.model().is_cgb() {
+            true => self.io.borrow()[0x4F] & 0x01,
+            false => 0,
+        };
+        bank as usize * VRAM_BANK_SIZE + offset as usize
+    }
+
+    /// Where an offset into 0xC000-0xDFFF is in WRAM. The first 4KB is always
+    /// bank 0; the CGB picks the bank for the rest with SVBK (FF70), where 0
+    /// also means bank 1.
+    fn wram_index(&self, offset: u16) -> usize {
+        let offset = offset as usize;
+        if offset < WRAM_BANK_SIZE {
+            return offset;
+        }
+
+        let bank = match self.model().is_cgb() {
+            true => (self.io.borrow()[0x70] & 0x07).max(1),
+            false => 1,
+        };
+        bank as usize * WRAM_BANK_SIZE + offset % WRAM_BANK_SIZE
+    }
+
+    fn read_location(&self, addr: u16) -> Option<u8> {
+        match self.get_location(addr) {
+            Cartridge(_) => self
+                .read_boot_rom(addr)
+                .or_else(|| Some(self.mbc.borrow().read_rom(addr))),
+            CartridgeMBC(_) => Some(self.mbc.borrow().read_rom(addr)),
+            CartridgeRAM(_) => Some(self.mbc.borrow().read_ram(addr)),
+            Unusable(offset) => Some(self.read_unusable(offset as u8)),
+            IO(offset) => Some(self.read_io(offset as u8)),
+            location => {
+                let (register, offset) = self.map_register(location);
+                register.borrow().get(offset).copied()
+            }
+        }
+    }
+
+    fn write_location(&self, addr: u16, value: u8) {
+        match self.get_location(addr) {
+            Cartridge(_) | CartridgeMBC(_) => self.write_mapper(addr, value),
+            CartridgeRAM(_) => self.mbc.borrow_mut().write_ram(addr, value),
+            Unusable(_) => {}
+            IO(offset) => self.write_io(offset as u8, value),
+            location => {
+                let (register, offset) = self.map_register(location);
+                register.borrow_mut()[offset] = value;
+            }
+        }
+    }
+
+    /// Read 0xFEA0-0xFEFF. The CGB repeats the high nibble of the address;
+    /// older models read 0xFF while the PPU has OAM locked, and 0x00
+    /// otherwise.
+    fn read_unusable(&self, offset: u8) -> u8 {
+        if self.model().is_cgb() {
+            let nibble = (0xA0 + offset) >> 4;
+            return nibble << 4 | nibble;
+        }
+
+        match self.ppu_mode() {
+            Some(2 | 3) => 0xFF,
+            _ => 0x00,
+        }
+    }
+
+    fn read_io(&self, offset: u8) -> u8 {
+        let Some(register) = io::register(offset) else {
+            return 0xFF;
+        };
+
+        match register.effect {
+            _ if register.cgb_only && !self.model().is_cgb() => 0xFF,
+            WriteEffect::Hdma => self.hdma.borrow().read(0xFF00 | offset as u16),
+            _ => self.io.borrow()[offset as usize] | register.read_mask,
+        }
+    }
+
+    fn write_io(&self, offset: u8, value: u8) {
+        let Some(register) = io::register(offset) else {
+            return;
+        };
+        if register.cgb_only && !self.model().is_cgb() {
+            return;
+        }
+
+        let old = self.io.borrow()[offset as usize];
+        let new = old & register.read_only | value & !register.read_only;
+        self.io.borrow_mut()[offset as usize] = new;
+
+        match register.effect {
+            WriteEffect::None => {}
+            WriteEffect::ResetDivider => self.io.borrow_mut()[offset as usize] = 0,
+            WriteEffect::OamDma => {
+                // sources past WRAM see its echo
+                let source = match value {
+                    0xE0..=0xFF => (value as u16 - 0x20) << 8,
+                    _ => (value as u16) << 8,
+                };
+                for i in 0..0xA0 {
+                    let byte = self.read_dma(source + i);
+                    self.oam.borrow_mut()[i as usize] = byte;
+                    self.notify_write(0xFE00 + i, byte, AccessSource::Dma);
+                }
+            }
+            WriteEffect::Lcdc => {
+                if old & 0x80 != 0 && value & 0x80 == 0 {
+                    let mut io = self.io.borrow_mut();
+                    io[0x44] = 0;
+                    io[0x41] &= !0x03;
+                }
+            }
+            WriteEffect::ApuPower => {
+                if value & 0x80 == 0 {
+                    self.io.borrow_mut()[0x10..=0x26].fill(0);
+                }
+            }
+            // once the boot ROM unmaps itself, there's no way back
+            WriteEffect::UnmapBootRom => {
+                if value != 0 {
+                    *self.boot_rom.borrow_mut() = None;
+                }
+            }
+            WriteEffect::Hdma => {
+                let transfer = self.hdma.borrow_mut().write(0xFF00 | offset as u16, value);
+                if let Some(transfer) = transfer {
+                    self.copy_to_vram(transfer);
+                }
+            }
+        }
+    }
+
     /// Read a byte (u8) from a memory address.
     pub fn read_byte(&self, addr: u16) -> Option<u8> {
-        let location = self.get_location(addr);
+        self.log_access(Access::Read(addr));
+        self.check_io_register(addr);
 
-        let (register, offset) = self.map_register(location);
+        let value = match self.locked_by_ppu(addr) {
+            true => Some(0xFF),
+            false => self.read_location(addr),
+        };
+        if let Some(value) = value {
+            self.notify_read(addr, value, AccessSource::Cpu);
+        }
+
+        value
+    }
+
+    /// Read a byte without recording it in the access log.
+    ///
+    /// This is for debugging tools that inspect memory, as opposed to the
+    /// emulated CPU accessing the bus.
+    pub fn peek_byte(&self, addr: u16) -> Option<u8> {
+        self.read_location(addr)
+    }
 
-        register.borrow().get(offset).map(|byte| *byte)
+    /// Read a byte as a two's complement offset, like the operand of `JR`.
+    pub fn read_signed_byte(&self, addr: u16) -> Option<i8> {
+        self.read_byte(addr).map(|byte| byte as i8)
+    }
+
+    /// Read `len` bytes starting at `start`, wrapping around at the end of
+    /// the address space. Like [`MMU::peek_byte`], this isn't logged or
+    /// observed.
+    pub fn dump_range(&self, start: u16, len: usize) -> Vec<u8> {
+        (0..len)
+            .map(|i| {
+                let addr = start.wrapping_add(i as u16);
+                self.read_location(addr).unwrap_or(0xFF)
+            })
+            .collect()
+    }
+
+    /// Write `bytes` starting at `start`, wrapping around at the end of the
+    /// address space. Each byte goes wherever a CPU write would (so writes to
+    /// the cartridge's ROM reach its mapper, and the I/O registers take their
+    /// side effects), but isn't logged, observed or counted in
+    /// [`MMU::io_writes`].
+    pub fn load_range(&self, start: u16, bytes: &[u8]) {
+        for (i, value) in bytes.iter().enumerate() {
+            self.write_location(start.wrapping_add(i as u16), *value);
+        }
     }
 
     /// Read a 16-bit word (u16) from a memory address.
     pub fn read_word(&self, addr: u16) -> Option<u16> {
-        let location = self.get_location(addr);
-
-        let (register, offset) = self.map_register(location);
-        let first = *register.borrow().get(offset)?;
-        let second = *register.borrow().get(offset + 1)?;
+        let first = self.read_byte(addr)?;
+        let second = self.read_byte(addr.wrapping_add(1))?;
 
         Some(u16::from_le_bytes([first, second]))
     }
 
     /// Write a byte (u8) to a memory address.
-    pub fn write_byte(&self, addr: u16, value: u8) -> () {
-        let location = self.get_location(addr);
+    pub fn write_byte(&self, addr: u16, value: u8) {
+        self.log_access(Access::Write(addr, value));
 
-        let (register, offset) = self.map_register(location);
+        if !self.locked_by_ppu(addr) {
+            self.count_io_write(addr);
+            self.count_lost_ram_write(addr);
+            self.check_io_register(addr);
+            self.write_location(addr, value);
+            self.notify_write(addr, value, AccessSource::Cpu);
+        }
+    }
 
-        register.borrow_mut()[offset] = value;
+    /// Write a byte without recording it in the access log, telling
+    /// observers, or counting it in [`MMU::io_writes`]. The counterpart of
+    /// [`MMU::peek_byte`].
+    pub fn poke_byte(&self, addr: u16, value: u8) {
+        self.write_location(addr, value);
     }
 
     /// Write a 16-bit word (u16) to a memory address.
-    pub fn write_word(&self, addr: u16, value: u16) -> () {
-        let location = self.get_location(addr);
+    pub fn write_word(&self, addr: u16, value: u16) {
+        let [first, second] = value.to_le_bytes();
 
-        let (register, offset) = self.map_register(location);
+        self.write_byte(addr, first);
+        self.write_byte(addr.wrapping_add(1), second);
+    }
 
-        let bytes = value.to_le_bytes();
+    /// Let the cartridge's hardware (e.g. its clock) catch up by `cycles`
+    /// M-cycles.
+    pub fn tick(&self, cycles: u8) {
+        let mut mbc = self.mbc.borrow_mut();
+        mbc.step(cycles);
+        if let Some(usage) = self.bank_usage.borrow_mut().as_mut() {
+            usage.add(mbc.banks(), cycles);
+        }
+    }
+
+    /// Do the speed switch armed by bit 0 of KEY1 (FF4D), flipping the
+    /// current speed in bit 7. Only the CGB can switch.
+    fn switch_speed(&self) -> Option<bool> {
+        let mut io = self.io.borrow_mut();
+        if !self.model().is_cgb() || io[0x4D] & 0x01 == 0 {
+            return None;
+        }
 
-        let mut register = register.borrow_mut();
-        register[offset] = bytes[0];
-        register[offset + 1] = bytes[1];
+        io[0x4D] = !io[0x4D] & 0x80;
+        Some(io[0x4D] != 0)
     }
 
-    pub fn get_location(&self, addr: u16) -> MemoryLocation {
+    pub(crate) fn get_location(&self, addr: u16) -> MemoryLocation {
         use MemoryLocation::*;
 
         match addr {
@@ -138,10 +973,584 @@ impl MMU {
             0xC000..=0xDFFF => WRAM(addr ^ 0xC000),
             0xE000..=0xFDFF => EchoRAM(addr ^ 0xE000), // echos 0xC000-0xDDFF
             0xFE00..=0xFE9F => OAM(addr ^ 0xFE00),
-            0xFEA0..=0xFEFF => panic!("Invalid memory address: {:04X}", addr),
+            0xFEA0..=0xFEFF => Unusable(addr - 0xFEA0),
             0xFF00..=0xFF7F => IO(addr ^ 0xFF00),
             0xFF80..=0xFFFE => HRAM(addr ^ 0xFF80),
-            0xFFFF => IE(0xFFFF),
+            0xFFFF => IE(0),
+        }
+    }
+}
+
+impl Bus for MMU {
+    fn read_byte(&self, addr: u16) -> Option<u8> {
+        MMU::read_byte(self, addr)
+    }
+
+    fn write_byte(&self, addr: u16, value: u8) {
+        MMU::write_byte(self, addr, value)
+    }
+
+    fn peek_byte(&self, addr: u16) -> Option<u8> {
+        MMU::peek_byte(self, addr)
+    }
+
+    fn poke_byte(&self, addr: u16, value: u8) {
+        MMU::poke_byte(self, addr, value)
+    }
+
+    fn tick(&self, cycles: u8) {
+        MMU::tick(self, cycles)
+    }
+
+    fn switch_speed(&self) -> Option<bool> {
+        MMU::switch_speed(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::cartridge::{
+        header::{header_checksum, MapperKind, NINTENDO_LOGO},
+        ROM_BANK_SIZE,
+    };
+
+    #[test]
+    fn test_load_rom_bytes() {
+        let mmu = MMU::new();
+        assert_eq!(mmu.cartridge_header(), None);
+
+        let mut rom = vec![0; 4 * ROM_BANK_SIZE];
+        rom[0x0101] = 0xC3;
+        rom[0x0134..0x0138].copy_from_slice(b"TEST");
+        rom[0x4000] = 0x01;
+        rom[0x7FFF] = 0x02;
+        rom[0x0104..0x0134].copy_from_slice(&NINTENDO_LOGO);
+        rom[0x014D] = header_checksum(&rom);
+
+        assert_eq!(mmu.load_rom_bytes(&rom, Validation::Strict).unwrap(), []);
+        assert_eq!(mmu.read_byte(0x0101), Some(0xC3));
+        assert_eq!(mmu.read_byte(0x4000), Some(0x01));
+        assert_eq!(mmu.read_byte(0x7FFF), Some(0x02));
+        assert_eq!(mmu.cartridge_header().unwrap().title, "TEST");
+    }
+
+    #[test]
+    fn test_save_type() {
+        let mmu = MMU::new();
+        let mut rom = vec![0; 2 * ROM_BANK_SIZE];
+        rom[0x0134..0x0138].copy_from_slice(b"LIAR");
+
+        // MBC1+RAM+BATTERY, with a RAM size of 0
+        rom[0x0147] = 0x03;
+        mmu.load_rom_bytes(&rom, Validation::Permissive).unwrap();
+        mmu.write_byte(0x0000, 0x0A);
+        mmu.write_byte(0xA000, 0x42);
+        assert_eq!(mmu.read_byte(0xA000), Some(0x42));
+        assert_eq!(mmu.save_data().unwrap().len(), 0x8000);
+        assert_eq!(mmu.lost_ram_writes(), 0);
+
+        // plain MBC1, which the game writes RAM on anyway
+        rom[0x0147] = 0x01;
+        mmu.load_rom_bytes(&rom, Validation::Permissive).unwrap();
+        mmu.write_byte(0x0000, 0x0A);
+        mmu.write_byte(0xA000, 0x42);
+        assert_eq!(mmu.read_byte(0xA000), Some(0xFF));
+        assert_eq!(mmu.lost_ram_writes(), 1);
+        assert!(!mmu.has_battery());
+
+        let mut overrides = SaveOverrides::new();
+        overrides.insert(
+            "LIAR",
+            0x0000,
+            SaveType {
+                ram_size: 0x2000,
+                battery: true,
+            },
+        );
+        mmu.set_save_overrides(overrides);
+        mmu.load_rom_bytes(&rom, Validation::Permissive).unwrap();
+        mmu.write_byte(0x0000, 0x0A);
+        mmu.write_byte(0xA000, 0x42);
+        assert_eq!(mmu.read_byte(0xA000), Some(0x42));
+        assert_eq!(mmu.save_data().unwrap().len(), 0x2000);
+        assert_eq!(mmu.lost_ram_writes(), 0);
+    }
+
+    #[test]
+    fn test_bank_usage() {
+        let mmu = MMU::new();
+        let mut rom = vec![0; 8 * ROM_BANK_SIZE];
+        rom[0x0147] = 0x1B; // MBC5+RAM+BATTERY
+        rom[0x0149] = 0x03;
+        mmu.load_rom_bytes(&rom, Validation::Permissive).unwrap();
+        assert_eq!(mmu.bank_usage(), None);
+
+        mmu.set_bank_tracking(true);
+        mmu.tick(4);
+        mmu.write_byte(0x2000, 0x03);
+        mmu.tick(2);
+        mmu.write_byte(0x0000, 0x0A); // RAM enable isn't a switch
+        mmu.write_byte(0x4000, 0x02);
+        mmu.tick(1);
+        // bank 9 wraps around to 1
+        mmu.write_byte(0x2000, 0x09);
+        mmu.tick(1);
+
+        assert_eq!(
+            mmu.banks(),
+            Banks {
+                rom0: 0,
+                romx: 1,
+                ram: Some(2),
+                ram_enabled: true
+            }
+        );
+        assert_eq!(
+            mmu.bank_usage(),
+            Some(BankUsage {
+                rom: vec![0, 5, 0, 3],
+                ram: vec![0, 0, 2],
+                switches: 3,
+            })
+        );
+    }
+
+    #[test]
+    fn test_load_rom_header_validation() {
+        let mmu = MMU::new();
+        let rom = vec![0; 2 * ROM_BANK_SIZE];
+
+        assert!(matches!(
+            mmu.load_rom_bytes(&rom, Validation::Strict),
+            Err(RomError::InvalidHeader(problems)) if problems.len() == 2
+        ));
+        assert_eq!(mmu.cartridge_header(), None);
+
+        let warnings = mmu.load_rom_bytes(&rom, Validation::Permissive).unwrap();
+        assert_eq!(warnings[0], HeaderProblem::BadLogo);
+        assert!(mmu.cartridge_header().is_some());
+    }
+
+    #[test]
+    fn test_cartridge_goes_through_mapper() {
+        let mmu = MMU::new();
+        let mut rom = vec![0; 2 * ROM_BANK_SIZE];
+        rom[0x0000] = 0x42;
+
+        // no RAM in the header, so it reads as open bus
+        mmu.load_rom_bytes(&rom, Validation::Permissive).unwrap();
+        mmu.write_byte(0x0000, 0x00);
+        mmu.write_byte(0xA000, 0x12);
+        assert_eq!(mmu.read_byte(0x0000), Some(0x42));
+        assert_eq!(mmu.read_byte(0xA000), Some(0xFF));
+
+        rom[0x0147] = 0x20; // MBC6
+        assert!(matches!(
+            mmu.load_rom_bytes(&rom, Validation::Permissive),
+            Err(RomError::UnsupportedMapper(MapperKind::Mbc6))
+        ));
+    }
+
+    #[test]
+    fn test_compatibility() {
+        let mmu = MMU::new();
+        let mut rom = vec![0; 4 * ROM_BANK_SIZE];
+        rom[0x0147] = 0x20; // MBC6
+        rom[0xC000] = 0x03;
+
+        mmu.set_compatibility(Compatibility::lenient());
+        mmu.load_rom_bytes(&rom, Validation::Permissive).unwrap();
+        mmu.write_byte(0x2000, 0x03);
+        assert_eq!(mmu.read_byte(0x4000), Some(0x03));
+
+        // unmapped registers still read 0xFF, and are noted once each
+        assert_eq!(mmu.read_byte(0xFF03), Some(0xFF));
+        mmu.write_byte(0xFF03, 0x00);
+        assert_eq!(mmu.peek_byte(0xFF7F), Some(0xFF));
+        assert_eq!(
+            mmu.unimplemented(),
+            [
+                Unimplemented::Mapper(MapperKind::Mbc6),
+                Unimplemented::IoRegister(0xFF03)
+            ]
+        );
+        assert_eq!(mmu.take_unimplemented_stop(), None);
+
+        mmu.set_compatibility(Compatibility::strict());
+        assert!(matches!(
+            mmu.load_rom_bytes(&rom, Validation::Permissive),
+            Err(RomError::UnsupportedMapper(MapperKind::Mbc6))
+        ));
+        mmu.read_byte(0xFF44);
+        assert_eq!(mmu.take_unimplemented_stop(), None);
+        mmu.read_byte(0xFF4C);
+        assert_eq!(
+            mmu.take_unimplemented_stop(),
+            Some(Unimplemented::IoRegister(0xFF4C))
+        );
+    }
+
+    #[test]
+    fn test_boot_rom_overlay() {
+        let mmu = MMU::new();
+        let mut rom = vec![0x11; 2 * ROM_BANK_SIZE];
+        rom[0x0147] = 0x00;
+        mmu.load_rom_bytes(&rom, Validation::Permissive).unwrap();
+
+        assert!(matches!(
+            mmu.load_boot_rom_bytes(&[0; 0x200]),
+            Err(RomError::InvalidBootRomSize(0x200))
+        ));
+
+        mmu.load_boot_rom_bytes(&[0x22; DMG_BOOT_ROM_SIZE]).unwrap();
+        assert!(mmu.boot_rom_mapped());
+        assert_eq!(mmu.read_byte(0x00FF), Some(0x22));
+        assert_eq!(mmu.read_byte(0x0100), Some(0x11));
+        assert_eq!(mmu.read_byte(0x0200), Some(0x11));
+
+        mmu.write_byte(0xFF50, 0x00);
+        assert!(mmu.boot_rom_mapped());
+        mmu.write_byte(0xFF50, 0x01);
+        assert!(!mmu.boot_rom_mapped());
+        assert_eq!(mmu.read_byte(0x0000), Some(0x11));
+
+        // the CGB boot ROM has a gap for the header
+        mmu.load_boot_rom_bytes(&[0x33; CGB_BOOT_ROM_SIZE]).unwrap();
+        assert_eq!(mmu.read_byte(0x0000), Some(0x33));
+        assert_eq!(mmu.read_byte(0x014F), Some(0x11));
+        assert_eq!(mmu.read_byte(0x08FF), Some(0x33));
+        assert_eq!(mmu.read_byte(0x0900), Some(0x11));
+    }
+
+    #[test]
+    fn test_skip_boot_rom() {
+        let mmu = MMU::new();
+        let mut rom = vec![0; 2 * ROM_BANK_SIZE];
+        rom[0x0104..0x0134].copy_from_slice(&NINTENDO_LOGO);
+        mmu.load_rom_bytes(&rom, Validation::Permissive).unwrap();
+        mmu.load_boot_rom_bytes(&[0; DMG_BOOT_ROM_SIZE]).unwrap();
+
+        mmu.skip_boot_rom(Model::Dmg);
+        assert!(!mmu.boot_rom_mapped());
+        assert_eq!(mmu.read_byte(0xFF40), Some(0x91));
+        assert_eq!(mmu.read_byte(0xFF4D), Some(0xFF));
+
+        // the logo starts 0xCE: 1100 becomes 11110000, over 2 rows
+        assert_eq!(mmu.read_byte(0x8010), Some(0xF0));
+        assert_eq!(mmu.read_byte(0x8011), Some(0x00));
+        assert_eq!(mmu.read_byte(0x8012), Some(0xF0));
+        assert_eq!(mmu.read_byte(0x8014), Some(0xFC));
+        assert_eq!(mmu.read_byte(0x9904), Some(0x01));
+        assert_eq!(mmu.read_byte(0x9910), Some(0x19));
+        assert_eq!(mmu.read_byte(0x992F), Some(0x18));
+
+        mmu.set_model(Model::Cgb);
+        mmu.skip_boot_rom(Model::Cgb);
+        assert_eq!(mmu.read_byte(0xFF4D), Some(0x7E));
+
+        // only STOP can change the current speed
+        mmu.write_byte(0xFF4D, 0x81);
+        assert_eq!(mmu.read_byte(0xFF4D), Some(0x7F));
+    }
+
+    #[test]
+    fn test_vram_dma() {
+        let mmu = MMU::new();
+        for i in 0..0x40 {
+            mmu.write_byte(0xC000 + i, i as u8);
+        }
+        for (addr, value) in [
+            (0xFF51, 0xC0),
+            (0xFF52, 0x00),
+            (0xFF53, 0x01),
+            (0xFF54, 0x00),
+        ] {
+            mmu.write_byte(addr, value);
+        }
+
+        // only on the CGB
+        mmu.write_byte(0xFF55, 0x00);
+        assert_eq!(mmu.read_byte(0x8100), Some(0x00));
+        assert_eq!(mmu.read_byte(0x810F), Some(0x00));
+
+        mmu.set_model(Model::Cgb);
+        for (addr, value) in [
+            (0xFF51, 0xC0),
+            (0xFF52, 0x00),
+            (0xFF53, 0x01),
+            (0xFF54, 0x00),
+        ] {
+            mmu.write_byte(addr, value);
+        }
+        mmu.write_byte(0xFF55, 0x00);
+        assert_eq!(mmu.read_byte(0x810F), Some(0x0F));
+        assert_eq!(mmu.read_byte(0x8110), Some(0x00));
+        assert_eq!(mmu.read_byte(0xFF55), Some(0xFF));
+
+        // an H-blank DMA carries on from there
+        mmu.write_byte(0xFF55, 0x81);
+        mmu.hblank();
+        assert_eq!(mmu.read_byte(0x811F), Some(0x1F));
+        assert_eq!(mmu.read_byte(0x8120), Some(0x00));
+        mmu.hblank();
+        assert_eq!(mmu.read_byte(0x812F), Some(0x2F));
+        assert_eq!(mmu.read_byte(0xFF55), Some(0xFF));
+    }
+
+    #[test]
+    fn test_io_registers() {
+        let mmu = MMU::new();
+
+        // unused bits read as 1, and unmapped registers as 0xFF
+        mmu.write_byte(0xFF0F, 0x01);
+        assert_eq!(mmu.read_byte(0xFF0F), Some(0xE1));
+        mmu.write_byte(0xFF03, 0x12);
+        assert_eq!(mmu.read_byte(0xFF03), Some(0xFF));
+        mmu.write_byte(0xFF70, 0x02);
+        assert_eq!(mmu.read_byte(0xFF70), Some(0xFF));
+
+        // some bits can't be written
+        mmu.write_byte(0xFF41, 0xFF);
+        assert_eq!(mmu.read_byte(0xFF41), Some(0xF8));
+        mmu.write_byte(0xFF44, 0x12);
+        assert_eq!(mmu.read_byte(0xFF44), Some(0x00));
+
+        mmu.write_byte(0xFF04, 0x12);
+        assert_eq!(mmu.read_byte(0xFF04), Some(0x00));
+
+        // turning the APU off clears it
+        mmu.write_byte(0xFF26, 0x80);
+        mmu.write_byte(0xFF24, 0x77);
+        mmu.write_byte(0xFF26, 0x00);
+        assert_eq!(mmu.read_byte(0xFF24), Some(0x00));
+        assert_eq!(mmu.read_byte(0xFF26), Some(0x70));
+    }
+
+    #[test]
+    fn test_oam_dma() {
+        let mmu = MMU::new();
+        for i in 0..0xA0 {
+            mmu.write_byte(0xC100 + i, i as u8);
+        }
+
+        mmu.write_byte(0xFF46, 0xC1);
+        assert_eq!(mmu.read_byte(0xFE00), Some(0x00));
+        assert_eq!(mmu.read_byte(0xFE9F), Some(0x9F));
+
+        // past WRAM is its echo
+        mmu.write_byte(0xFE9F, 0x00);
+        mmu.write_byte(0xFF46, 0xE1);
+        assert_eq!(mmu.read_byte(0xFE9F), Some(0x9F));
+    }
+
+    #[test]
+    fn test_words_are_little_endian() {
+        let mmu = MMU::new();
+        mmu.write_word(0xC000, 0x1234);
+        assert_eq!(mmu.read_byte(0xC000), Some(0x34));
+        assert_eq!(mmu.read_byte(0xC001), Some(0x12));
+        assert_eq!(mmu.read_word(0xC000), Some(0x1234));
+
+        // wraps around the end of the address space
+        mmu.write_word(0xFFFF, 0xABCD);
+        assert_eq!(mmu.read_byte(0x0000), Some(0x00));
+        assert_eq!(mmu.read_byte(0xFFFF), Some(0xCD));
+
+        assert_eq!(mmu.read_signed_byte(0xC001), Some(0x12));
+        mmu.write_byte(0xC002, 0xFE);
+        assert_eq!(mmu.read_signed_byte(0xC002), Some(-2));
+    }
+
+    #[test]
+    fn test_echo_ram() {
+        let mmu = MMU::new();
+        mmu.write_byte(0xC000, 0x11);
+        mmu.write_byte(0xFDFF, 0x22);
+        assert_eq!(mmu.read_byte(0xE000), Some(0x11));
+        assert_eq!(mmu.read_byte(0xDDFF), Some(0x22));
+
+        // the echo stops short of the end of WRAM: 0xFE00 is OAM, not 0xDE00
+        mmu.write_byte(0xDE00, 0x33);
+        mmu.write_byte(0xFE00, 0x44);
+        assert_eq!(mmu.read_byte(0xDE00), Some(0x33));
+        assert_eq!(mmu.read_byte(0xFE00), Some(0x44));
+        assert_eq!(mmu.oam.borrow()[0], 0x44);
+    }
+
+    #[test]
+    fn test_wram_banks() {
+        let mmu = MMU::new();
+        mmu.set_model(Model::Cgb);
+        for bank in 0..8 {
+            mmu.write_byte(0xFF70, bank);
+            mmu.write_byte(0xD000, 0x10 + bank);
+        }
+
+        // bank 0 selects bank 1
+        mmu.write_byte(0xFF70, 0x00);
+        assert_eq!(mmu.read_byte(0xFF70), Some(0xF8));
+        assert_eq!(mmu.read_byte(0xD000), Some(0x11));
+        mmu.write_byte(0xFF70, 0x05);
+        assert_eq!(mmu.read_byte(0xD000), Some(0x15));
+        assert_eq!(mmu.read_byte(0xF000), Some(0x15));
+
+        // the lower half doesn't move
+        mmu.write_byte(0xC000, 0x42);
+        mmu.write_byte(0xFF70, 0x02);
+        assert_eq!(mmu.read_byte(0xC000), Some(0x42));
+
+        // and the DMG only has bank 1
+        mmu.set_model(Model::Dmg);
+        assert_eq!(mmu.read_byte(0xFF70), Some(0xFF));
+        assert_eq!(mmu.read_byte(0xD000), Some(0x11));
+    }
+
+    #[test]
+    fn test_vram_banks() {
+        let mmu = MMU::new();
+        mmu.set_model(Model::Cgb);
+        mmu.write_byte(0x8000, 0x11);
+        mmu.write_byte(0xFF4F, 0x01);
+        assert_eq!(mmu.read_byte(0xFF4F), Some(0xFF));
+        assert_eq!(mmu.read_byte(0x8000), Some(0x00));
+        mmu.write_byte(0x9FFF, 0x22);
+
+        mmu.write_byte(0xFF4F, 0xFE);
+        assert_eq!(mmu.read_byte(0xFF4F), Some(0xFE));
+        assert_eq!(mmu.read_byte(0x8000), Some(0x11));
+        assert_eq!(mmu.read_byte(0x9FFF), Some(0x00));
+
+        // the PPU can see both, whichever is selected
+        assert_eq!(mmu.read_vram(0, 0x0000), 0x11);
+        assert_eq!(mmu.read_vram(1, 0x1FFF), 0x22);
+
+        mmu.set_model(Model::Dmg);
+        assert_eq!(mmu.read_vram(1, 0x1FFF), 0xFF);
+    }
+
+    #[test]
+    fn test_observers() {
+        /// Records writes to one address.
+        struct Watchpoint(u16, Rc<RefCell<Vec<(u8, AccessSource)>>>);
+
+        impl MemObserver for Watchpoint {
+            fn on_write(&mut self, addr: u16, value: u8, source: AccessSource) {
+                if addr == self.0 {
+                    self.1.borrow_mut().push((value, source));
+                }
+            }
+        }
+
+        let mmu = MMU::new();
+        let hits = Rc::new(RefCell::new(Vec::new()));
+        mmu.add_observer(Watchpoint(0xFE01, hits.clone()));
+
+        mmu.write_byte(0xFE01, 0x12);
+        mmu.write_byte(0xFE02, 0x34);
+        mmu.write_byte(0xC001, 0x56);
+        mmu.write_byte(0xFF46, 0xC0);
+        assert_eq!(
+            *hits.borrow(),
+            [(0x12, AccessSource::Cpu), (0x56, AccessSource::Dma)]
+        );
+
+        mmu.clear_observers();
+        mmu.write_byte(0xFE01, 0x78);
+        assert_eq!(hits.borrow().len(), 2);
+    }
+
+    #[test]
+    fn test_dump_and_load_range() {
+        let mmu = MMU::new();
+        mmu.set_access_logging(true);
+
+        // across the end of WRAM, through echo RAM, OAM and the unusable area
+        let image: Vec<u8> = (0..0x2000).map(|i| i as u8).collect();
+        mmu.load_range(0xC000, &image);
+        mmu.load_range(0xFE9E, &[0xAA, 0xBB, 0xCC]);
+        assert_eq!(mmu.dump_range(0xDFFE, 4), [0xFE, 0xFF, 0x00, 0x01]);
+        assert_eq!(mmu.dump_range(0xFE9E, 3), [0xAA, 0xBB, 0x00]);
+
+        // wraps around to the cartridge
+        mmu.load_range(0xFFFF, &[0x1F, 0x00]);
+        assert_eq!(mmu.dump_range(0xFFFF, 2), [0x1F, 0x00]);
+
+        assert_eq!(mmu.take_access_log(), []);
+    }
+
+    #[test]
+    fn test_ppu_access_locks() {
+        /// Records the address of every write.
+        struct Writes(Rc<RefCell<Vec<u16>>>);
+
+        impl MemObserver for Writes {
+            fn on_write(&mut self, addr: u16, _value: u8, _source: AccessSource) {
+                self.0.borrow_mut().push(addr);
+            }
+        }
+
+        let mmu = MMU::new();
+        let writes = Rc::new(RefCell::new(Vec::new()));
+        mmu.add_observer(Writes(writes.clone()));
+        mmu.write_byte(0x8000, 0x11);
+        mmu.write_byte(0xFE00, 0x22);
+        mmu.write_byte(0xFF40, 0x80);
+
+        mmu.set_ppu_mode(2);
+        assert_eq!(mmu.read_byte(0x8000), Some(0x11));
+        assert_eq!(mmu.read_byte(0xFE00), Some(0xFF));
+        mmu.write_byte(0xFE00, 0x33);
+
+        mmu.set_ppu_mode(3);
+        assert_eq!(mmu.read_byte(0x8000), Some(0xFF));
+        mmu.write_byte(0x8000, 0x44);
+        assert_eq!(mmu.peek_byte(0x8000), Some(0x11));
+
+        // blocked writes never happened, as far as observers can tell
+        assert_eq!(*writes.borrow(), [0x8000, 0xFE00, 0xFF40]);
+
+        mmu.set_ppu_access_locks(false);
+        assert_eq!(mmu.read_byte(0xFE00), Some(0x22));
+
+        // nothing's locked with the LCD off
+        mmu.set_ppu_access_locks(true);
+        mmu.write_byte(0xFF40, 0x00);
+        assert_eq!(mmu.read_byte(0x8000), Some(0x11));
+        assert_eq!(mmu.read_byte(0xFF41), Some(0x80));
+    }
+
+    #[test]
+    fn test_unusable_area() {
+        let mmu = MMU::new();
+        mmu.write_byte(0xFEA0, 0x12);
+        assert_eq!(mmu.read_byte(0xFEA0), Some(0x00));
+
+        // the PPU has OAM during modes 2 and 3
+        mmu.write_byte(0xFF40, 0x80);
+        mmu.set_ppu_mode(3);
+        assert_eq!(mmu.read_byte(0xFEFF), Some(0xFF));
+
+        mmu.set_model(Model::Cgb);
+        assert_eq!(mmu.read_byte(0xFEA0), Some(0xAA));
+        assert_eq!(mmu.read_byte(0xFED5), Some(0xDD));
+    }
+
+    #[test]
+    fn test_load_rom_rejects_bad_sizes() {
+        let mmu = MMU::new();
+
+        for size in [0, ROM_BANK_SIZE, 3 * ROM_BANK_SIZE + 1] {
+            assert!(matches!(
+                mmu.load_rom_bytes(&vec![0; size], Validation::Permissive),
+                Err(RomError::InvalidSize(found)) if found == size
+            ));
         }
+        assert!(matches!(
+            mmu.load_rom("does/not/exist.gb", Validation::Permissive),
+            Err(RomError::Io(_))
+        ));
     }
 }
@@ -0,0 +1,25 @@
+/// The Game Boy hardware revisions, which differ in the state the boot ROM
+/// leaves behind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Model {
+    /// The original Game Boy, with the early boot ROM.
+    Dmg0,
+    #[default]
+    Dmg,
+    /// Game Boy Pocket (and Light).
+    Mgb,
+    /// Super Game Boy.
+    Sgb,
+    Sgb2,
+    /// Game Boy Color, running a CGB game.
+    Cgb,
+    /// Game Boy Advance, running a CGB game.
+    Agb,
+}
+
+impl Model {
+    /// Whether the model has the Game Boy Color hardware.
+    pub fn is_cgb(&self) -> bool {
+        matches!(self, Model::Cgb | Model::Agb)
+    }
+}
@@ -0,0 +1,31 @@
+//! The types most library consumers need, for a single glob import.
+//!
+//! ```
+//! use magi::prelude::*;
+//!
+//! let gb = GB::new();
+//! ```
+
+pub use crate::{
+    bus::{Bus, FlatMemory},
+    cartridge::{
+        dat::{Dat, DumpStatus},
+        mbc::Banks,
+        save_type::{SaveOverrides, SaveType},
+    },
+    compatibility::{Compatibility, OnUnimplemented, Unimplemented},
+    cpu::{
+        error::ExecutionError,
+        interrupts::{Interrupt, Latency},
+        registers::{Flag, FlagRegister, SM83RegisterBank},
+        sm83::{HistoryEntry, SM83},
+    },
+    crash::{Config, CrashReport, MemoryRegion},
+    debug_message::DebugMessage,
+    gb::{DevMode, Reload, GB},
+    mmu::{Access, AccessSource, BankUsage, MemObserver, MMU},
+    model::Model,
+    storage::{FileStorage, MemoryStorage, StorageBackend},
+    symbols::Symbols,
+    watchdog::{ProbableHang, Watchdog},
+};
@@ -0,0 +1,103 @@
+//! Where persistent data (battery RAM, clocks, save states) ends up.
+//!
+//! The emulator never touches the filesystem directly for these; it goes
+//! through a [`StorageBackend`], so a host without `std::fs` (a browser, a
+//! libretro core) can supply its own.
+//!
+//! ```
+//! use magi::prelude::*;
+//!
+//! let mut storage = MemoryStorage::new();
+//! storage.save("tetris.sav", &[1, 2, 3]).unwrap();
+//! assert_eq!(storage.load("tetris.sav").unwrap(), Some(vec![1, 2, 3]));
+//! ```
+
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// A store of named blobs.
+///
+/// Keys are plain file names (e.g. `"tetris.sav"`), with no directories.
+pub trait StorageBackend {
+    /// Read a blob, or `None` if nothing has been saved under `key`.
+    fn load(&self, key: &str) -> io::Result<Option<Vec<u8>>>;
+
+    /// Store a blob, replacing anything already saved under `key`.
+    fn save(&mut self, key: &str, data: &[u8]) -> io::Result<()>;
+}
+
+/// Keeps everything in a directory, one file per key.
+#[derive(Debug, Clone)]
+pub struct FileStorage {
+    root: PathBuf,
+}
+
+impl FileStorage {
+    /// The directory is created when something is first saved.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        FileStorage { root: root.into() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+}
+
+impl StorageBackend for FileStorage {
+    fn load(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        match fs::read(self.root.join(key)) {
+            Ok(data) => Ok(Some(data)),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    fn save(&mut self, key: &str, data: &[u8]) -> io::Result<()> {
+        fs::create_dir_all(&self.root)?;
+        fs::write(self.root.join(key), data)
+    }
+}
+
+/// Keeps everything in memory, for tests and hosts that persist on their
+/// own terms.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStorage {
+    pub blobs: HashMap<String, Vec<u8>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl StorageBackend for MemoryStorage {
+    fn load(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        Ok(self.blobs.get(key).cloned())
+    }
+
+    fn save(&mut self, key: &str, data: &[u8]) -> io::Result<()> {
+        self.blobs.insert(key.to_string(), data.to_vec());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_storage_round_trip() {
+        let root = std::env::temp_dir().join(format!("magi-storage-{}", std::process::id()));
+        let mut storage = FileStorage::new(&root);
+
+        assert_eq!(storage.load("game.sav").unwrap(), None);
+        storage.save("game.sav", &[0xAB; 4]).unwrap();
+        assert_eq!(storage.load("game.sav").unwrap(), Some(vec![0xAB; 4]));
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
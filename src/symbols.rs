@@ -0,0 +1,103 @@
+//! Symbol files, as written by RGBDS's `rgblink -n`.
+//!
+//! Each line names one address, as `bank:address name` in hex, and anything
+//! after a `;` is a comment:
+//!
+//! ```text
+//! ; File generated by rgblink
+//! 00:0150 Main
+//! 00:0158 Main.loop
+//! 01:4000 Tiles
+//! ```
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs, io,
+    path::Path,
+};
+
+/// The labels in a symbol file, by bank and address.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Symbols {
+    names: BTreeMap<(u16, u16), String>,
+    addresses: HashMap<String, (u16, u16)>,
+}
+
+impl Symbols {
+    /// Read a symbol file. See [`Symbols::parse`].
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::parse(&fs::read_to_string(path)?))
+    }
+
+    /// Parse the contents of a symbol file. Lines that don't make sense are
+    /// skipped, since newer linkers may add things this doesn't know about.
+    pub fn parse(text: &str) -> Self {
+        let mut symbols = Symbols::default();
+        for (location, name) in text.lines().filter_map(parse_line) {
+            symbols
+                .names
+                .entry(location)
+                .or_insert_with(|| name.clone());
+            symbols.addresses.insert(name, location);
+        }
+
+        symbols
+    }
+
+    /// How many labels there are.
+    pub fn len(&self) -> usize {
+        self.addresses.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.addresses.is_empty()
+    }
+
+    /// The label at `addr` in `bank`, if there is one. If several labels
+    /// share an address, this is the first one in the file.
+    pub fn name(&self, bank: u16, addr: u16) -> Option<&str> {
+        self.names.get(&(bank, addr)).map(String::as_str)
+    }
+
+    /// The bank and address of a label.
+    pub fn address(&self, name: &str) -> Option<(u16, u16)> {
+        self.addresses.get(name).copied()
+    }
+}
+
+/// A `bank:address name` line.
+fn parse_line(line: &str) -> Option<((u16, u16), String)> {
+    let line = line.split(';').next()?.trim();
+    let (location, name) = line.split_once(char::is_whitespace)?;
+    let (bank, addr) = location.split_once(':')?;
+    let bank = u16::from_str_radix(bank, 16).ok()?;
+    let addr = u16::from_str_radix(addr, 16).ok()?;
+
+    Some(((bank, addr), name.trim().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let symbols = Symbols::parse(
+            "; File generated by rgblink\n\
+             00:0150 Main\n\
+             00:0158 Main.loop ; a comment\n\
+             00:0158 Main.alias\n\
+             01:4000 Tiles\n\
+             garbage\n\
+             1ff:7fff LastByte\n",
+        );
+
+        assert_eq!(symbols.len(), 5);
+        assert_eq!(symbols.name(0, 0x0158), Some("Main.loop"));
+        assert_eq!(symbols.address("Main.alias"), Some((0, 0x0158)));
+        assert_eq!(symbols.name(1, 0x4000), Some("Tiles"));
+        assert_eq!(symbols.name(0, 0x4000), None);
+        assert_eq!(symbols.address("LastByte"), Some((0x1FF, 0x7FFF)));
+        assert_eq!(symbols.address("Missing"), None);
+    }
+}
@@ -0,0 +1,172 @@
+//! Spotting programs that have stopped making progress.
+//!
+//! A [`Watchdog`] is fed the CPU after every step. If execution stays within
+//! a few bytes of code for a long time, with interrupts disabled and nothing
+//! written to the I/O registers, nothing can break it out of the loop, so
+//! it's reported as a [`ProbableHang`].
+
+use std::{fmt, ops::RangeInclusive};
+
+use crate::{
+    cpu::sm83::SM83,
+    disasm::{disassemble_range, Line},
+    mmu::MMU,
+};
+
+/// Watches for the CPU spinning in a small loop that can't be exited.
+#[derive(Debug, Clone)]
+pub struct Watchdog {
+    /// How many M-cycles the CPU may spend in one loop before it's reported.
+    pub limit: u64,
+    /// The widest span of addresses still treated as a single loop.
+    pub span: u16,
+    // the addresses PC has visited since the watchdog was last reset
+    window: Option<(u16, u16)>,
+    cycles: u64,
+    io_writes: u64,
+}
+
+/// A loop the CPU has been stuck in, as reported by a [`Watchdog`].
+#[derive(Debug, Clone)]
+pub struct ProbableHang {
+    /// The addresses PC moved between while looping.
+    pub range: RangeInclusive<u16>,
+    /// How long the CPU spent in the loop, in M-cycles.
+    pub cycles: u64,
+    pub disassembly: Vec<Line>,
+}
+
+impl Default for Watchdog {
+    /// About four seconds of emulated time, within a 64 byte loop.
+    fn default() -> Self {
+        Self::new(4_000_000, 0x40)
+    }
+}
+
+impl Watchdog {
+    pub fn new(limit: u64, span: u16) -> Self {
+        Watchdog {
+            limit,
+            span,
+            window: None,
+            cycles: 0,
+            io_writes: 0,
+        }
+    }
+
+    /// Account for the instruction the CPU just executed.
+    ///
+    /// Enabling interrupts, writing to an I/O register or leaving the loop
+    /// all count as progress and start the count over.
+    pub fn observe(&mut self, cpu: &SM83, mmu: &MMU) -> Option<ProbableHang> {
+        let pc = cpu.registers.pc;
+        let io_writes = mmu.io_writes();
+        let (start, end) = self.window.unwrap_or((pc, pc));
+        let (start, end) = (start.min(pc), end.max(pc));
+
+        if cpu.ime() || io_writes != self.io_writes || end - start > self.span {
+            self.restart(pc, io_writes);
+            return None;
+        }
+
+        self.window = Some((start, end));
+        self.cycles += cpu.registers.m as u64;
+        if self.cycles < self.limit {
+            return None;
+        }
+
+        let hang = ProbableHang {
+            range: start..=end,
+            cycles: self.cycles,
+            disassembly: disassemble_range(mmu, start..=end),
+        };
+        self.restart(pc, io_writes);
+
+        Some(hang)
+    }
+
+    fn restart(&mut self, pc: u16, io_writes: u64) {
+        self.window = Some((pc, pc));
+        self.cycles = 0;
+        self.io_writes = io_writes;
+    }
+}
+
+impl fmt::Display for ProbableHang {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "probable hang in {:04X}-{:04X} after {} M-cycles:",
+            self.range.start(),
+            self.range.end(),
+            self.cycles
+        )?;
+        for line in &self.disassembly {
+            writeln!(f, "  {}", line)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(mmu: &MMU, addr: u16, program: &[u8]) {
+        for (i, byte) in program.iter().enumerate() {
+            mmu.write_byte(addr + i as u16, *byte);
+        }
+    }
+
+    /// Step until the watchdog fires, giving up after `steps`.
+    fn watch(cpu: &mut SM83, mmu: &MMU, steps: usize) -> Option<ProbableHang> {
+        let mut watchdog = Watchdog::new(1000, 0x10);
+
+        (0..steps).find_map(|_| {
+            cpu.step(mmu).unwrap();
+            watchdog.observe(cpu, mmu)
+        })
+    }
+
+    #[test]
+    fn test_tight_loop_is_reported() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+        load(&mmu, 0xC000, &[0x00, 0x18, 0xFD]); // NOP; JR -3
+        cpu.registers.pc = 0xC000;
+
+        let hang = watch(&mut cpu, &mmu, 1000).expect("should detect the loop");
+
+        assert_eq!(hang.range, 0xC000..=0xC001);
+        assert!(hang.cycles >= 1000);
+        assert_eq!(
+            hang.to_string().lines().skip(1).collect::<Vec<_>>(),
+            ["  C000: 00        NOP", "  C001: 18 FD     JR -3 ; C000"]
+        );
+    }
+
+    #[test]
+    fn test_interrupts_enabled_is_progress() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+        load(&mmu, 0xC000, &[0x18, 0xFE]); // JR -2
+        cpu.registers.pc = 0xC000;
+        cpu.ime = true;
+
+        assert!(watch(&mut cpu, &mmu, 1000).is_none());
+    }
+
+    #[test]
+    fn test_io_writes_are_progress() {
+        let mut cpu = SM83::new();
+        let mmu = MMU::new();
+        load(&mmu, 0xC000, &[0xE0, 0x80, 0x18, 0xFC]); // LDH (80),A; JR -4
+        cpu.registers.pc = 0xC000;
+        assert!(watch(&mut cpu, &mmu, 1000).is_some()); // 0xFF80 is HRAM
+
+        load(&mmu, 0xC000, &[0xE0, 0x01]); // LDH (01),A
+        cpu.registers.pc = 0xC000;
+        assert!(watch(&mut cpu, &mmu, 1000).is_none());
+    }
+}